@@ -0,0 +1,27 @@
+//! Embed the GDPR Manifest Version from Cargo.toml at compile time.
+//!
+//! Reading it at runtime requires the source tree, which isn't available
+//! wherever the binary is installed.
+use std::fs;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.toml");
+
+    let cargo_toml = fs::read_to_string("Cargo.toml").expect("Can't read Cargo.toml");
+    let mut in_metadata = false;
+    let mut version = None;
+    for line in cargo_toml.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_metadata = line == "[package.metadata]";
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if in_metadata && key.trim() == "manifest_version" {
+                version = Some(value.trim().trim_matches('"').to_owned());
+            }
+        }
+    }
+
+    let version = version.expect("manifest_version not found in Cargo.toml");
+    println!("cargo:rustc-env=GDPR_MANIFEST_VERSION={}", version);
+}
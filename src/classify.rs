@@ -0,0 +1,473 @@
+//! Tag fields that hold personal or sensitive data.
+//!
+//! Fields are classified by their name, by Directus metadata and optionally
+//! by sampling their values. A rules file can add name patterns and override
+//! the tags of single fields.
+//!
+//! Tags are plain strings. `pii` marks personal data, `sensitive` marks
+//! secrets and special categories of personal data. Other tags like `email`
+//! or `financial` tell what kind of data it is.
+use crate::cli::{Classify, OutputFormat};
+use crate::dump::{Request, RequestEntity};
+use crate::entities::directus_fields;
+use crate::output;
+use anyhow::Context;
+use regex::Regex;
+use sea_orm::sea_query::{Alias, Query};
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbErr, EntityTrait, RuntimeErr};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Name heuristics as `(regex, tags)`. Names are matched in lowercase.
+const NAME_RULES: &[(&str, &[&str])] = &[
+    (r"(^|_)e?_?mail(_|$)", &["pii", "email"]),
+    (r"(^|_)(phone|mobile|fax|tel)(_|$)", &["pii", "phone"]),
+    (
+        r"(^|_)(iban|bic|account_number|credit_card|card_number)(_|$)",
+        &["pii", "financial"],
+    ),
+    (r"(^|_)(birth|dob)|birthday", &["pii", "birthdate"]),
+    (
+        r"(^|_)(first_name|last_name|surname|full_name|given_name)(_|$)",
+        &["pii", "name"],
+    ),
+    (
+        r"(^|_)(address|street|zip|postal_code|postcode|city)(_|$)",
+        &["pii", "location"],
+    ),
+    (r"(^|_)ip(_address)?(_|$)", &["pii", "ip"]),
+    (
+        r"(^|_)(ssn|social_security|passport|national_id|tax_id)(_|$)",
+        &["pii", "sensitive", "identifier"],
+    ),
+    (
+        r"health|religio|ethnic|diagnos|sexual|biometric|political",
+        &["pii", "sensitive"],
+    ),
+    (
+        r"(^|_)(password|secret|token|api_key|tfa_secret)(_|$)",
+        &["sensitive", "secret"],
+    ),
+];
+
+/// Value patterns for sampling as `(name, regex, tags)`.
+const VALUE_RULES: &[(&str, &str, &[&str])] = &[
+    (
+        "email",
+        r"(?i)^[^@\s]+@[^@\s]+\.[a-z]{2,}$",
+        &["pii", "email"],
+    ),
+    ("phone", r"^\+?[0-9][0-9 ()/-]{6,}$", &["pii", "phone"]),
+    (
+        "iban",
+        r"^[A-Z]{2}[0-9]{2}[A-Z0-9 ]{11,30}$",
+        &["pii", "financial"],
+    ),
+    ("ip", r"^([0-9]{1,3}\.){3}[0-9]{1,3}$", &["pii", "ip"]),
+];
+
+/// 🏡 Handle logic for the `classify` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `classify` specific options.
+pub async fn classify_entrypoint(args: &ClassifyOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+    let classifier = Classifier::new(&Rules::read(args.rules.as_deref())?)?;
+
+    let mut classifications = Vec::new();
+    for meta in fetch_field_meta(&db).await? {
+        if !args.resources.is_empty()
+            && !args
+                .resources
+                .iter()
+                .any(|r| r.matches(&meta.collection, &meta.field))
+        {
+            continue;
+        }
+        let samples = match args.sample {
+            Some(limit) => sample(&db, &meta.collection, &meta.field, limit).await?,
+            None => Vec::new(),
+        };
+        let classification = classifier.classify(&meta, &samples);
+        let wanted = match &args.tag {
+            Some(tag) => classification.tags.contains(tag),
+            None => !classification.tags.is_empty(),
+        };
+        if wanted {
+            classifications.push(classification);
+        }
+    }
+
+    output::print(&args.output, &classifications)
+}
+
+#[derive(Debug)]
+pub struct ClassifyOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub resources: Vec<Request>,
+    pub rules: Option<PathBuf>,
+    pub sample: Option<u64>,
+    pub tag: Option<String>,
+}
+
+impl From<Classify> for ClassifyOptions {
+    fn from(classify: Classify) -> Self {
+        ClassifyOptions {
+            url: classify.global_args.url,
+            output: classify.global_args.output,
+            resources: classify
+                .dump_args
+                .resource
+                .unwrap_or_default()
+                .iter()
+                .map(|req| RequestEntity::from(req.clone()).into())
+                .collect(),
+            rules: classify.dump_args.rules,
+            sample: classify.sample,
+            tag: classify.dump_args.tag,
+        }
+    }
+}
+
+/// User rules from a yaml file.
+///
+/// * `names` - Additional name heuristics, e.g. `"^tax_no$": [pii, identifier]`
+/// * `fields` - Tags for fields like `customers.notes` or `*.internal_*`.
+///   They replace the detected tags. An empty list marks a field as harmless.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Rules {
+    pub names: BTreeMap<String, BTreeSet<String>>,
+    pub fields: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Rules {
+    /// Read rules from `file`. No file means no rules.
+    pub fn read(file: Option<&Path>) -> anyhow::Result<Self> {
+        match file {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Can't read rules {}", path.display()))?;
+                Ok(serde_yaml::from_str(&content)?)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// The tags of a field and why it got them.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub collection: String,
+    pub field: String,
+    pub tags: BTreeSet<String>,
+    pub reasons: Vec<String>,
+}
+
+pub struct Classifier {
+    names: Vec<(Regex, BTreeSet<String>)>,
+    values: Vec<(&'static str, Regex, BTreeSet<String>)>,
+    overrides: Vec<(Request, BTreeSet<String>)>,
+}
+
+impl Classifier {
+    pub fn new(rules: &Rules) -> anyhow::Result<Self> {
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect();
+        let mut names: Vec<(Regex, BTreeSet<String>)> = NAME_RULES
+            .iter()
+            .map(|(pattern, t)| (Regex::new(pattern).unwrap(), tags(t)))
+            .collect();
+        for (pattern, t) in &rules.names {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("Invalid name pattern `{}`", pattern))?;
+            names.push((regex, t.clone()));
+        }
+
+        Ok(Self {
+            names,
+            values: VALUE_RULES
+                .iter()
+                .map(|(name, pattern, t)| (*name, Regex::new(pattern).unwrap(), tags(t)))
+                .collect(),
+            overrides: rules
+                .fields
+                .iter()
+                .map(|(field, t)| (RequestEntity::from(field.to_owned()).into(), t.clone()))
+                .collect(),
+        })
+    }
+
+    /// Classify a field. `samples` are some of its values, if any.
+    ///
+    /// A value pattern applies if at least half of the samples match.
+    pub fn classify(&self, meta: &directus_fields::Model, samples: &[String]) -> Classification {
+        let mut classification = Classification {
+            collection: meta.collection.to_owned(),
+            field: meta.field.to_owned(),
+            tags: BTreeSet::new(),
+            reasons: Vec::new(),
+        };
+        if let Some((_, tags)) = self
+            .overrides
+            .iter()
+            .find(|(request, _)| request.matches(&meta.collection, &meta.field))
+        {
+            classification.tags = tags.clone();
+            classification.reasons.push("override".to_owned());
+            return classification;
+        }
+
+        let mut tag = |tags: &BTreeSet<String>, reason: String| {
+            classification.tags.extend(tags.iter().cloned());
+            if !classification.reasons.contains(&reason) {
+                classification.reasons.push(reason);
+            }
+        };
+
+        let name = meta.field.to_lowercase();
+        for (regex, tags) in &self.names {
+            if regex.is_match(&name) {
+                tag(tags, format!("name matches `{}`", regex));
+            }
+        }
+
+        let interface = meta.interface.as_deref().unwrap_or_default();
+        let secret = BTreeSet::from(["sensitive".to_owned(), "secret".to_owned()]);
+        if is_hashed(meta) {
+            tag(&secret, "special or interface is a hash".to_owned());
+        }
+        if meta
+            .options
+            .as_ref()
+            .and_then(|o| o.get("masked"))
+            .and_then(|m| m.as_bool())
+            == Some(true)
+        {
+            tag(&secret, "interface is masked".to_owned());
+        }
+        if interface == "map" {
+            tag(
+                &BTreeSet::from(["pii".to_owned(), "location".to_owned()]),
+                "interface is a map".to_owned(),
+            );
+        }
+        if meta
+            .validation
+            .as_ref()
+            .is_some_and(|v| v.to_string().contains('@'))
+        {
+            tag(
+                &BTreeSet::from(["pii".to_owned(), "email".to_owned()]),
+                "validation expects an email".to_owned(),
+            );
+        }
+
+        let samples: Vec<&String> = samples.iter().filter(|s| !s.trim().is_empty()).collect();
+        for (name, regex, tags) in &self.values {
+            let matching = samples.iter().filter(|s| regex.is_match(s.trim())).count();
+            if !samples.is_empty() && matching * 2 >= samples.len() {
+                tag(tags, format!("values look like {}", name));
+            }
+        }
+
+        classification
+    }
+
+    /// The name of the first value pattern `value` matches, e.g. `email`.
+    pub fn value_kind(&self, value: &str) -> Option<&'static str> {
+        self.values
+            .iter()
+            .find(|(_, regex, _)| regex.is_match(value.trim()))
+            .map(|(name, _, _)| *name)
+    }
+}
+
+/// Check if Directus hashes or conceals the values of a field, e.g. passwords.
+pub fn is_hashed(meta: &directus_fields::Model) -> bool {
+    let special = meta.special.as_deref().unwrap_or_default();
+    special.split(',').any(|s| s == "hash" || s == "conceal")
+        || meta.interface.as_deref() == Some("input-hash")
+}
+
+/// Get the metadata of all fields in `directus_fields`
+pub async fn fetch_field_meta(
+    db: &DatabaseConnection,
+) -> Result<Vec<directus_fields::Model>, DbErr> {
+    directus_fields::Entity::find().all(db).await
+}
+
+/// Read up to `limit` values of a column. Values that aren't text are skipped.
+pub async fn sample(
+    db: &DatabaseConnection,
+    collection: &str,
+    field: &str,
+    limit: u64,
+) -> Result<Vec<String>, DbErr> {
+    let query = Query::select()
+        .column(Alias::new(field))
+        .from(Alias::new(collection))
+        .limit(limit)
+        .to_owned();
+    let statement = db.get_database_backend().build(&query);
+    let rows = match db.query_all(statement).await {
+        Ok(rows) => rows,
+        // Alias fields like `o2m` relations have no column
+        Err(err) if is_missing_relation(&err) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(rows
+        .iter()
+        .filter_map(|row| row.try_get::<Option<String>>("", field).ok().flatten())
+        .collect())
+}
+
+/// Check if a query failed because its table or column doesn't exist.
+///
+/// Postgres and MySQL report the SQLSTATE, SQLite only a message.
+fn is_missing_relation(err: &DbErr) -> bool {
+    let error = match err {
+        DbErr::Query(RuntimeErr::SqlxError(e)) => e.as_database_error(),
+        _ => None,
+    };
+    error.is_some_and(|e| {
+        matches!(
+            e.code().as_deref(),
+            Some("42P01" | "42703" | "42S02" | "42S22")
+        ) || e.message().starts_with("no such table")
+            || e.message().starts_with("no such column")
+    })
+}
+
+/// Classify all fields that match `requests` and keep the ones tagged `tag`.
+pub async fn classify_tagged(
+    db: &DatabaseConnection,
+    requests: &[Request],
+    tag: &str,
+    rules: Option<&Path>,
+) -> anyhow::Result<Vec<Classification>> {
+    let classifier = Classifier::new(&Rules::read(rules)?)?;
+    Ok(fetch_field_meta(db)
+        .await?
+        .iter()
+        .filter(|meta| {
+            requests.is_empty()
+                || requests
+                    .iter()
+                    .any(|r| r.matches(&meta.collection, &meta.field))
+        })
+        .map(|meta| classifier.classify(meta, &[]))
+        .filter(|classification| classification.tags.contains(tag))
+        .collect())
+}
+
+/// Narrow `requests` down to the fields tagged `tag`.
+///
+/// Fails if no field has the tag. An empty list of requests would match all.
+pub async fn tagged_requests(
+    db: &DatabaseConnection,
+    requests: &[Request],
+    tag: &str,
+    rules: Option<&Path>,
+) -> anyhow::Result<Vec<Request>> {
+    let tagged = to_requests(&classify_tagged(db, requests, tag, rules).await?);
+    if tagged.is_empty() {
+        anyhow::bail!("No field is tagged `{}`.", tag);
+    }
+    Ok(tagged)
+}
+
+/// A request for each classified field.
+pub fn to_requests(classifications: &[Classification]) -> Vec<Request> {
+    classifications
+        .iter()
+        .map(|c| RequestEntity::from(format!("{}.{}", c.collection, c.field)).into())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn meta(field: &str) -> directus_fields::Model {
+        test_support::field_meta("customers", field, None)
+    }
+
+    fn tags(classification: &Classification) -> Vec<&str> {
+        classification.tags.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn classify_uses_names_and_metadata() {
+        let classifier = Classifier::new(&Rules::default()).unwrap();
+        assert_eq!(
+            tags(&classifier.classify(&meta("work_email"), &[])),
+            vec!["email", "pii"]
+        );
+        assert_eq!(
+            tags(&classifier.classify(&meta("date_of_birth"), &[])),
+            vec!["birthdate", "pii"]
+        );
+        assert!(classifier.classify(&meta("title"), &[]).tags.is_empty());
+
+        let hashed = test_support::field_meta("customers", "pin", Some("hash"));
+        assert_eq!(
+            tags(&classifier.classify(&hashed, &[])),
+            vec!["secret", "sensitive"]
+        );
+    }
+
+    #[test]
+    fn classify_samples_values() {
+        let classifier = Classifier::new(&Rules::default()).unwrap();
+        let samples = vec![
+            "jane@example.com".to_owned(),
+            "john@example.org".to_owned(),
+            "".to_owned(),
+        ];
+        let result = classifier.classify(&meta("contact"), &samples);
+        assert_eq!(tags(&result), vec!["email", "pii"]);
+        assert_eq!(result.reasons, vec!["values look like email"]);
+    }
+
+    #[test]
+    fn classify_applies_overrides_and_custom_names() {
+        let rules: Rules = serde_yaml::from_str(
+            "names:\n  '^tax_no$': [pii, identifier]\nfields:\n  customers.email: []\n",
+        )
+        .unwrap();
+        let classifier = Classifier::new(&rules).unwrap();
+        assert!(classifier.classify(&meta("email"), &[]).tags.is_empty());
+        assert_eq!(
+            tags(&classifier.classify(&meta("tax_no"), &[])),
+            vec!["identifier", "pii"]
+        );
+    }
+
+    #[test]
+    fn sample_skips_missing_columns_only() {
+        futures::executor::block_on(async {
+            let db = Database::connect("sqlite::memory:").await.unwrap();
+            db.execute_unprepared("CREATE TABLE customers (email TEXT)")
+                .await
+                .unwrap();
+            db.execute_unprepared("INSERT INTO customers VALUES ('jane@example.com')")
+                .await
+                .unwrap();
+
+            assert_eq!(
+                sample(&db, "customers", "email", 5).await.unwrap(),
+                vec!["jane@example.com"]
+            );
+            assert!(sample(&db, "customers", "orders", 5)
+                .await
+                .unwrap()
+                .is_empty());
+            assert!(sample(&db, "nope", "email", 5).await.unwrap().is_empty());
+            db.close().await.unwrap();
+        })
+    }
+}
@@ -1,6 +1,7 @@
 use crate::utils;
 use clap::Parser;
 use std::fmt;
+use std::path::PathBuf;
 use std::str;
 
 #[derive(Parser)]
@@ -19,7 +20,155 @@ pub struct Cli {
 #[derive(Parser)]
 pub enum Command {
     Dump(Dump),
+    /// Replace the permissions of all collections in a manifest
     Replace(Replace),
+    /// Fail if the permissions differ from a manifest
+    Check(Check),
+    /// Find equal and shadowed rules across roles
+    Overlaps(Overlaps),
+    /// Audit share links for expired, unprotected and sensitive shares
+    Shares(Shares),
+    /// Report active sessions, static tokens and weak logins per role
+    Sessions(Sessions),
+    /// List static API tokens and the fields they can read and write
+    Tokens(Tokens),
+    /// Audit presets and bookmarks for unreadable fields and personal data
+    Presets(Presets),
+    /// Map Insights panels to the data they show and flag exposed data
+    Panels(Panels),
+    /// Report which folders and files each role can read
+    Files(Files),
+    /// List what unauthenticated users can do and fail on risky grants
+    Public(Public),
+    /// Apply a built-in permission template to roles
+    Patch(Patch),
+    /// List built-in permission templates and find roles that deviate
+    #[command(subcommand)]
+    Template(TemplateCommand),
+    /// Create or update roles and their permissions from a yaml file
+    Seed(Seed),
+    /// Clone and merge roles with their permissions
+    #[command(subcommand)]
+    Role(RoleCommand),
+    /// Copy the permissions of roles to another database by role name
+    Promote(Promote),
+    /// List who changed items and fields of a collection
+    History(History),
+    /// Find create, update and delete grants nobody used
+    Unused(Unused),
+    /// Map flows to the collections they read and write
+    Flows(Flows),
+    /// List hosts that receive data from webhooks and flows
+    Egress(Egress),
+    /// Tag fields that hold personal or sensitive data
+    Classify(Classify),
+    /// Generate a Record of Processing Activities (GDPR Art. 30)
+    Ropa(Ropa),
+    /// Export everything stored about a person
+    Export(Export),
+    /// Plan or execute the erasure of a person
+    Erase(Erase),
+    /// Find and purge rows older than retention rules allow
+    Retention(Retention),
+    /// Inspect and upgrade manifest files
+    #[command(subcommand)]
+    Manifest(ManifestCommand),
+}
+
+#[derive(Parser)]
+pub enum ManifestCommand {
+    /// Upgrade a manifest to the current manifest version
+    Migrate(Migrate),
+    /// Print the JSON Schema of the manifest format
+    Schema,
+}
+
+#[derive(Parser)]
+pub struct Migrate {
+    /// Read the manifest from a file instead of stdin
+    #[arg(short = 'f', long)]
+    pub file: Option<PathBuf>,
+
+    #[arg(short = 'o', long, default_value_t = OutputFormat::Yaml)]
+    pub output: OutputFormat,
+}
+
+#[derive(Parser)]
+pub enum TemplateCommand {
+    /// List all built-in templates
+    List(TemplateList),
+    /// Report roles with app access that deviate from a template
+    Check(TemplateCheck),
+}
+
+#[derive(Parser)]
+pub struct TemplateList {
+    #[arg(short = 'o', long, default_value_t = OutputFormat::Yaml)]
+    pub output: OutputFormat,
+}
+
+#[derive(Parser)]
+pub struct TemplateCheck {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// The template to compare roles with
+    #[arg(short = 't', long, default_value_t = String::from("app-minimal"))]
+    pub template: String,
+}
+
+#[derive(Parser)]
+pub enum RoleCommand {
+    /// Copy a role and all its permissions
+    Clone(RoleClone),
+    /// Union the permissions of two roles into a new or existing role
+    Merge(RoleMerge),
+}
+
+#[derive(Parser)]
+pub struct RoleClone {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// The role to copy
+    pub source: String,
+
+    /// The name of the new role
+    pub name: String,
+
+    /// Print the changes without applying them
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Apply the changes without asking
+    #[arg(short = 'y', long, conflicts_with = "dry_run")]
+    pub yes: bool,
+}
+
+#[derive(Parser)]
+pub struct RoleMerge {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// The role whose rows win on conflicts
+    pub a: String,
+
+    /// The other role
+    pub b: String,
+
+    /// The role to merge into. It's created if it doesn't exist
+    #[arg(long)]
+    pub into: String,
+
+    /// Apply despite conflicts and keep the rows of the first role
+    #[arg(long)]
+    pub keep_first: bool,
+
+    /// Print the changes without applying them
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Apply the changes without asking
+    #[arg(short = 'y', long, conflicts_with = "dry_run")]
+    pub yes: bool,
 }
 
 #[derive(Parser)]
@@ -31,10 +180,318 @@ pub struct Dump {
     pub dump_args: DumpUserArgs,
 }
 
+#[derive(Parser)]
+pub struct Overlaps {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    #[clap(flatten)]
+    pub dump_args: DumpUserArgs,
+}
+
+#[derive(Parser)]
+pub struct Shares {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Flag shares that can read these fields, e.g. `customers.email` or `*.password`
+    #[arg(short = 's', long)]
+    pub sensitive: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct Sessions {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Treat roles that can read fields with this tag as privileged
+    #[arg(short = 't', long, default_value_t = String::from("pii"))]
+    pub tag: String,
+
+    /// A yaml file with classification rules
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+
+    /// Treat roles that can read these fields as privileged too, e.g. `customers.email`
+    #[arg(short = 's', long)]
+    pub sensitive: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct Tokens {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+}
+
+#[derive(Parser)]
+pub struct Presets {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Flag bookmarks that filter fields with this tag
+    #[arg(short = 't', long, default_value_t = String::from("pii"))]
+    pub tag: String,
+
+    /// A yaml file with classification rules
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+
+    /// Flag bookmarks that filter these fields too, e.g. `customers.email`
+    #[arg(short = 's', long)]
+    pub sensitive: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct Panels {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+}
+
+#[derive(Parser)]
+pub struct Files {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+}
+
+#[derive(Parser)]
+pub struct Public {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Flag reads of fields with this tag
+    #[arg(short = 't', long, default_value_t = String::from("pii"))]
+    pub tag: String,
+
+    /// A yaml file with classification rules
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+
+    /// Accept findings on these fields, e.g. `comments.*`
+    #[arg(long)]
+    pub allow: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct Patch {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Apply a template, e.g. `app-minimal`
+    #[arg(short = 't', long)]
+    pub template: Option<String>,
+
+    /// Roles to patch, globs like `Editor*` are allowed
+    #[arg(short = 'r', long, required = true)]
+    pub role: Vec<String>,
+
+    /// Print the changes without applying them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Apply the changes without asking
+    #[arg(short = 'y', long, conflicts_with = "dry_run")]
+    pub yes: bool,
+}
+
+#[derive(Parser)]
+pub struct Seed {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// A yaml file with roles and their permissions
+    pub file: PathBuf,
+
+    /// Print the changes without applying them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Apply the changes without asking
+    #[arg(short = 'y', long, conflicts_with = "dry_run")]
+    pub yes: bool,
+}
+
+#[derive(Parser)]
+pub struct Promote {
+    /// The database to copy permissions from, e.g. dev
+    #[arg(long)]
+    pub from: String,
+
+    /// The database to copy permissions to, e.g. prod
+    #[arg(long)]
+    pub to: String,
+
+    /// Roles to copy, globs like `editor*` are allowed
+    #[arg(short = 'r', long, required = true)]
+    pub roles: Vec<String>,
+
+    /// Apply the changes without asking
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+
+    #[arg(short = 'o', long, default_value_t = OutputFormat::Yaml)]
+    pub output: OutputFormat,
+}
+
+#[derive(Parser)]
+pub struct History {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// A collection or a field like `directus_users.email`
+    pub resource: String,
+
+    /// Only include changes of this period, e.g. `30d`, `12h` or `2w`
+    #[arg(long, value_parser = utils::parse_duration)]
+    pub since: Option<chrono::Duration>,
+}
+
+#[derive(Parser)]
+pub struct Unused {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Period without activity, e.g. `180d` or `26w`
+    #[arg(long, value_parser = utils::parse_duration, default_value = "180d")]
+    pub since: chrono::Duration,
+
+    /// Print a manifest without the unused grants, ready for `gdpr replace`
+    #[arg(long)]
+    pub manifest: bool,
+}
+
+#[derive(Parser)]
+pub struct Flows {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Flag webhook flows that can reach these fields, e.g. `customers.email`
+    #[arg(short = 's', long)]
+    pub sensitive: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct Egress {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+}
+
+#[derive(Parser)]
+pub struct Classify {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    #[clap(flatten)]
+    pub dump_args: DumpUserArgs,
+
+    /// Also scan up to this many values of each field
+    #[arg(long)]
+    pub sample: Option<u64>,
+}
+
+#[derive(Parser)]
+pub struct Ropa {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Include fields with this tag
+    #[arg(short = 't', long, default_value_t = String::from("pii"))]
+    pub tag: String,
+
+    /// A yaml file with classification rules
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Export {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// Find the person in `directus_users` by email
+    #[arg(
+        long,
+        required_unless_present = "field_match",
+        conflicts_with = "field_match"
+    )]
+    pub email: Option<String>,
+
+    /// Find the person by any field, e.g. `customers.email=jane@example.com`
+    #[arg(long = "match")]
+    pub field_match: Option<String>,
+
+    /// How many relations to follow from the person's item
+    #[arg(long, default_value_t = 3)]
+    pub depth: usize,
+
+    /// Write the export to a `.json` or `.zip` file instead of stdout
+    #[arg(short = 'f', long)]
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Erase {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// A `directus_users` id or a match like `customers.email=jane@example.com`
+    #[arg(long)]
+    pub subject: String,
+
+    /// Only print the plan. This is the default.
+    #[arg(long, conflicts_with = "execute")]
+    pub dry_run: bool,
+
+    /// Apply the plan in a single transaction
+    #[arg(long)]
+    pub execute: bool,
+
+    /// Execute without asking
+    #[arg(short = 'y', long, requires = "execute")]
+    pub yes: bool,
+
+    /// Delete files the person uploaded instead of unlinking them
+    #[arg(long)]
+    pub delete_files: bool,
+}
+
+#[derive(Parser)]
+pub struct Retention {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    /// A yaml file with a retention rule per collection
+    #[arg(short = 'f', long)]
+    pub file: PathBuf,
+
+    /// Delete all rows that violate a rule in a single transaction
+    #[arg(long)]
+    pub purge: bool,
+
+    /// Purge without asking
+    #[arg(short = 'y', long, requires = "purge")]
+    pub yes: bool,
+}
+
 #[derive(Parser)]
 pub struct Replace {
     #[clap(flatten)]
-    args: GlobalArgs,
+    pub global_args: GlobalArgs,
+
+    #[clap(flatten)]
+    pub manifest_args: ManifestArgs,
+
+    /// Apply the changes without asking. Required to read the manifest from stdin
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+}
+
+#[derive(Parser)]
+pub struct Check {
+    #[clap(flatten)]
+    pub global_args: GlobalArgs,
+
+    #[clap(flatten)]
+    pub manifest_args: ManifestArgs,
 }
 
 #[derive(Parser)]
@@ -56,6 +513,9 @@ pub enum OutputFormat {
     Pretty,
     Dot,
     GraphML,
+    Markdown,
+    Html,
+    Csv,
 }
 
 const JSON_FORMAT: &str = "json";
@@ -63,6 +523,9 @@ const YAML_FORMAT: &str = "yaml";
 const PRETTY_FORMAT: &str = "pretty";
 const DOT: &str = "dot";
 const GRAPHML: &str = "graphml";
+const MARKDOWN: &str = "markdown";
+const HTML: &str = "html";
+const CSV: &str = "csv";
 
 impl fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -72,6 +535,9 @@ impl fmt::Display for OutputFormat {
             OutputFormat::Pretty => write!(f, "{}", PRETTY_FORMAT),
             OutputFormat::Dot => write!(f, "{}", DOT),
             OutputFormat::GraphML => write!(f, "{}", GRAPHML),
+            OutputFormat::Markdown => write!(f, "{}", MARKDOWN),
+            OutputFormat::Html => write!(f, "{}", HTML),
+            OutputFormat::Csv => write!(f, "{}", CSV),
         }
     }
 }
@@ -86,9 +552,12 @@ impl str::FromStr for OutputFormat {
             PRETTY_FORMAT => Ok(OutputFormat::Pretty),
             DOT => Ok(OutputFormat::Dot),
             GRAPHML => Ok(OutputFormat::GraphML),
+            MARKDOWN | "md" => Ok(OutputFormat::Markdown),
+            HTML => Ok(OutputFormat::Html),
+            CSV => Ok(OutputFormat::Csv),
             _ => Err(format!(
-                "Unknown output format. You used `{}`. Choose one of: `{}`, `{}`, `{}`. Use --help to see a list of all options.",
-                s, JSON_FORMAT, YAML_FORMAT, PRETTY_FORMAT
+                "Unknown output format. You used `{}`. Choose one of: `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`. Use --help to see a list of all options.",
+                s, JSON_FORMAT, YAML_FORMAT, PRETTY_FORMAT, DOT, GRAPHML, MARKDOWN, HTML, CSV
             )),
         }
     }
@@ -107,4 +576,23 @@ pub struct GlobalArgs {
 pub struct DumpUserArgs {
     #[arg(short = 'r', long, value_parser = utils::remove_whitespace)]
     pub resource: Option<Vec<String>>,
+
+    /// Only include fields with this tag, e.g. `pii`. See `gdpr classify`.
+    #[arg(short = 't', long)]
+    pub tag: Option<String>,
+
+    /// A yaml file with classification rules
+    #[arg(long)]
+    pub rules: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct ManifestArgs {
+    /// Read the manifest from a file instead of stdin
+    #[arg(short = 'f', long)]
+    pub file: Option<PathBuf>,
+
+    /// Refuse if a glob matches a different set than when the manifest was written
+    #[arg(long)]
+    pub frozen: bool,
 }
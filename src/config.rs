@@ -23,8 +23,8 @@ pub const DIRECTUS_SYSTEM_COLLECTIONS: &[&str] = &[
 ];
 
 pub fn get_directus_system_collections() -> Vec<String> {
-    return DIRECTUS_SYSTEM_COLLECTIONS
+    DIRECTUS_SYSTEM_COLLECTIONS
         .iter()
         .map(|c| c.to_string())
-        .collect::<Vec<String>>();
+        .collect::<Vec<String>>()
 }
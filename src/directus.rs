@@ -1,6 +1,26 @@
 use crate::config;
 use crate::entities::*;
+use sea_orm::prelude::Uuid;
 use sea_orm::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Name of the role Directus uses for unauthenticated users (`role = NULL`)
+pub const PUBLIC_ROLE: &str = "Public";
+
+/// A Directus user as far as audits are concerned.
+#[derive(Clone, Debug)]
+pub struct User {
+    pub id: String,
+    pub email: Option<String>,
+    pub role: Option<String>,
+}
+
+impl User {
+    /// A human readable name, i.e. the email or the id.
+    pub fn label(&self) -> String {
+        self.email.clone().unwrap_or_else(|| self.id.to_owned())
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Field {
@@ -50,6 +70,136 @@ pub async fn fetch_collections(db: &DatabaseConnection) -> Result<Vec<String>, D
     Ok(collections)
 }
 
+/// Get a map of all role ids to their names in `directus_roles`
+pub async fn fetch_role_names(db: &DatabaseConnection) -> Result<HashMap<String, String>, DbErr> {
+    let roles: HashMap<String, String> = directus_roles::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|role| (role.id.to_string(), role.name))
+        .collect();
+
+    Ok(roles)
+}
+
+/// Get a map of all user ids to their email and role id in `directus_users`
+pub async fn fetch_users(db: &DatabaseConnection) -> Result<HashMap<String, User>, DbErr> {
+    let users: Vec<(Uuid, Option<String>, Option<Uuid>)> = directus_users::Entity::find()
+        .select_only()
+        .column(directus_users::Column::Id)
+        .column(directus_users::Column::Email)
+        .column(directus_users::Column::Role)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(users
+        .into_iter()
+        .map(|(id, email, role)| {
+            let user = User {
+                id: id.to_string(),
+                email,
+                role: role.map(|r| r.to_string()),
+            };
+            (user.id.to_owned(), user)
+        })
+        .collect())
+}
+
+/// Get all share links in `directus_shares`
+pub async fn fetch_shares(db: &DatabaseConnection) -> Result<Vec<directus_shares::Model>, DbErr> {
+    directus_shares::Entity::find().all(db).await
+}
+
+/// Get the ids of all roles with `admin_access`
+pub async fn fetch_admin_roles(db: &DatabaseConnection) -> Result<HashSet<String>, DbErr> {
+    Ok(directus_roles::Entity::find()
+        .filter(directus_roles::Column::AdminAccess.eq(true))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|role| role.id.to_string())
+        .collect())
+}
+
+/// All fields `role` is granted `action` on in `collection`.
+///
+/// A wildcard `*` or `NULL` in `fields` is expanded to all known fields,
+/// see [`known_fields`].
+pub fn granted_fields(
+    permissions: &[directus_permissions::Model],
+    fields: &[Field],
+    role: Option<&str>,
+    collection: &str,
+    action: &str,
+) -> BTreeSet<String> {
+    permissions
+        .iter()
+        .filter(|p| p.role.as_deref() == role && p.collection == collection && p.action == action)
+        .flat_map(|p| match p.fields.as_deref() {
+            Some("*") | None => known_fields(fields, collection),
+            Some(csv) => csv.split(',').map(|f| f.trim().to_owned()).collect(),
+        })
+        .collect()
+}
+
+/// The fields of `collection` in `fields`.
+///
+/// System collections have no rows in `directus_fields`, their fields are
+/// the columns of their entities.
+pub fn known_fields(fields: &[Field], collection: &str) -> Vec<String> {
+    let known: Vec<String> = fields
+        .iter()
+        .filter(|f| f.collection == collection)
+        .map(|f| f.field.to_owned())
+        .collect();
+    match known.is_empty() {
+        true => system_fields(collection),
+        false => known,
+    }
+}
+
+/// The columns of a system collection. Empty for other collections.
+pub fn system_fields(collection: &str) -> Vec<String> {
+    fn columns<C: Iterable + IdenStatic>() -> Vec<String> {
+        C::iter().map(|c| c.as_str().to_owned()).collect()
+    }
+    match collection {
+        "directus_activity" => columns::<directus_activity::Column>(),
+        "directus_collections" => columns::<directus_collections::Column>(),
+        "directus_dashboards" => columns::<directus_dashboards::Column>(),
+        "directus_fields" => columns::<directus_fields::Column>(),
+        "directus_files" => columns::<directus_files::Column>(),
+        "directus_flows" => columns::<directus_flows::Column>(),
+        "directus_folders" => columns::<directus_folders::Column>(),
+        "directus_migrations" => columns::<directus_migrations::Column>(),
+        "directus_notifications" => columns::<directus_notifications::Column>(),
+        "directus_operations" => columns::<directus_operations::Column>(),
+        "directus_panels" => columns::<directus_panels::Column>(),
+        "directus_permissions" => columns::<directus_permissions::Column>(),
+        "directus_presets" => columns::<directus_presets::Column>(),
+        "directus_relations" => columns::<directus_relations::Column>(),
+        "directus_revisions" => columns::<directus_revisions::Column>(),
+        "directus_roles" => columns::<directus_roles::Column>(),
+        "directus_sessions" => columns::<directus_sessions::Column>(),
+        "directus_settings" => columns::<directus_settings::Column>(),
+        "directus_shares" => columns::<directus_shares::Column>(),
+        "directus_users" => columns::<directus_users::Column>(),
+        "directus_webhooks" => columns::<directus_webhooks::Column>(),
+        _ => Vec::new(),
+    }
+}
+
+/// Resolve a role id to its name. `None` is the public role.
+///
+/// Fall back to the role id if the role doesn't exist in `names`.
+pub fn role_name(role: Option<&str>, names: &HashMap<String, String>) -> String {
+    match role {
+        Some(id) => names.get(id).cloned().unwrap_or_else(|| id.to_owned()),
+        None => PUBLIC_ROLE.to_owned(),
+    }
+}
+
 /// Build condition for a specific field
 ///
 /// It's tricky 'cause the `fields` column are CSVs or could be a wildcard.
@@ -57,7 +207,7 @@ pub async fn fetch_collections(db: &DatabaseConnection) -> Result<Vec<String>, D
 /// # Arguments
 /// * `field` - a string reference of a field.
 pub fn field_specific(field: &str) -> Condition {
-    return Condition::any()
+    Condition::any()
         // if `field` matches exactly
         .add(directus_permissions::Column::Fields.eq(field))
         // if `field` is in the middle of a csv
@@ -67,7 +217,7 @@ pub fn field_specific(field: &str) -> Condition {
         // if `field` is at the end
         .add(directus_permissions::Column::Fields.like(("%,".to_owned() + field).as_str()))
         // if it's about all fields of a table directus uses a wildcard
-        .add(directus_permissions::Column::Fields.eq("*"));
+        .add(directus_permissions::Column::Fields.eq("*"))
 }
 
 /// FIXME: This is more difficult.
@@ -86,7 +236,7 @@ pub fn field_specific(field: &str) -> Condition {
 /// * `field` - a string reference of a field with a wildcard character
 pub fn field_wildcard(field: &str) -> Condition {
     let binding = field.replace("*", "%");
-    return Condition::any()
+    Condition::any()
         // if there's only one value in `field` column
         // this is so convoluted, because we can't check with .eq(&binding)
         // because we're using wildcards.
@@ -106,7 +256,7 @@ pub fn field_wildcard(field: &str) -> Condition {
         // FIXME: this is not good, it only works if wildcard is at the start of binding
         .add(directus_permissions::Column::Fields.ends_with(&binding))
         // if it's about all fields of a table directus uses a wildcard
-        .add(directus_permissions::Column::Fields.eq("*"));
+        .add(directus_permissions::Column::Fields.eq("*"))
 }
 
 pub fn collection_wildcard(collection: &str) -> Condition {
@@ -117,3 +267,25 @@ pub fn collection_wildcard(collection: &str) -> Condition {
 pub fn collection_specific(collection: &str) -> Condition {
     Condition::all().add(directus_permissions::Column::Collection.eq(collection))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn granted_fields_expands_wildcards_of_system_collections() {
+        let permissions = vec![
+            test_support::permission(Some("1"), "directus_users", "read", "*"),
+            directus_permissions::Model {
+                fields: None,
+                ..test_support::permission(Some("1"), "directus_files", "delete", "")
+            },
+        ];
+        let users = granted_fields(&permissions, &[], Some("1"), "directus_users", "read");
+        assert!(users.contains("email") && users.contains("tfa_secret"));
+        let files = granted_fields(&permissions, &[], Some("1"), "directus_files", "delete");
+        assert!(files.contains("filename_disk"));
+        assert!(granted_fields(&permissions, &[], Some("1"), "articles", "read").is_empty());
+    }
+}
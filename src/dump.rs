@@ -1,22 +1,30 @@
+use crate::classify;
 use crate::cli::{Dump, OutputFormat};
 use crate::directus;
+use crate::egress;
 use crate::entities::directus_permissions;
 use crate::graph::{build_graph, GraphToString};
-use crate::manifest;
+use crate::manifest::Manifest;
+use crate::presets;
 use crate::reversed_permissions;
 use crate::utils;
+use crate::wildcard;
 use petgraph_graphml::GraphMl;
 use sea_orm::{entity::*, query::*};
-use sea_orm::{Condition, Database, DbErr, Select};
-use serde::{Deserialize, Serialize};
+use sea_orm::{Condition, Database, Select};
+use std::path::PathBuf;
 
 /// 🏡 Handle logic for the `dump` command.
 ///
 /// # Arguments
 ///
 /// * `args` - A reference to user's `dump` specific options.
-pub async fn dump_entrypoint(args: &mut DumpOptions) -> Result<(), DbErr> {
+pub async fn dump_entrypoint(args: &mut DumpOptions) -> anyhow::Result<()> {
     let db = Database::connect(&args.url).await?;
+    if let Some(tag) = &args.tag {
+        args.resources =
+            classify::tagged_requests(&db, &args.resources, tag, args.rules.as_deref()).await?;
+    }
 
     // FIXME: importing collections and fields should relate to args.resources
     // and only request necessary rows.
@@ -31,35 +39,37 @@ pub async fn dump_entrypoint(args: &mut DumpOptions) -> Result<(), DbErr> {
     // println!("query: {}", sql_query);
     // ```
     let permissions: Vec<directus_permissions::Model> = query.all(&db).await?;
-    let graph = build_graph(permissions, &fields);
 
     match args.output {
-        OutputFormat::Dot => graph.draw(),
+        OutputFormat::Dot => {
+            let shares = directus::fetch_shares(&db).await?;
+            let egress = egress::fetch(&db).await?;
+            build_graph(permissions, &fields, &shares, &egress)?.draw()
+        }
         OutputFormat::GraphML => println!(
             "{}",
-            GraphMl::new(&graph)
-                .pretty_print(true)
-                .export_node_weights_display()
+            GraphMl::new(&build_graph(
+                permissions,
+                &fields,
+                &directus::fetch_shares(&db).await?,
+                &egress::fetch(&db).await?
+            )?)
+            .pretty_print(true)
+            .export_node_weights_display()
         ),
-        _ => (),
+        _ => {
+            let role_names = directus::fetch_role_names(&db).await?;
+            let mut organized_dump =
+                reversed_permissions::Builder::new(&permissions, &fields, &role_names)
+                    .requests(&args.resources)
+                    .build();
+            let shared = presets::fetch_shared(&db).await?;
+            organized_dump.presets =
+                Some(presets::to_manifest(&shared, &role_names, &args.resources));
+            output_dump(&args.output, &organized_dump);
+        }
     }
 
-    // FIXME: -- skip. implemented as graph
-    // let organized_dump = reversed_permissions::Builder::request(&args.resources)
-    //     .permission(&permissions)
-    //     .options()
-    //     .dedupe_roles()
-    //     .dedupe_permissions()
-    //     .build();
-
-    // TODO:
-    // Toggled off: We'll replace role ids with role names later
-    // let roles: Vec<Option<directus_roles::Model>> =
-    //     permissions.load_one(directus_roles::Entity, &db).await?;
-    // println!("roles {:#?}", roles);
-
-    // output_dump(&args.output, &organized_dump);
-
     Ok(())
 }
 
@@ -68,11 +78,13 @@ pub struct DumpOptions {
     pub url: String,
     pub output: OutputFormat,
     pub resources: Vec<Request>,
+    pub tag: Option<String>,
+    pub rules: Option<PathBuf>,
 }
 
 impl From<Dump> for DumpOptions {
     fn from(dump: Dump) -> Self {
-        return DumpOptions {
+        DumpOptions {
             url: dump.global_args.url,
             output: dump.global_args.output,
             resources: dump
@@ -82,11 +94,13 @@ impl From<Dump> for DumpOptions {
                 .iter()
                 .map(|req| RequestEntity::from(req.clone()).into())
                 .collect(),
-        };
+            tag: dump.dump_args.tag,
+            rules: dump.dump_args.rules,
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RequestEntity {
     collection: MaybeWildcard,
     field: MaybeWildcard,
@@ -102,7 +116,7 @@ impl From<String> for RequestEntity {
     }
 }
 
-trait ToQuery {
+pub trait ToQuery {
     fn to_query(&self) -> Select<directus_permissions::Entity>;
 }
 
@@ -128,7 +142,7 @@ impl ToConditions for Vec<Request> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum MaybeWildcard {
     Specific(String),
     HasWildcard(String),
@@ -136,6 +150,14 @@ enum MaybeWildcard {
 }
 
 impl MaybeWildcard {
+    fn matches(&self, s: &str) -> bool {
+        match self {
+            MaybeWildcard::All => true,
+            MaybeWildcard::Specific(specific) => specific == s,
+            MaybeWildcard::HasWildcard(w) => !wildcard::find_with(w, &[s.to_owned()]).is_empty(),
+        }
+    }
+
     fn as_str(&self) -> &str {
         match self {
             MaybeWildcard::HasWildcard(s) => s.as_str(),
@@ -157,7 +179,7 @@ impl From<String> for MaybeWildcard {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Request {
     BothAll(RequestEntity),
     WildCollectionAllFields(RequestEntity),
@@ -214,6 +236,51 @@ impl From<RequestEntity> for Request {
 }
 
 impl Request {
+    fn entity(&self) -> &RequestEntity {
+        match self {
+            Request::BothAll(r)
+            | Request::WildCollectionAllFields(r)
+            | Request::BothWild(r)
+            | Request::WildCollectionSpecificField(r)
+            | Request::SpecificCollectionAllFields(r)
+            | Request::SpecificCollectionWildField(r)
+            | Request::BothSpecific(r)
+            | Request::AllCollectionsWildField(r)
+            | Request::AllCollectionsSpecificField(r) => r,
+        }
+    }
+
+    /// Check if a field of a collection is part of this request.
+    pub fn matches(&self, collection: &str, field: &str) -> bool {
+        let entity = self.entity();
+        entity.collection.matches(collection) && entity.field.matches(field)
+    }
+
+    /// Check if a collection is part of this request, regardless of fields.
+    pub fn matches_collection(&self, collection: &str) -> bool {
+        self.entity().collection.matches(collection)
+    }
+
+    /// Filter `column` by this request's collection, e.g. the `collection`
+    /// column of `directus_activity`. Fields aren't considered.
+    ///
+    /// `LIKE` treats `_` as a wildcard too, so wildcard matches may include
+    /// more collections than `matches_collection`.
+    pub fn collection_condition<C: ColumnTrait>(&self, column: C) -> Condition {
+        match &self.entity().collection {
+            MaybeWildcard::All => Condition::all(),
+            MaybeWildcard::Specific(collection) => Condition::all().add(column.eq(collection)),
+            MaybeWildcard::HasWildcard(collection) => {
+                Condition::all().add(column.like(collection.replace('*', "%").as_str()))
+            }
+        }
+    }
+
+    /// Check if this request covers all fields of its collections.
+    pub fn all_fields(&self) -> bool {
+        matches!(self.entity().field, MaybeWildcard::All)
+    }
+
     pub fn to_condition(&self) -> Condition {
         match &self {
             Request::BothAll(_) => Condition::all(),
@@ -250,30 +317,16 @@ impl Request {
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct DataWithVersion {
-    version: String,
-    #[serde(flatten)]
-    data: reversed_permissions::CollectionRules,
-}
-
 /// Displays the organized permissions dump in a human-readable format.
 ///
 /// # Arguments
 ///
 /// * `output` - A reference to the user's preferred output format.
-/// * `permissions` - a reference to the raw permissions.
-pub fn output_dump(output: &OutputFormat, data: &reversed_permissions::CollectionRules) {
-    let data_with_version = DataWithVersion {
-        version: manifest::get_version(),
-        // TODO: #low-priority
-        // avoid using clone()
-        data: data.clone(),
-    };
-
+/// * `manifest` - a reference to the organized permissions.
+pub fn output_dump(output: &OutputFormat, manifest: &Manifest) {
     let show: String = match output {
-        OutputFormat::Yaml => serde_yaml::to_string(&data_with_version).unwrap(),
-        OutputFormat::Json => serde_json::to_string_pretty(&data_with_version).unwrap(),
+        OutputFormat::Yaml => serde_yaml::to_string(manifest).unwrap(),
+        OutputFormat::Json => serde_json::to_string_pretty(manifest).unwrap(),
         OutputFormat::Pretty => {
             panic!("Pretty is not yet implemented. Choose either json or yaml.")
         }
@@ -0,0 +1,279 @@
+//! Find out where data leaves Directus.
+//!
+//! Webhooks and `request` operations of flows send data to other hosts.
+//! Every receiver of personal data is a processor that needs to be listed.
+use crate::cli::{Egress as EgressArgs, OutputFormat};
+use crate::entities::{directus_flows, directus_operations, directus_webhooks};
+use crate::flows::ItemAccess;
+use crate::output;
+use sea_orm::{Database, DatabaseConnection, DbErr, EntityTrait};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet};
+use url::{ParseError, Url};
+
+/// 🏡 Handle logic for the `egress` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `egress` specific options.
+pub async fn egress_entrypoint(args: &EgressOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+    output::print(&args.output, &EgressReport::new(fetch(&db).await?))
+}
+
+#[derive(Debug)]
+pub struct EgressOptions {
+    pub url: String,
+    pub output: OutputFormat,
+}
+
+impl From<EgressArgs> for EgressOptions {
+    fn from(egress: EgressArgs) -> Self {
+        EgressOptions {
+            url: egress.global_args.url,
+            output: egress.global_args.output,
+        }
+    }
+}
+
+/// Get all webhooks and `request` operations.
+pub async fn fetch(db: &DatabaseConnection) -> Result<Vec<Egress>, DbErr> {
+    let webhooks = directus_webhooks::Entity::find().all(db).await?;
+    let flows = directus_flows::Entity::find().all(db).await?;
+    let operations = directus_operations::Entity::find().all(db).await?;
+
+    let mut egress: Vec<Egress> = webhooks.iter().map(Egress::from_webhook).collect();
+    for flow in &flows {
+        let steps: Vec<&directus_operations::Model> =
+            operations.iter().filter(|op| op.flow == flow.id).collect();
+        egress.extend(Egress::from_flow(flow, &steps));
+    }
+    Ok(egress)
+}
+
+/// Where data is sent from.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Source {
+    Webhook {
+        id: i32,
+        name: String,
+    },
+    Flow {
+        id: String,
+        name: String,
+        operation: String,
+    },
+}
+
+/// Data sent to a host.
+///
+/// * `collections` - Collections whose items can be part of the request
+/// * `payload` - Whether item data is sent, not just keys or events
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Egress {
+    pub source: Source,
+    pub status: String,
+    pub host: String,
+    pub url: String,
+    pub method: String,
+    pub collections: BTreeSet<String>,
+    pub payload: bool,
+}
+
+impl Egress {
+    pub fn from_webhook(webhook: &directus_webhooks::Model) -> Self {
+        Self {
+            source: Source::Webhook {
+                id: webhook.id,
+                name: webhook.name.to_owned(),
+            },
+            status: webhook.status.to_owned(),
+            host: host(&webhook.url),
+            url: webhook.url.to_owned(),
+            method: webhook.method.to_owned(),
+            collections: split_csv(&webhook.collections),
+            payload: webhook.data,
+        }
+    }
+
+    /// One entry per `request` operation of a flow.
+    ///
+    /// A flow can send the items of the collections of its trigger and of
+    /// its `item-*` operations. Item data is assumed to be sent if the url,
+    /// the headers or the body interpolate anything with `{{ }}`.
+    pub fn from_flow(
+        flow: &directus_flows::Model,
+        operations: &[&directus_operations::Model],
+    ) -> Vec<Self> {
+        let mut collections: BTreeSet<String> = flow
+            .options
+            .as_ref()
+            .and_then(|o| o.get("collections"))
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c.as_str().map(str::to_owned))
+            .collect();
+        collections.extend(
+            operations
+                .iter()
+                .filter_map(|op| ItemAccess::from_operation(op))
+                .map(|access| access.collection),
+        );
+
+        operations
+            .iter()
+            .filter(|op| op.r#type == "request")
+            .map(|op| {
+                let options = op.options.as_ref().unwrap_or(&JsonValue::Null);
+                let url = options
+                    .get("url")
+                    .and_then(JsonValue::as_str)
+                    .unwrap_or_default();
+                let templated = ["url", "headers", "body"]
+                    .iter()
+                    .filter_map(|key| options.get(*key))
+                    .any(|value| match value {
+                        JsonValue::String(s) => s.contains("{{"),
+                        other => other.to_string().contains("{{"),
+                    });
+                Self {
+                    source: Source::Flow {
+                        id: flow.id.to_string(),
+                        name: flow.name.to_owned(),
+                        operation: op.key.to_owned(),
+                    },
+                    status: flow.status.to_owned(),
+                    host: host(url),
+                    url: url.to_owned(),
+                    method: options
+                        .get("method")
+                        .and_then(JsonValue::as_str)
+                        .unwrap_or("GET")
+                        .to_owned(),
+                    collections: collections.clone(),
+                    payload: templated,
+                }
+            })
+            .collect()
+    }
+}
+
+/// All egress and the collections each host receives.
+///
+/// `hosts` only lists collections whose item data is sent.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EgressReport {
+    pub hosts: BTreeMap<String, BTreeSet<String>>,
+    pub egress: Vec<Egress>,
+}
+
+impl EgressReport {
+    pub fn new(egress: Vec<Egress>) -> Self {
+        let mut hosts: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for e in egress.iter().filter(|e| e.payload) {
+            hosts
+                .entry(e.host.to_owned())
+                .or_default()
+                .extend(e.collections.iter().cloned());
+        }
+        Self { hosts, egress }
+    }
+}
+
+/// Extract the host of a url without scheme, credentials, port or path.
+///
+/// Urls without a scheme are read as `https`. IPv6 hosts keep their brackets.
+fn host(url: &str) -> String {
+    let parsed = match Url::parse(url) {
+        Err(ParseError::RelativeUrlWithoutBase) => Url::parse(&format!("https://{}", url)),
+        parsed => parsed,
+    };
+    parsed
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default()
+}
+
+fn split_csv(csv: &str) -> BTreeSet<String> {
+    csv.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::prelude::Uuid;
+    use serde_json::json;
+
+    #[test]
+    fn host_strips_everything_but_the_host() {
+        assert_eq!(
+            host("https://user:pw@Hooks.Example.com:8443/path?q=1"),
+            "hooks.example.com"
+        );
+        assert_eq!(host("example.com/path"), "example.com");
+        assert_eq!(host("http://[2001:db8::1]:8080/hook"), "[2001:db8::1]");
+    }
+
+    #[test]
+    fn from_flow_collects_collections_of_trigger_and_items() {
+        let flow = directus_flows::Model {
+            id: Uuid::nil(),
+            name: "Sync CRM".to_owned(),
+            icon: None,
+            color: None,
+            description: None,
+            status: "active".to_owned(),
+            trigger: Some("event".to_owned()),
+            accountability: None,
+            options: Some(json!({ "collections": ["customers"] })),
+            operation: None,
+            date_created: None,
+            user_created: None,
+        };
+        let operation = |r#type: &str, options: JsonValue| directus_operations::Model {
+            id: Uuid::nil(),
+            name: None,
+            key: r#type.to_owned(),
+            r#type: r#type.to_owned(),
+            position_x: 0,
+            position_y: 0,
+            options: Some(options),
+            resolve: None,
+            reject: None,
+            flow: Uuid::nil(),
+            date_created: None,
+            user_created: None,
+        };
+        let read = operation("item-read", json!({ "collection": "orders" }));
+        let request = operation(
+            "request",
+            json!({ "url": "https://crm.example.com/api", "method": "POST", "body": "{{ $last }}" }),
+        );
+
+        let egress = Egress::from_flow(&flow, &[&read, &request]);
+        assert_eq!(egress.len(), 1);
+        assert_eq!(egress[0].host, "crm.example.com");
+        assert!(egress[0].payload);
+
+        let query = operation(
+            "request",
+            json!({ "url": "https://crm.example.com/api?email={{ $trigger.payload.email }}" }),
+        );
+        assert!(Egress::from_flow(&flow, &[&query])[0].payload);
+        let ping = operation("request", json!({ "url": "https://crm.example.com/ping" }));
+        assert!(!Egress::from_flow(&flow, &[&ping])[0].payload);
+
+        let report = EgressReport::new(egress);
+        assert_eq!(
+            report.hosts["crm.example.com"],
+            BTreeSet::from(["customers".to_owned(), "orders".to_owned()])
+        );
+    }
+}
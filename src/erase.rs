@@ -0,0 +1,600 @@
+//! Plan and execute the erasure of a person (GDPR Art. 17).
+//!
+//! Starting at the person's item, follow `directus_relations` to every item
+//! that references it and decide whether to delete it or to unlink it.
+//! Activity is anonymized instead of deleted, to keep the audit trail.
+use crate::cli::{Erase, OutputFormat};
+use crate::entities::directus_relations;
+use crate::export::{self, Subject, PRIMARY_KEY};
+use crate::output;
+use crate::utils;
+use sea_orm::sea_query::{Alias, Expr, Query};
+use sea_orm::{
+    ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, EntityTrait, FromQueryResult,
+    Statement, TransactionTrait,
+};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+
+/// References to `directus_users` that Directus doesn't list in `directus_relations`.
+const USER_REFERENCES: &[(&str, &str, Reference)] = &[
+    ("directus_sessions", "user", Reference::Delete),
+    ("directus_presets", "user", Reference::Delete),
+    ("directus_notifications", "recipient", Reference::Delete),
+    ("directus_notifications", "sender", Reference::Nullify),
+    ("directus_shares", "user_created", Reference::Nullify),
+    ("directus_files", "uploaded_by", Reference::Nullify),
+    ("directus_files", "modified_by", Reference::Nullify),
+    ("directus_dashboards", "user_created", Reference::Nullify),
+    ("directus_panels", "user_created", Reference::Nullify),
+    ("directus_flows", "user_created", Reference::Nullify),
+    ("directus_operations", "user_created", Reference::Nullify),
+];
+
+/// Columns of `directus_activity` that identify a person.
+const ACTIVITY_FIELDS: &[&str] = &["user", "ip", "user_agent"];
+
+/// 🏡 Handle logic for the `erase` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `erase` specific options.
+pub async fn erase_entrypoint(args: &EraseOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+    let planner = Planner {
+        relations: directus_relations::Entity::find().all(&db).await?,
+        delete_rules: fetch_delete_rules(&db).await?,
+        delete_files: args.delete_files,
+    };
+
+    let plan = planner.plan(&db, &args.subject).await?;
+    output::print(&args.output, &plan)?;
+    if !args.execute {
+        return Ok(());
+    }
+    if !plan.blocked.is_empty() {
+        anyhow::bail!("Erasure is blocked by RESTRICT relations. Nothing was changed.");
+    }
+    let question = format!(
+        "Run {} step(s) to erase `{}`?",
+        plan.steps.len(),
+        args.subject.value
+    );
+    match args.yes || utils::confirm(&question)? {
+        true => Ok(execute(&db, &plan).await?),
+        false => {
+            eprintln!("Nothing applied.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EraseOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub subject: Subject,
+    pub execute: bool,
+    pub yes: bool,
+    pub delete_files: bool,
+}
+
+impl TryFrom<Erase> for EraseOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(erase: Erase) -> anyhow::Result<Self> {
+        let subject = match erase.subject.contains('=') {
+            true => Subject::parse(&erase.subject)?,
+            false => Subject {
+                collection: "directus_users".to_owned(),
+                field: PRIMARY_KEY.to_owned(),
+                value: erase.subject,
+            },
+        };
+        Ok(EraseOptions {
+            url: erase.global_args.url,
+            output: erase.global_args.output,
+            subject,
+            execute: erase.execute,
+            yes: erase.yes,
+            delete_files: erase.delete_files,
+        })
+    }
+}
+
+/// What happens to items that reference an erased item.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reference {
+    /// Delete the referencing item and erase its references too
+    Delete,
+    /// Set the referencing field to `NULL`
+    Nullify,
+    /// The database refuses to delete the erased item while it's referenced
+    Restrict,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "operation")]
+pub enum Operation {
+    Delete,
+    Nullify { field: String },
+    Anonymize { fields: Vec<String> },
+}
+
+/// An operation on some items of a collection.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Step {
+    pub collection: String,
+    #[serde(flatten)]
+    pub operation: Operation,
+    /// Which field selects the items, usually the primary key
+    pub key: String,
+    pub ids: Vec<String>,
+}
+
+/// Items that reference the person through a `RESTRICT` foreign key.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Blocked {
+    pub collection: String,
+    pub field: String,
+    pub ids: Vec<String>,
+}
+
+/// All steps to erase a person, in the order they must run.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Plan {
+    pub subject: Subject,
+    pub steps: Vec<Step>,
+    pub blocked: Vec<Blocked>,
+}
+
+pub struct Planner {
+    pub relations: Vec<directus_relations::Model>,
+    /// `ON DELETE` rules of foreign keys by `(table, column)`
+    pub delete_rules: HashMap<(String, String), String>,
+    /// Delete files the person uploaded instead of unlinking them
+    pub delete_files: bool,
+}
+
+impl Planner {
+    /// How to handle items of `relation.many_collection` when the item they reference is erased.
+    ///
+    /// The foreign key's `ON DELETE` rule wins. `RESTRICT` blocks the erasure.
+    /// Without a rule, Directus' `one_deselect_action` decides.
+    pub fn reference(&self, relation: &directus_relations::Model) -> Reference {
+        let key = (
+            relation.many_collection.to_owned(),
+            relation.many_field.to_owned(),
+        );
+        match self
+            .delete_rules
+            .get(&key)
+            .map(|r| r.to_uppercase())
+            .as_deref()
+        {
+            Some("CASCADE") => Reference::Delete,
+            Some("SET NULL") => Reference::Nullify,
+            Some("RESTRICT") => Reference::Restrict,
+            _ if relation.one_deselect_action == "delete" => Reference::Delete,
+            _ => Reference::Nullify,
+        }
+    }
+
+    pub async fn plan(&self, db: &DatabaseConnection, subject: &Subject) -> anyhow::Result<Plan> {
+        let roots = export::select_where(
+            db,
+            &subject.collection,
+            &subject.field,
+            &[subject.value.to_owned()],
+        )
+        .await?;
+        let root_ids = ids(&roots);
+        if root_ids.is_empty() {
+            anyhow::bail!(
+                "No item in `{}` has `{}` = `{}`.",
+                subject.collection,
+                subject.field,
+                subject.value
+            );
+        }
+
+        let mut plan = Plan {
+            subject: subject.clone(),
+            steps: Vec::new(),
+            blocked: Vec::new(),
+        };
+        let mut seen = HashSet::new();
+        self.erase(
+            db,
+            &subject.collection,
+            root_ids.clone(),
+            &mut plan,
+            &mut seen,
+        )
+        .await?;
+
+        if subject.collection == "directus_users" {
+            for (collection, field, reference) in USER_REFERENCES {
+                let reference = match (*collection, *field) {
+                    ("directus_files", "uploaded_by") if self.delete_files => Reference::Delete,
+                    _ => *reference,
+                };
+                let items = export::select_where(db, collection, field, &root_ids).await?;
+                match reference {
+                    // Deleted items are erased like any other, e.g. files used by articles
+                    Reference::Delete => {
+                        self.erase(db, collection, ids(&items), &mut plan, &mut seen)
+                            .await?
+                    }
+                    reference => self.reference_step(
+                        collection,
+                        field,
+                        reference,
+                        ids(&items),
+                        &root_ids,
+                        &mut plan,
+                    ),
+                }
+            }
+            plan.steps.push(Step {
+                collection: "directus_activity".to_owned(),
+                operation: Operation::Anonymize {
+                    fields: ACTIVITY_FIELDS.iter().map(|f| f.to_string()).collect(),
+                },
+                key: "user".to_owned(),
+                ids: root_ids.clone(),
+            });
+        }
+
+        // Items must be deleted after everything that references them
+        plan.steps.reverse();
+        Ok(plan)
+    }
+
+    /// Delete `ids` of `collection` and handle everything that references them.
+    ///
+    /// Steps are pushed parents first and reversed by the caller.
+    async fn erase(
+        &self,
+        db: &DatabaseConnection,
+        collection: &str,
+        ids: Vec<String>,
+        plan: &mut Plan,
+        seen: &mut HashSet<(String, String)>,
+    ) -> Result<(), DbErr> {
+        let ids: Vec<String> = ids
+            .into_iter()
+            .filter(|id| seen.insert((collection.to_owned(), id.to_owned())))
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        plan.steps.push(Step {
+            collection: collection.to_owned(),
+            operation: Operation::Delete,
+            key: PRIMARY_KEY.to_owned(),
+            ids: ids.clone(),
+        });
+        let revisions = export::select_where(db, "directus_revisions", "item", &ids)
+            .await?
+            .into_iter()
+            .filter(|r| r.get("collection").and_then(JsonValue::as_str) == Some(collection))
+            .collect::<Vec<_>>();
+        if !revisions.is_empty() {
+            plan.steps.push(Step {
+                collection: "directus_revisions".to_owned(),
+                operation: Operation::Delete,
+                key: PRIMARY_KEY.to_owned(),
+                ids: self::ids(&revisions),
+            });
+        }
+
+        for relation in export::children_of(&self.relations, collection) {
+            let children =
+                export::select_where(db, &relation.many_collection, &relation.many_field, &ids)
+                    .await?;
+            if children.is_empty() {
+                continue;
+            }
+            let child_ids = self::ids(&children);
+            match self.reference(relation) {
+                Reference::Delete => {
+                    Box::pin(self.erase(db, &relation.many_collection, child_ids, plan, seen))
+                        .await?
+                }
+                reference => self.reference_step(
+                    &relation.many_collection,
+                    &relation.many_field,
+                    reference,
+                    child_ids,
+                    &ids,
+                    plan,
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a step for items that reference erased items through `field`.
+    fn reference_step(
+        &self,
+        collection: &str,
+        field: &str,
+        reference: Reference,
+        item_ids: Vec<String>,
+        erased_ids: &[String],
+        plan: &mut Plan,
+    ) {
+        if item_ids.is_empty() {
+            return;
+        }
+        match reference {
+            Reference::Restrict => plan.blocked.push(Blocked {
+                collection: collection.to_owned(),
+                field: field.to_owned(),
+                ids: item_ids,
+            }),
+            Reference::Nullify => plan.steps.push(Step {
+                collection: collection.to_owned(),
+                operation: Operation::Nullify {
+                    field: field.to_owned(),
+                },
+                key: field.to_owned(),
+                ids: erased_ids.to_vec(),
+            }),
+            Reference::Delete => plan.steps.push(Step {
+                collection: collection.to_owned(),
+                operation: Operation::Delete,
+                key: PRIMARY_KEY.to_owned(),
+                ids: item_ids,
+            }),
+        }
+    }
+}
+
+fn ids(items: &[JsonValue]) -> Vec<String> {
+    items
+        .iter()
+        .filter_map(|item| item.get(PRIMARY_KEY).and_then(export::to_text))
+        .collect()
+}
+
+/// The `ON DELETE` rules of all foreign keys.
+///
+/// SQLite doesn't expose them in `information_schema`, so there are none.
+pub async fn fetch_delete_rules(
+    db: &DatabaseConnection,
+) -> Result<HashMap<(String, String), String>, DbErr> {
+    let backend = db.get_database_backend();
+    if backend == DbBackend::Sqlite {
+        return Ok(HashMap::new());
+    }
+    Ok(
+        JsonValue::find_by_statement(delete_rules_statement(backend))
+            .all(db)
+            .await?
+            .iter()
+            .filter_map(|row| {
+                let get = |key: &str| row.get(key).and_then(JsonValue::as_str).map(str::to_owned);
+                Some((
+                    (get("table_name")?, get("column_name")?),
+                    get("delete_rule")?,
+                ))
+            })
+            .collect(),
+    )
+}
+
+/// Select the `ON DELETE` rules of the current schema's foreign keys.
+///
+/// Other schemas may have tables with the same names.
+pub fn delete_rules_statement(backend: DbBackend) -> Statement {
+    let schema = match backend {
+        DbBackend::MySql => "DATABASE()",
+        _ => "current_schema()",
+    };
+    Statement::from_string(
+        backend,
+        format!(
+            "SELECT kcu.table_name AS table_name, kcu.column_name AS column_name, rc.delete_rule AS delete_rule \
+             FROM information_schema.referential_constraints rc \
+             JOIN information_schema.key_column_usage kcu \
+             ON rc.constraint_name = kcu.constraint_name AND rc.constraint_schema = kcu.constraint_schema \
+             WHERE rc.constraint_schema = {}",
+            schema
+        ),
+    )
+}
+
+/// Build the statement of a step.
+pub fn statement(backend: DbBackend, step: &Step) -> Statement {
+    let condition = export::text_in(backend, &step.key, &step.ids);
+    let table = Alias::new(&step.collection);
+    match &step.operation {
+        Operation::Delete => backend.build(Query::delete().from_table(table).and_where(condition)),
+        Operation::Nullify { field } => backend.build(
+            Query::update()
+                .table(table)
+                .value(Alias::new(field), Expr::cust("NULL"))
+                .and_where(condition),
+        ),
+        Operation::Anonymize { fields } => {
+            let mut query = Query::update();
+            query.table(table).and_where(condition);
+            for field in fields {
+                query.value(Alias::new(field), Expr::cust("NULL"));
+            }
+            backend.build(&query)
+        }
+    }
+}
+
+/// Run all steps in a single transaction.
+pub async fn execute(db: &DatabaseConnection, plan: &Plan) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    for step in &plan.steps {
+        txn.execute(statement(db.get_database_backend(), step))
+            .await?;
+    }
+    txn.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relation(deselect: &str) -> directus_relations::Model {
+        directus_relations::Model {
+            id: 0,
+            many_collection: "orders".to_owned(),
+            many_field: "customer".to_owned(),
+            one_collection: Some("customers".to_owned()),
+            one_field: None,
+            one_collection_field: None,
+            one_allowed_collections: None,
+            junction_field: None,
+            sort_field: None,
+            one_deselect_action: deselect.to_owned(),
+        }
+    }
+
+    fn planner(rule: Option<&str>) -> Planner {
+        Planner {
+            relations: Vec::new(),
+            delete_rules: rule
+                .map(|r| (("orders".to_owned(), "customer".to_owned()), r.to_owned()))
+                .into_iter()
+                .collect(),
+            delete_files: false,
+        }
+    }
+
+    #[test]
+    fn reference_prefers_foreign_key_rules() {
+        assert_eq!(
+            planner(None).reference(&relation("nullify")),
+            Reference::Nullify
+        );
+        assert_eq!(
+            planner(None).reference(&relation("delete")),
+            Reference::Delete
+        );
+        assert_eq!(
+            planner(Some("CASCADE")).reference(&relation("nullify")),
+            Reference::Delete
+        );
+        assert_eq!(
+            planner(Some("RESTRICT")).reference(&relation("delete")),
+            Reference::Restrict
+        );
+        assert_eq!(
+            planner(Some("NO ACTION")).reference(&relation("delete")),
+            Reference::Delete
+        );
+    }
+
+    #[test]
+    fn statement_builds_deletes_and_updates() {
+        let step = Step {
+            collection: "orders".to_owned(),
+            operation: Operation::Nullify {
+                field: "customer".to_owned(),
+            },
+            key: "customer".to_owned(),
+            ids: vec!["1".to_owned()],
+        };
+        assert_eq!(
+            statement(DbBackend::Postgres, &step).to_string(),
+            r#"UPDATE "orders" SET "customer" = NULL WHERE CAST("customer" AS text) IN ('1')"#
+        );
+
+        let step = Step {
+            operation: Operation::Delete,
+            key: "id".to_owned(),
+            ..step
+        };
+        assert_eq!(
+            statement(DbBackend::Postgres, &step).to_string(),
+            r#"DELETE FROM "orders" WHERE CAST("id" AS text) IN ('1')"#
+        );
+    }
+
+    #[test]
+    fn delete_rules_are_limited_to_the_current_schema() {
+        assert!(delete_rules_statement(DbBackend::Postgres)
+            .to_string()
+            .ends_with("WHERE rc.constraint_schema = current_schema()"));
+        assert!(delete_rules_statement(DbBackend::MySql)
+            .to_string()
+            .ends_with("WHERE rc.constraint_schema = DATABASE()"));
+    }
+
+    #[test]
+    fn plan_erases_deleted_files_and_their_references() {
+        futures::executor::block_on(async {
+            let db = Database::connect("sqlite::memory:").await.unwrap();
+            for table in [
+                "directus_users (id TEXT)",
+                "directus_revisions (id INTEGER, collection TEXT, item TEXT)",
+                "directus_sessions (token TEXT, user TEXT)",
+                "directus_presets (id INTEGER, user TEXT)",
+                "directus_notifications (id INTEGER, recipient TEXT, sender TEXT)",
+                "directus_shares (id TEXT, user_created TEXT)",
+                "directus_files (id TEXT, uploaded_by TEXT, modified_by TEXT)",
+                "directus_dashboards (id TEXT, user_created TEXT)",
+                "directus_panels (id TEXT, user_created TEXT)",
+                "directus_flows (id TEXT, user_created TEXT)",
+                "directus_operations (id TEXT, user_created TEXT)",
+                "articles (id INTEGER, image TEXT)",
+            ] {
+                db.execute_unprepared(&format!("CREATE TABLE {}", table))
+                    .await
+                    .unwrap();
+            }
+            db.execute_unprepared(
+                "INSERT INTO directus_users VALUES ('u1');
+                INSERT INTO directus_files VALUES ('f1', 'u1', NULL), ('f2', 'u2', 'u1');
+                INSERT INTO articles VALUES (1, 'f1');",
+            )
+            .await
+            .unwrap();
+            let planner = Planner {
+                relations: vec![directus_relations::Model {
+                    many_collection: "articles".to_owned(),
+                    many_field: "image".to_owned(),
+                    one_collection: Some("directus_files".to_owned()),
+                    ..relation("nullify")
+                }],
+                delete_rules: HashMap::new(),
+                delete_files: true,
+            };
+
+            let subject = Subject::parse("directus_users.id=u1").unwrap();
+            let plan = planner.plan(&db, &subject).await.unwrap();
+            let steps: Vec<(&str, &Operation, Vec<&str>)> = plan
+                .steps
+                .iter()
+                .filter(|s| !matches!(s.operation, Operation::Anonymize { .. }))
+                .map(|s| {
+                    let ids = s.ids.iter().map(String::as_str).collect();
+                    (s.collection.as_str(), &s.operation, ids)
+                })
+                .collect();
+            let nullify = |field: &str| Operation::Nullify {
+                field: field.to_owned(),
+            };
+            assert_eq!(
+                steps,
+                vec![
+                    ("directus_files", &nullify("modified_by"), vec!["u1"]),
+                    ("articles", &nullify("image"), vec!["f1"]),
+                    ("directus_files", &Operation::Delete, vec!["f1"]),
+                    ("directus_users", &Operation::Delete, vec!["u1"]),
+                ]
+            );
+            db.close().await.unwrap();
+        })
+    }
+}
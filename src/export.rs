@@ -0,0 +1,562 @@
+//! Export everything stored about a person (GDPR Art. 15).
+//!
+//! Start at the person's item, e.g. their `directus_users` row, and follow
+//! `directus_relations` to all items that reference it. Add their files,
+//! their activity and the revisions of all exported items.
+//!
+//! Credentials are left out: the secret fields of `directus_users` and all
+//! fields Directus hashes or conceals.
+use crate::classify;
+use crate::cli::{Export, OutputFormat};
+use crate::entities::{directus_fields, directus_relations};
+use crate::output;
+use crate::zip;
+use anyhow::Context;
+use chrono::Local;
+use sea_orm::sea_query::{Alias, Expr, Func, Query, SimpleExpr};
+use sea_orm::{
+    ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, EntityTrait, FromQueryResult,
+    Statement,
+};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::path::PathBuf;
+
+/// The primary key of all collections. Directus uses `id` unless configured otherwise.
+pub const PRIMARY_KEY: &str = "id";
+
+/// Secret fields of system collections, which have no `special` in `directus_fields`.
+const SYSTEM_SECRETS: &[(&str, &str)] = &[
+    ("directus_users", "password"),
+    ("directus_users", "tfa_secret"),
+    ("directus_users", "token"),
+    ("directus_users", "auth_data"),
+];
+
+/// 🏡 Handle logic for the `export` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `export` specific options.
+pub async fn export_entrypoint(args: &ExportOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+    let relations = directus_relations::Entity::find().all(&db).await?;
+    let meta = classify::fetch_field_meta(&db).await?;
+
+    let subject = &args.subject;
+    let roots = select_where(
+        &db,
+        &subject.collection,
+        &subject.field,
+        &[subject.value.to_owned()],
+    )
+    .await?;
+    if roots.is_empty() {
+        anyhow::bail!(
+            "No item in `{}` has `{}` = `{}`.",
+            subject.collection,
+            subject.field,
+            subject.value
+        );
+    }
+
+    let mut bundle = Bundle::new(subject.clone(), secrets(&meta));
+    let mut queue = VecDeque::new();
+    for root in roots {
+        bundle.add(&subject.collection, &root);
+        queue.push_back((subject.collection.to_owned(), root, 0));
+    }
+    while let Some((collection, item, depth)) = queue.pop_front() {
+        if depth >= args.depth {
+            continue;
+        }
+        let id = match item.get(PRIMARY_KEY).and_then(to_text) {
+            Some(id) => id,
+            None => continue,
+        };
+        for relation in children_of(&relations, &collection) {
+            let children = select_where(
+                &db,
+                &relation.many_collection,
+                &relation.many_field,
+                &[id.to_owned()],
+            )
+            .await?;
+            for child in children {
+                if !bundle.add(&relation.many_collection, &child) {
+                    continue;
+                }
+                if let Some((linked_collection, key)) =
+                    junction_target(&relations, relation, &child)
+                {
+                    for linked in select_where(&db, &linked_collection, PRIMARY_KEY, &[key]).await?
+                    {
+                        bundle.add(&linked_collection, &linked);
+                    }
+                }
+                queue.push_back((relation.many_collection.to_owned(), child, depth + 1));
+            }
+        }
+        if depth == 0 {
+            for (parent_collection, key) in parents_of(&relations, &collection, &item) {
+                for parent in select_where(&db, &parent_collection, PRIMARY_KEY, &[key]).await? {
+                    bundle.add(&parent_collection, &parent);
+                }
+            }
+        }
+    }
+
+    let user_ids = match subject.collection.as_str() {
+        "directus_users" => bundle.ids("directus_users"),
+        _ => Vec::new(),
+    };
+    if !user_ids.is_empty() {
+        bundle.files = select_where(&db, "directus_files", "uploaded_by", &user_ids).await?;
+        bundle.activity = select_where(&db, "directus_activity", "user", &user_ids).await?;
+    }
+    for collection in bundle.items.keys().cloned().collect::<Vec<_>>() {
+        let ids = bundle.ids(&collection);
+        for mut revision in select_where(&db, "directus_revisions", "item", &ids).await? {
+            if revision.get("collection").and_then(JsonValue::as_str) != Some(&collection) {
+                continue;
+            }
+            for version in ["data", "delta"] {
+                if let Some(item) = revision.get_mut(version) {
+                    bundle.redact(&collection, item);
+                }
+            }
+            bundle.revisions.push(revision);
+        }
+    }
+
+    match &args.file {
+        Some(path) if path.extension().is_some_and(|e| e == "zip") => {
+            let archive = zip::write(&bundle.to_files()?, Local::now().naive_local())?;
+            std::fs::write(path, archive).with_context(|| format!("Can't write {}", path.display()))
+        }
+        Some(path) => std::fs::write(path, serde_json::to_string_pretty(&bundle)?)
+            .with_context(|| format!("Can't write {}", path.display())),
+        None => output::print(&args.output, &bundle),
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub subject: Subject,
+    pub depth: usize,
+    pub file: Option<PathBuf>,
+}
+
+impl TryFrom<Export> for ExportOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(export: Export) -> anyhow::Result<Self> {
+        let subject = match (export.email, export.field_match) {
+            (Some(email), _) => Subject {
+                collection: "directus_users".to_owned(),
+                field: "email".to_owned(),
+                value: email,
+            },
+            (None, Some(field_match)) => Subject::parse(&field_match)?,
+            (None, None) => anyhow::bail!("Use either --email or --match."),
+        };
+        Ok(ExportOptions {
+            url: export.global_args.url,
+            output: export.global_args.output,
+            subject,
+            depth: export.depth,
+            file: export.file,
+        })
+    }
+}
+
+/// How to find the person, e.g. `customers.email=jane@example.com`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Subject {
+    pub collection: String,
+    pub field: String,
+    pub value: String,
+}
+
+impl Subject {
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let error = || format!("Invalid match `{}`. Use `collection.field=value`.", s);
+        let (path, value) = s.split_once('=').with_context(error)?;
+        let (collection, field) = path.split_once('.').with_context(error)?;
+        if collection.is_empty() || field.is_empty() {
+            anyhow::bail!(error());
+        }
+        Ok(Self {
+            collection: collection.trim().to_owned(),
+            field: field.trim().to_owned(),
+            value: value.trim().to_owned(),
+        })
+    }
+}
+
+/// Everything found about a person.
+///
+/// * `items` - Items by collection
+/// * `files` - Metadata of files the person uploaded
+/// * `activity` - Everything the person did
+/// * `revisions` - All versions of the exported items
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Bundle {
+    pub subject: Subject,
+    pub items: BTreeMap<String, Vec<JsonValue>>,
+    pub files: Vec<JsonValue>,
+    pub activity: Vec<JsonValue>,
+    pub revisions: Vec<JsonValue>,
+    #[serde(skip)]
+    seen: HashSet<(String, String)>,
+    /// Fields left out as `(collection, field)`
+    #[serde(skip)]
+    secrets: HashSet<(String, String)>,
+}
+
+impl Bundle {
+    pub fn new(subject: Subject, secrets: HashSet<(String, String)>) -> Self {
+        Self {
+            subject,
+            items: BTreeMap::new(),
+            files: Vec::new(),
+            activity: Vec::new(),
+            revisions: Vec::new(),
+            seen: HashSet::new(),
+            secrets,
+        }
+    }
+
+    /// Add an item without its secrets. Return `false` if it was already added.
+    pub fn add(&mut self, collection: &str, item: &JsonValue) -> bool {
+        let id = item
+            .get(PRIMARY_KEY)
+            .and_then(to_text)
+            .unwrap_or_else(|| item.to_string());
+        if !self.seen.insert((collection.to_owned(), id)) {
+            return false;
+        }
+        let mut item = item.clone();
+        self.redact(collection, &mut item);
+        self.items
+            .entry(collection.to_owned())
+            .or_default()
+            .push(item);
+        true
+    }
+
+    /// Remove the secret fields of an item of `collection`.
+    pub fn redact(&self, collection: &str, item: &mut JsonValue) {
+        if let JsonValue::Object(fields) = item {
+            fields.retain(|field, _| {
+                !self
+                    .secrets
+                    .contains(&(collection.to_owned(), field.to_owned()))
+            });
+        }
+    }
+
+    /// The primary keys of all items of `collection`.
+    pub fn ids(&self, collection: &str) -> Vec<String> {
+        self.items
+            .get(collection)
+            .into_iter()
+            .flatten()
+            .filter_map(|item| item.get(PRIMARY_KEY).and_then(to_text))
+            .collect()
+    }
+
+    /// One json file per collection plus the rest, for a zip archive.
+    pub fn to_files(&self) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        let mut files = vec![(
+            "subject.json".to_owned(),
+            serde_json::to_vec_pretty(&self.subject)?,
+        )];
+        for (collection, items) in &self.items {
+            files.push((
+                format!("items/{}.json", collection),
+                serde_json::to_vec_pretty(items)?,
+            ));
+        }
+        files.push((
+            "files.json".to_owned(),
+            serde_json::to_vec_pretty(&self.files)?,
+        ));
+        files.push((
+            "activity.json".to_owned(),
+            serde_json::to_vec_pretty(&self.activity)?,
+        ));
+        files.push((
+            "revisions.json".to_owned(),
+            serde_json::to_vec_pretty(&self.revisions)?,
+        ));
+        Ok(files)
+    }
+}
+
+/// All fields that must not be exported, as `(collection, field)`.
+pub fn secrets(meta: &[directus_fields::Model]) -> HashSet<(String, String)> {
+    SYSTEM_SECRETS
+        .iter()
+        .map(|(collection, field)| (collection.to_string(), field.to_string()))
+        .chain(
+            meta.iter()
+                .filter(|m| classify::is_hashed(m))
+                .map(|m| (m.collection.to_owned(), m.field.to_owned())),
+        )
+        .collect()
+}
+
+/// Relations whose many side references items of `collection`, e.g. `orders.customer`.
+pub fn children_of<'a>(
+    relations: &'a [directus_relations::Model],
+    collection: &str,
+) -> impl Iterator<Item = &'a directus_relations::Model> {
+    let collection = collection.to_owned();
+    relations
+        .iter()
+        .filter(move |r| r.one_collection.as_deref() == Some(collection.as_str()))
+}
+
+/// The item a junction row of a many-to-many relation links to, as `(collection, key)`.
+///
+/// `relation` is the junction's relation to the exported item. System
+/// collections are skipped like in [`parents_of`].
+fn junction_target(
+    relations: &[directus_relations::Model],
+    relation: &directus_relations::Model,
+    item: &JsonValue,
+) -> Option<(String, String)> {
+    let field = relation.junction_field.as_ref()?;
+    let target = relations
+        .iter()
+        .find(|r| r.many_collection == relation.many_collection && &r.many_field == field)?;
+    // Many-to-any junctions store the collection next to the key
+    let collection = match (&target.one_collection, &target.one_collection_field) {
+        (Some(collection), _) => collection.to_owned(),
+        (None, Some(collection_field)) => item.get(collection_field)?.as_str()?.to_owned(),
+        (None, None) => return None,
+    };
+    if collection.starts_with("directus_") {
+        return None;
+    }
+    Some((collection, item.get(field).and_then(to_text)?))
+}
+
+/// Items `item` references, as `(collection, key)`.
+///
+/// System collections are skipped. They would add other people, e.g. the
+/// user in `user_created`.
+fn parents_of(
+    relations: &[directus_relations::Model],
+    collection: &str,
+    item: &JsonValue,
+) -> Vec<(String, String)> {
+    relations
+        .iter()
+        .filter(|r| r.many_collection == collection)
+        .filter_map(|r| {
+            let parent = r.one_collection.as_ref()?;
+            if parent.starts_with("directus_") {
+                return None;
+            }
+            Some((
+                parent.to_owned(),
+                item.get(&r.many_field).and_then(to_text)?,
+            ))
+        })
+        .collect()
+}
+
+/// Ids can be numbers or strings, so compare them as text.
+pub fn to_text(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::String(s) => Some(s.to_owned()),
+        JsonValue::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Select all rows of `collection` where `field` is one of `values`.
+pub async fn select_where(
+    db: &DatabaseConnection,
+    collection: &str,
+    field: &str,
+    values: &[String],
+) -> Result<Vec<JsonValue>, DbErr> {
+    if values.is_empty() {
+        return Ok(Vec::new());
+    }
+    let statement = select_where_statement(db.get_database_backend(), collection, field, values);
+    JsonValue::find_by_statement(statement).all(db).await
+}
+
+pub fn select_where_statement(
+    backend: DbBackend,
+    collection: &str,
+    field: &str,
+    values: &[String],
+) -> Statement {
+    let query = Query::select()
+        .expr(Expr::asterisk())
+        .from(Alias::new(collection))
+        .and_where(text_in(backend, field, values))
+        .to_owned();
+    backend.build(&query)
+}
+
+/// `field` is one of `values`.
+///
+/// The column is cast to text, because ids may be integers or uuids.
+pub fn text_in(backend: DbBackend, field: &str, values: &[String]) -> SimpleExpr {
+    let text = match backend {
+        DbBackend::MySql => "char",
+        _ => "text",
+    };
+    Expr::expr(Func::cast_as(
+        Expr::col(Alias::new(field)),
+        Alias::new(text),
+    ))
+    .is_in(values.iter().cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::json;
+
+    fn relation(many: &str, field: &str, one: &str) -> directus_relations::Model {
+        directus_relations::Model {
+            id: 0,
+            many_collection: many.to_owned(),
+            many_field: field.to_owned(),
+            one_collection: Some(one.to_owned()),
+            one_field: None,
+            one_collection_field: None,
+            one_allowed_collections: None,
+            junction_field: None,
+            sort_field: None,
+            one_deselect_action: "nullify".to_owned(),
+        }
+    }
+
+    #[test]
+    fn subject_parse_requires_collection_field_and_value() {
+        assert_eq!(
+            Subject::parse("customers.email=jane@example.com").unwrap(),
+            Subject {
+                collection: "customers".to_owned(),
+                field: "email".to_owned(),
+                value: "jane@example.com".to_owned(),
+            }
+        );
+        assert!(Subject::parse("customers=jane").is_err());
+        assert!(Subject::parse("customers.email").is_err());
+    }
+
+    #[test]
+    fn relations_walk_to_children_and_skip_system_parents() {
+        let relations = vec![
+            relation("orders", "customer", "customers"),
+            relation("customers", "address", "addresses"),
+            relation("customers", "user_created", "directus_users"),
+        ];
+        let children: Vec<&str> = children_of(&relations, "customers")
+            .map(|r| r.many_collection.as_str())
+            .collect();
+        assert_eq!(children, vec!["orders"]);
+
+        let item = json!({ "id": 1, "address": 7, "user_created": "u1" });
+        assert_eq!(
+            parents_of(&relations, "customers", &item),
+            vec![("addresses".to_owned(), "7".to_owned())]
+        );
+    }
+
+    #[test]
+    fn junction_target_follows_many_to_many_relations() {
+        let customers = directus_relations::Model {
+            junction_field: Some("tags_id".to_owned()),
+            ..relation("customers_tags", "customers_id", "customers")
+        };
+        let relations = vec![
+            customers.clone(),
+            directus_relations::Model {
+                junction_field: Some("customers_id".to_owned()),
+                ..relation("customers_tags", "tags_id", "tags")
+            },
+        ];
+        let item = json!({ "id": 3, "customers_id": 1, "tags_id": 9 });
+        assert_eq!(
+            junction_target(&relations, &customers, &item),
+            Some(("tags".to_owned(), "9".to_owned()))
+        );
+        assert_eq!(
+            junction_target(
+                &relations,
+                &relation("orders", "customer", "customers"),
+                &item
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn bundle_add_skips_duplicates() {
+        let mut bundle = Bundle::new(Subject::parse("customers.id=1").unwrap(), HashSet::new());
+        assert!(bundle.add("orders", &json!({ "id": 1 })));
+        assert!(!bundle.add("orders", &json!({ "id": "1" })));
+        assert_eq!(bundle.ids("orders"), vec!["1"]);
+    }
+
+    #[test]
+    fn select_where_statement_compares_as_text() {
+        let statement =
+            select_where_statement(DbBackend::Postgres, "orders", "customer", &["1".to_owned()]);
+        assert_eq!(
+            statement.to_string(),
+            r#"SELECT * FROM "orders" WHERE CAST("customer" AS text) IN ('1')"#
+        );
+    }
+
+    #[test]
+    fn bundle_leaves_out_secrets() {
+        let pin = test_support::field_meta("customers", "pin", Some("hash"));
+        let mut bundle = Bundle::new(
+            Subject::parse("directus_users.email=jane@example.com").unwrap(),
+            secrets(&[pin]),
+        );
+        bundle.add(
+            "directus_users",
+            &json!({
+                "id": "u1",
+                "email": "jane@example.com",
+                "password": "$argon2id$v=19$m=65536,t=3,p=4$c2FsdA$aGFzaA",
+                "tfa_secret": "JBSWY3DPEHPK3PXP",
+                "token": "static-token",
+                "auth_data": "{\"refresh_token\":\"r1\"}",
+            }),
+        );
+        bundle.add("customers", &json!({ "id": 1, "pin": "$argon2id$pin" }));
+
+        let content: String = bundle
+            .to_files()
+            .unwrap()
+            .iter()
+            .map(|(_, bytes)| String::from_utf8_lossy(bytes).into_owned())
+            .collect();
+        assert!(content.contains("jane@example.com"));
+        for secret in [
+            "argon2id",
+            "JBSWY3DPEHPK3PXP",
+            "static-token",
+            "refresh_token",
+        ] {
+            assert!(!content.contains(secret), "{} is exported", secret);
+        }
+    }
+}
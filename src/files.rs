@@ -0,0 +1,352 @@
+//! Report which files each role can read.
+//!
+//! Access to `directus_files` is a permission on that system collection,
+//! usually filtered by `folder`. Filters are evaluated against every file,
+//! with its folder and the folder's parents nested like Directus resolves
+//! them. Files behind dynamic variables like `$CURRENT_USER` depend on the
+//! user and are counted as conditional.
+use crate::cli::{Files, OutputFormat};
+use crate::directus;
+use crate::entities::{directus_files, directus_folders, directus_permissions};
+use crate::graph::filter::Filter;
+use crate::output;
+use sea_orm::prelude::Uuid;
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Folders nest deeper than this only in cycles.
+const MAX_DEPTH: usize = 32;
+
+/// 🏡 Handle logic for the `files` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `files` specific options.
+pub async fn files_entrypoint(args: &FilesOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let role_names = directus::fetch_role_names(&db).await?;
+    let admin_roles = directus::fetch_admin_roles(&db).await?;
+    let permissions = directus_permissions::Entity::find()
+        .filter(directus_permissions::Column::Collection.eq("directus_files"))
+        .filter(directus_permissions::Column::Action.eq("read"))
+        .all(&db)
+        .await?;
+    let folders = Folders::new(directus_folders::Entity::find().all(&db).await?);
+    let files = directus_files::Entity::find().all(&db).await?;
+
+    let items: Vec<FileItem> = files.iter().map(|file| folders.item(file)).collect();
+    let mut report = FilesReport::default();
+    let roles = role_names.keys().map(|id| Some(id.as_str())).chain([None]);
+    for role in roles {
+        let name = directus::role_name(role, &role_names);
+        let access = match role.is_some_and(|id| admin_roles.contains(id)) {
+            true => Some(Access::admin(name, &items)),
+            false => Access::new(name, role, &permissions, &items)?,
+        };
+        match (role, access) {
+            (None, access) => report.public = access,
+            (Some(_), Some(access)) => report.roles.push(access),
+            (Some(_), None) => (),
+        }
+    }
+    report.roles.sort_by(|a, b| a.role.cmp(&b.role));
+
+    output::print(&args.output, &report)
+}
+
+#[derive(Debug)]
+pub struct FilesOptions {
+    pub url: String,
+    pub output: OutputFormat,
+}
+
+impl From<Files> for FilesOptions {
+    fn from(files: Files) -> Self {
+        FilesOptions {
+            url: files.global_args.url,
+            output: files.global_args.output,
+        }
+    }
+}
+
+/// All folders by id.
+pub struct Folders(HashMap<Uuid, directus_folders::Model>);
+
+impl Folders {
+    pub fn new(folders: Vec<directus_folders::Model>) -> Self {
+        Self(folders.into_iter().map(|f| (f.id, f)).collect())
+    }
+
+    /// The folder as nested object, e.g. `{ "id": …, "name": "Passports", "parent": { … } }`.
+    pub fn nested(&self, id: Option<Uuid>) -> Value {
+        self.chain(id)
+            .iter()
+            .rev()
+            .fold(Value::Null, |parent, folder| {
+                json!({ "id": folder.id.to_string(), "name": folder.name, "parent": parent })
+            })
+    }
+
+    /// The folder's path like `/HR/Passports`. Files without a folder are in `/`.
+    pub fn path(&self, id: Option<Uuid>) -> String {
+        let names: Vec<&str> = self
+            .chain(id)
+            .iter()
+            .rev()
+            .map(|folder| folder.name.as_str())
+            .collect();
+        format!("/{}", names.join("/"))
+    }
+
+    /// The folder and all its parents, innermost first.
+    fn chain(&self, id: Option<Uuid>) -> Vec<&directus_folders::Model> {
+        let mut chain = Vec::new();
+        let mut next = id;
+        while let Some(folder) = next.and_then(|id| self.0.get(&id)) {
+            if chain.len() == MAX_DEPTH {
+                break;
+            }
+            chain.push(folder);
+            next = folder.parent;
+        }
+        chain
+    }
+
+    /// A file as filters see it.
+    pub fn item(&self, file: &directus_files::Model) -> FileItem {
+        FileItem {
+            folder: self.path(file.folder),
+            filesize: file.filesize.unwrap_or_default(),
+            value: json!({
+                "id": file.id.to_string(),
+                "storage": file.storage,
+                "filename_disk": file.filename_disk,
+                "filename_download": file.filename_download,
+                "title": file.title,
+                "type": file.r#type,
+                "folder": self.nested(file.folder),
+                "uploaded_by": file.uploaded_by.map(|id| id.to_string()),
+                "modified_by": file.modified_by.map(|id| id.to_string()),
+                "filesize": file.filesize,
+                "tags": file.tags,
+            }),
+        }
+    }
+}
+
+pub struct FileItem {
+    pub folder: String,
+    pub filesize: i64,
+    pub value: Value,
+}
+
+/// Files of a folder, without its subfolders.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct FolderAccess {
+    pub folder: String,
+    pub files: usize,
+    pub filesize: i64,
+    pub conditional_files: usize,
+}
+
+/// What a role can read in `directus_files`.
+///
+/// `conditional_files` depend on the user, e.g. on `$CURRENT_USER`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Access {
+    pub role: String,
+    pub admin_access: bool,
+    pub files: usize,
+    pub filesize: i64,
+    pub conditional_files: usize,
+    pub folders: Vec<FolderAccess>,
+}
+
+impl Access {
+    /// `None` if the role can't read `directus_files`.
+    pub fn new(
+        role: String,
+        role_id: Option<&str>,
+        permissions: &[directus_permissions::Model],
+        items: &[FileItem],
+    ) -> anyhow::Result<Option<Self>> {
+        let filters: Vec<Filter> = permissions
+            .iter()
+            .filter(|p| p.role.as_deref() == role_id)
+            .map(|p| Filter::try_from(p.permissions.as_ref().unwrap_or(&Value::Null)))
+            .collect::<Result<_, _>>()?;
+        if filters.is_empty() {
+            return Ok(None);
+        }
+
+        let filter = Filter::Or(filters);
+        Ok(Some(Self::count(role, false, items, |item| {
+            filter.evaluate(item)
+        })))
+    }
+
+    /// Admins read all files.
+    pub fn admin(role: String, items: &[FileItem]) -> Self {
+        Self::count(role, true, items, |_| Some(true))
+    }
+
+    fn count(
+        role: String,
+        admin_access: bool,
+        items: &[FileItem],
+        readable: impl Fn(&Value) -> Option<bool>,
+    ) -> Self {
+        let mut folders: BTreeMap<&str, FolderAccess> = BTreeMap::new();
+        for item in items {
+            let result = readable(&item.value);
+            if result == Some(false) {
+                continue;
+            }
+            let folder = folders.entry(&item.folder).or_insert_with(|| FolderAccess {
+                folder: item.folder.to_owned(),
+                ..Default::default()
+            });
+            match result {
+                Some(_) => {
+                    folder.files += 1;
+                    folder.filesize += item.filesize;
+                }
+                None => folder.conditional_files += 1,
+            }
+        }
+        let folders: Vec<FolderAccess> = folders.into_values().collect();
+
+        Self {
+            role,
+            admin_access,
+            files: folders.iter().map(|f| f.files).sum(),
+            filesize: folders.iter().map(|f| f.filesize).sum(),
+            conditional_files: folders.iter().map(|f| f.conditional_files).sum(),
+            folders,
+        }
+    }
+}
+
+/// File access of the public role and of all roles that can read files.
+#[derive(Serialize, Debug, Default)]
+pub struct FilesReport {
+    pub public: Option<Access>,
+    pub roles: Vec<Access>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use chrono::DateTime;
+
+    fn folder(id: u128, name: &str, parent: Option<u128>) -> directus_folders::Model {
+        directus_folders::Model {
+            id: Uuid::from_u128(id),
+            name: name.to_owned(),
+            parent: parent.map(Uuid::from_u128),
+        }
+    }
+
+    fn file(id: u128, folder: Option<u128>, filesize: i64) -> directus_files::Model {
+        let now = DateTime::parse_from_rfc3339("2023-06-01T00:00:00Z").unwrap();
+        directus_files::Model {
+            id: Uuid::from_u128(id),
+            storage: "local".to_owned(),
+            filename_disk: None,
+            filename_download: format!("{}.pdf", id),
+            title: None,
+            r#type: Some("application/pdf".to_owned()),
+            folder: folder.map(Uuid::from_u128),
+            uploaded_by: None,
+            uploaded_on: now,
+            modified_by: None,
+            modified_on: now,
+            charset: None,
+            filesize: Some(filesize),
+            width: None,
+            height: None,
+            duration: None,
+            embed: None,
+            description: None,
+            location: None,
+            tags: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn folders_resolve_paths_and_parents() {
+        let folders = Folders::new(vec![folder(1, "HR", None), folder(2, "Passports", Some(1))]);
+        assert_eq!(folders.path(Some(Uuid::from_u128(2))), "/HR/Passports");
+        assert_eq!(folders.path(None), "/");
+        assert_eq!(
+            folders.nested(Some(Uuid::from_u128(2)))["parent"]["name"],
+            "HR"
+        );
+    }
+
+    #[test]
+    fn access_counts_files_per_folder() {
+        let folders = Folders::new(vec![folder(1, "HR", None), folder(2, "Passports", Some(1))]);
+        let items: Vec<FileItem> = [
+            file(1, Some(2), 100),
+            file(2, Some(2), 50),
+            file(3, None, 1),
+        ]
+        .iter()
+        .map(|f| folders.item(f))
+        .collect();
+        let permissions = vec![directus_permissions::Model {
+            id: 1,
+            role: None,
+            collection: "directus_files".to_owned(),
+            action: "read".to_owned(),
+            permissions: Some(json!({ "folder": { "parent": { "name": { "_eq": "HR" } } } })),
+            validation: None,
+            presets: None,
+            fields: Some("*".to_owned()),
+        }];
+
+        let result = Access::new("Public".to_owned(), None, &permissions, &items)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.files, 2);
+        assert_eq!(result.filesize, 150);
+        assert_eq!(result.folders.len(), 1);
+        assert_eq!(result.folders[0].folder, "/HR/Passports");
+        assert_eq!(
+            Access::new("Editor".to_owned(), Some("1"), &permissions, &items).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn access_compares_folders_by_id() {
+        let folders = Folders::new(vec![folder(1, "HR", None), folder(2, "Passports", Some(1))]);
+        let items: Vec<FileItem> = [file(1, Some(1), 10), file(2, Some(2), 20)]
+            .iter()
+            .map(|f| folders.item(f))
+            .collect();
+        let passports = Uuid::from_u128(2).to_string();
+        let read = |filter: Value| directus_permissions::Model {
+            permissions: Some(filter),
+            ..test_support::permission(None, "directus_files", "read", "*")
+        };
+
+        let eq = read(json!({ "folder": { "_eq": passports } }));
+        let result = Access::new("Public".to_owned(), None, &[eq], &items)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.filesize, 20);
+
+        let any = read(json!({ "folder": { "_in": [Uuid::from_u128(1).to_string(), passports] } }));
+        let result = Access::new("Public".to_owned(), None, &[any], &items)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.files, 2);
+    }
+}
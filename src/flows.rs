@@ -0,0 +1,328 @@
+//! Audit Directus Flows, which read and write items outside of permissions.
+//!
+//! Item operations run with the permissions of the trigger, the public role,
+//! a specific role or with full access. A webhook flow with full access makes
+//! its collections readable for anyone who knows the url.
+use crate::cli::{Flows, OutputFormat};
+use crate::directus::{self, Field};
+use crate::dump::{Request, RequestEntity};
+use crate::entities::{directus_flows, directus_operations, directus_permissions};
+use crate::output;
+use sea_orm::{Database, EntityTrait};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeSet, HashSet};
+
+/// 🏡 Handle logic for the `flows` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `flows` specific options.
+pub async fn flows_entrypoint(args: &FlowsOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let admin_roles = directus::fetch_admin_roles(&db).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let flows = directus_flows::Entity::find().all(&db).await?;
+    let operations = directus_operations::Entity::find().all(&db).await?;
+
+    let audit = Audit {
+        permissions: &permissions,
+        fields: &fields,
+        admin_roles: &admin_roles,
+        sensitive: &args.sensitive,
+    };
+    let reports: Vec<FlowReport> = flows
+        .iter()
+        .map(|flow| {
+            let steps: Vec<&directus_operations::Model> =
+                operations.iter().filter(|op| op.flow == flow.id).collect();
+            audit.check(flow, &steps)
+        })
+        .collect();
+
+    output::print(&args.output, &reports)
+}
+
+#[derive(Debug)]
+pub struct FlowsOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub sensitive: Vec<Request>,
+}
+
+impl From<Flows> for FlowsOptions {
+    fn from(flows: Flows) -> Self {
+        FlowsOptions {
+            url: flows.global_args.url,
+            output: flows.global_args.output,
+            sensitive: flows
+                .sensitive
+                .into_iter()
+                .map(|req| RequestEntity::from(req).into())
+                .collect(),
+        }
+    }
+}
+
+/// Whose permissions an item operation uses, from its `permissions` option.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunAs {
+    /// `$trigger`, the default: The user or token that triggered the flow
+    Trigger,
+    /// `$public`
+    Public,
+    /// `$full`: Full access, ignoring all permissions
+    Full,
+    /// A role id
+    Role(String),
+}
+
+impl From<Option<&str>> for RunAs {
+    fn from(permissions: Option<&str>) -> Self {
+        match permissions {
+            None | Some("$trigger") => RunAs::Trigger,
+            Some("$public") => RunAs::Public,
+            Some("$full") => RunAs::Full,
+            Some(role) => RunAs::Role(role.to_owned()),
+        }
+    }
+}
+
+/// An `item-*` operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemAccess {
+    pub collection: String,
+    /// The permission action the operation needs, e.g. `read` for `item-read`
+    pub action: String,
+    pub run_as: RunAs,
+}
+
+impl ItemAccess {
+    /// Return `None` for operations that don't touch items.
+    pub fn from_operation(operation: &directus_operations::Model) -> Option<Self> {
+        let action = operation.r#type.strip_prefix("item-")?;
+        let options = operation.options.as_ref().unwrap_or(&JsonValue::Null);
+        Some(Self {
+            collection: options.get("collection")?.as_str()?.to_owned(),
+            action: action.to_owned(),
+            run_as: RunAs::from(options.get("permissions").and_then(JsonValue::as_str)),
+        })
+    }
+}
+
+/// What's worth a second look in a flow.
+///
+/// * `RunsAsAdmin` -> An operation uses full access or an admin role
+/// * `WebhookSensitive` -> A webhook triggers the flow and it can reach sensitive fields
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowIssue {
+    RunsAsAdmin,
+    WebhookSensitive,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FlowReport {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    /// `event`, `webhook`, `schedule`, `manual` or `operation`
+    pub trigger: Option<String>,
+    pub runs_as_admin: bool,
+    pub reads: BTreeSet<String>,
+    pub writes: BTreeSet<String>,
+    /// Sensitive fields as `collection.field`
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub sensitive_fields: BTreeSet<String>,
+    pub issues: Vec<FlowIssue>,
+}
+
+/// Everything needed to check flows.
+pub struct Audit<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [Field],
+    pub admin_roles: &'a HashSet<String>,
+    pub sensitive: &'a [Request],
+}
+
+impl<'a> Audit<'a> {
+    pub fn check(
+        &self,
+        flow: &directus_flows::Model,
+        operations: &[&directus_operations::Model],
+    ) -> FlowReport {
+        let webhook = flow.trigger.as_deref() == Some("webhook");
+        let accesses: Vec<ItemAccess> = operations
+            .iter()
+            .filter_map(|op| ItemAccess::from_operation(op))
+            .collect();
+
+        let mut reads = BTreeSet::new();
+        let mut writes = BTreeSet::new();
+        let mut sensitive_fields = BTreeSet::new();
+        let mut runs_as_admin = false;
+        for access in &accesses {
+            match access.action.as_str() {
+                "read" => reads.insert(access.collection.to_owned()),
+                _ => writes.insert(access.collection.to_owned()),
+            };
+            let admin = match &access.run_as {
+                RunAs::Full => true,
+                RunAs::Role(role) => self.admin_roles.contains(role),
+                _ => false,
+            };
+            runs_as_admin |= admin;
+
+            for field in self.reachable_fields(access, admin, webhook) {
+                if self
+                    .sensitive
+                    .iter()
+                    .any(|s| s.matches(&access.collection, &field))
+                {
+                    sensitive_fields.insert(format!("{}.{}", access.collection, field));
+                }
+            }
+        }
+
+        let mut issues = Vec::new();
+        if runs_as_admin {
+            issues.push(FlowIssue::RunsAsAdmin);
+        }
+        if webhook && !sensitive_fields.is_empty() {
+            issues.push(FlowIssue::WebhookSensitive);
+        }
+
+        FlowReport {
+            id: flow.id.to_string(),
+            name: flow.name.to_owned(),
+            status: flow.status.to_owned(),
+            trigger: flow.trigger.clone(),
+            runs_as_admin,
+            reads,
+            writes,
+            sensitive_fields,
+            issues,
+        }
+    }
+
+    /// Fields an operation can reach. The trigger of a webhook is usually
+    /// anonymous, so it's treated like the public role. Other triggers depend
+    /// on the user and can't be known ahead of time.
+    fn reachable_fields(
+        &self,
+        access: &ItemAccess,
+        admin: bool,
+        webhook: bool,
+    ) -> BTreeSet<String> {
+        let role = match &access.run_as {
+            _ if admin => {
+                return directus::known_fields(self.fields, &access.collection)
+                    .into_iter()
+                    .collect()
+            }
+            RunAs::Role(role) => Some(role.as_str()),
+            RunAs::Public => None,
+            RunAs::Trigger if webhook => None,
+            _ => return BTreeSet::new(),
+        };
+        directus::granted_fields(
+            self.permissions,
+            self.fields,
+            role,
+            &access.collection,
+            &access.action,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::prelude::Uuid;
+    use serde_json::json;
+
+    fn flow(trigger: &str) -> directus_flows::Model {
+        directus_flows::Model {
+            id: Uuid::nil(),
+            name: "Export customers".to_owned(),
+            icon: None,
+            color: None,
+            description: None,
+            status: "active".to_owned(),
+            trigger: Some(trigger.to_owned()),
+            accountability: Some("all".to_owned()),
+            options: None,
+            operation: None,
+            date_created: None,
+            user_created: None,
+        }
+    }
+
+    fn operation(r#type: &str, options: JsonValue) -> directus_operations::Model {
+        directus_operations::Model {
+            id: Uuid::nil(),
+            name: None,
+            key: r#type.to_owned(),
+            r#type: r#type.to_owned(),
+            position_x: 0,
+            position_y: 0,
+            options: Some(options),
+            resolve: None,
+            reject: None,
+            flow: Uuid::nil(),
+            date_created: None,
+            user_created: None,
+        }
+    }
+
+    #[test]
+    fn check_flags_webhooks_with_full_access_to_sensitive_fields() {
+        let fields = vec![Field {
+            collection: "customers".to_owned(),
+            field: "email".to_owned(),
+        }];
+        let sensitive: Vec<Request> = vec![RequestEntity::from("*.email".to_owned()).into()];
+        let admin_roles = HashSet::new();
+        let audit = Audit {
+            permissions: &[],
+            fields: &fields,
+            admin_roles: &admin_roles,
+            sensitive: &sensitive,
+        };
+        let read = operation(
+            "item-read",
+            json!({ "collection": "customers", "permissions": "$full" }),
+        );
+        let log = operation("log", json!({ "message": "done" }));
+
+        let report = audit.check(&flow("webhook"), &[&read, &log]);
+        assert!(report.runs_as_admin);
+        assert_eq!(report.reads, BTreeSet::from(["customers".to_owned()]));
+        assert_eq!(
+            report.issues,
+            vec![FlowIssue::RunsAsAdmin, FlowIssue::WebhookSensitive]
+        );
+
+        let report = audit.check(&flow("manual"), &[&read]);
+        assert_eq!(report.issues, vec![FlowIssue::RunsAsAdmin]);
+    }
+
+    #[test]
+    fn check_uses_trigger_permissions_by_default() {
+        let admin_roles = HashSet::new();
+        let audit = Audit {
+            permissions: &[],
+            fields: &[],
+            admin_roles: &admin_roles,
+            sensitive: &[],
+        };
+        let update = operation("item-update", json!({ "collection": "articles" }));
+        let report = audit.check(&flow("event"), &[&update]);
+        assert!(!report.runs_as_admin);
+        assert_eq!(report.writes, BTreeSet::from(["articles".to_owned()]));
+        assert!(report.issues.is_empty());
+    }
+}
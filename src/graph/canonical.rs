@@ -0,0 +1,223 @@
+//! Normalize Directus filter Json into a canonical form.
+//!
+//! Different Directus versions and manual edits produce logically identical
+//! Json that differs in key order, nesting or number formatting. Everything
+//! that compares or hashes filters should use [`canonicalize`] first.
+use serde_json::{Map, Number, Value as JsonValue};
+use std::collections::BTreeMap;
+
+/// Operators whose values are compared as numbers, i.e. `5.0` equals `5`.
+/// On key fields, `"5"` equals `5` too.
+const COMPARISON_OPERATORS: &[&str] = &[
+    "_eq",
+    "_neq",
+    "_lt",
+    "_lte",
+    "_gt",
+    "_gte",
+    "_in",
+    "_nin",
+    "_between",
+    "_nbetween",
+];
+
+/// Operators that take a list of values. Directus also accepts CSV strings.
+const LIST_OPERATORS: &[&str] = &["_in", "_nin"];
+
+/// Return a canonical version of a Directus filter Json.
+///
+/// The rules are:
+/// * Object keys are sorted
+/// * `_and` / `_or` with a single child are replaced by the child
+/// * Nested `_and` in `_and` (and `_or` in `_or`) are flattened
+/// * Branches of `_and` / `_or` are de-duplicated and sorted
+/// * Values of `_in` / `_nin` are de-duplicated and sorted
+/// * Numbers are normalized, e.g. `5.0` becomes `5`
+/// * Integer strings compared with key fields like `id` or `author_id` become
+///   numbers. Other fields like `zip` keep their strings, `"01234"` isn't `1234`
+///
+/// # Example
+/// ```
+/// let a = canonicalize(&json!({ "_and": [{ "id": { "_eq": "5" } }] }));
+/// assert_eq!(a, json!({ "id": { "_eq": 5 } }));
+/// ```
+pub fn canonicalize(json: &JsonValue) -> JsonValue {
+    canonicalize_field(json, None)
+}
+
+/// Canonicalize the filter of `field`, e.g. `{ "_eq": "5" }` of `id`.
+fn canonicalize_field(json: &JsonValue, field: Option<&str>) -> JsonValue {
+    match json {
+        JsonValue::Object(o) => canonicalize_object(o, field),
+        JsonValue::Array(a) => JsonValue::Array(
+            a.iter()
+                .map(|item| canonicalize_field(item, field))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Primary and foreign keys by Directus naming conventions.
+fn is_key(field: Option<&str>) -> bool {
+    field.is_some_and(|f| f == "id" || f.ends_with("_id"))
+}
+
+fn canonicalize_object(object: &Map<String, JsonValue>, field: Option<&str>) -> JsonValue {
+    let is_key = is_key(field);
+    let mut sorted: BTreeMap<String, JsonValue> = BTreeMap::new();
+    for (key, value) in object {
+        let value = match key.as_str() {
+            "_and" | "_or" => match value {
+                JsonValue::Array(branches) => canonicalize_branches(key, branches),
+                other => canonicalize(other),
+            },
+            operator if LIST_OPERATORS.contains(&operator) => canonicalize_list(value, is_key),
+            operator if COMPARISON_OPERATORS.contains(&operator) => match value {
+                JsonValue::Array(a) => JsonValue::Array(
+                    a.iter()
+                        .map(|item| normalize_scalar(item, is_key))
+                        .collect(),
+                ),
+                other => normalize_scalar(other, is_key),
+            },
+            operator if operator.starts_with('_') => value.clone(),
+            key => canonicalize_field(value, Some(key)),
+        };
+        sorted.insert(key.to_owned(), value);
+    }
+
+    // A logical operator with a single child is the child itself
+    if sorted.len() == 1 {
+        if let Some((key, JsonValue::Array(branches))) = sorted.iter().next() {
+            if key == "_and" || key == "_or" {
+                match branches.len() {
+                    0 => return JsonValue::Object(Map::new()),
+                    1 => return branches[0].clone(),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    JsonValue::Object(sorted.into_iter().collect())
+}
+
+/// Canonicalize, flatten, de-duplicate and sort the branches of `_and` / `_or`.
+fn canonicalize_branches(operator: &str, branches: &[JsonValue]) -> JsonValue {
+    let mut flat: BTreeMap<String, JsonValue> = BTreeMap::new();
+    for branch in branches.iter().map(canonicalize) {
+        let nested = match &branch {
+            JsonValue::Object(o) if o.len() == 1 => match o.get(operator) {
+                Some(JsonValue::Array(nested)) => Some(nested.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        for item in nested.unwrap_or_else(|| vec![branch]) {
+            // An empty object doesn't restrict anything in an `_and`
+            if operator == "_and" && item == JsonValue::Object(Map::new()) {
+                continue;
+            }
+            flat.insert(item.to_string(), item);
+        }
+    }
+
+    JsonValue::Array(flat.into_values().collect())
+}
+
+/// Turn a list or CSV string into a sorted, de-duplicated list of normalized values.
+fn canonicalize_list(value: &JsonValue, is_key: bool) -> JsonValue {
+    let items: Vec<JsonValue> = match value {
+        JsonValue::Array(a) => a.clone(),
+        JsonValue::String(s) if !s.starts_with('$') => s
+            .split(',')
+            .map(|item| JsonValue::String(item.trim().to_owned()))
+            .collect(),
+        other => return normalize_scalar(other, is_key),
+    };
+    let unique: BTreeMap<String, JsonValue> = items
+        .iter()
+        .map(|item| normalize_scalar(item, is_key))
+        .map(|item| (item.to_string(), item))
+        .collect();
+
+    JsonValue::Array(unique.into_values().collect())
+}
+
+/// Normalize numbers and ids. Floats without a fraction like `5.0` become
+/// integers. Integer strings like `"5"` of key fields become numbers.
+///
+/// Strings with leading zeros like `"007"` stay strings.
+fn normalize_scalar(value: &JsonValue, is_key: bool) -> JsonValue {
+    match value {
+        JsonValue::String(s) if is_key && is_integer(s) => match s.parse::<i64>() {
+            Ok(n) => JsonValue::Number(n.into()),
+            Err(_) => value.clone(),
+        },
+        JsonValue::Number(n) if n.is_f64() => match n.as_f64() {
+            Some(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                JsonValue::Number(Number::from(f as i64))
+            }
+            _ => value.clone(),
+        },
+        other => other.clone(),
+    }
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && (digits == "0" || !digits.starts_with('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn canonicalize_unwraps_single_child_operators() {
+        let result = canonicalize(&json!({ "_and": [{ "_or": [{ "id": { "_eq": 1 } }] }] }));
+        assert_eq!(result, json!({ "id": { "_eq": 1 } }));
+    }
+
+    #[test]
+    fn canonicalize_flattens_sorts_and_dedupes_branches() {
+        let a = canonicalize(&json!({ "_and": [
+            { "status": { "_eq": "draft" } },
+            { "_and": [{ "id": { "_lt": 5 } }, { "status": { "_eq": "draft" } }] }
+        ] }));
+        let b = canonicalize(
+            &json!({ "_and": [{ "id": { "_lt": 5 } }, { "status": { "_eq": "draft" } }] }),
+        );
+        assert_eq!(a, b);
+        assert_eq!(a["_and"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn canonicalize_normalizes_ids() {
+        let a = canonicalize(&json!({ "id": { "_in": "3,1, 2,1" } }));
+        assert_eq!(a, json!({ "id": { "_in": [1, 2, 3] } }));
+        let b = canonicalize(&json!({ "id": { "_eq": 5.0 } }));
+        assert_eq!(b, json!({ "id": { "_eq": 5 } }));
+    }
+
+    #[test]
+    fn canonicalize_keeps_strings_that_are_not_ids() {
+        let a = canonicalize(&json!({ "code": { "_eq": "007" }, "name": { "_contains": "12" } }));
+        assert_eq!(
+            a,
+            json!({ "code": { "_eq": "007" }, "name": { "_contains": "12" } })
+        );
+        let zip = canonicalize(&json!({ "zip": { "_eq": "1234" } }));
+        assert_eq!(zip, json!({ "zip": { "_eq": "1234" } }));
+        let author =
+            canonicalize(&json!({ "author": { "id": { "_eq": "5" } }, "team_id": { "_in": "7" } }));
+        assert_eq!(
+            author,
+            json!({ "author": { "id": { "_eq": 5 } }, "team_id": { "_in": [7] } })
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! Parse Directus Filter Rules into a structure we can reason about.
+//!
+//! A filter is a deeply nested JSON Object that chains rules with logical
+//! `_and` / `_or` operators. See the [Directus Documentation about Filter
+//! Rules](https://docs.directus.io/reference/filter-rules.html#filter-rules).
+//!
+//! The main purpose of this module is to compare filters semantically:
+//! Does one filter allow everything another filter allows? Filters can also
+//! be evaluated against items we've read from the database.
+use super::canonical::canonicalize;
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum FilterError {
+    #[error("Filter should be an object, got: {0}")]
+    NotAnObject(JsonValue),
+    #[error("`{0}` should contain an array, got: {1}")]
+    NotAnArray(String, JsonValue),
+}
+
+/// A parsed Directus filter.
+///
+/// * `All` -> No restriction at all, e.g. `{}` or `null`
+/// * `And` -> All children must match
+/// * `Or` -> At least one child must match
+/// * `Rule` -> A single operator applied to a (relational) field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    All,
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Rule(Rule),
+}
+
+/// A single filter rule like `{ "author": { "id": { "_eq": "$CURRENT_USER" } } }`
+///
+/// `path` holds the (relational) field names, here `["author", "id"]`,
+/// `operator` holds `_eq` and `value` holds `"$CURRENT_USER"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub path: Vec<String>,
+    pub operator: String,
+    pub value: JsonValue,
+}
+
+/// Operators that take a nested filter rather than a value.
+///
+/// `_some` is part of the path. `_none` negates its filter, so it's kept as
+/// a rule whose value is the nested filter.
+const NESTING_OPERATORS: &[&str] = &["_some", "_none"];
+
+impl TryFrom<&JsonValue> for Filter {
+    type Error = FilterError;
+
+    /// Parse the canonical form of `json`, see [`canonicalize`].
+    fn try_from(json: &JsonValue) -> Result<Self, Self::Error> {
+        parse(&canonicalize(json), &[])
+    }
+}
+
+fn parse(json: &JsonValue, path: &[String]) -> Result<Filter, FilterError> {
+    let object = match json {
+        JsonValue::Null if path.is_empty() => return Ok(Filter::All),
+        JsonValue::Object(o) => o,
+        _ => return Err(FilterError::NotAnObject(json.clone())),
+    };
+
+    let mut children = Vec::new();
+    for (key, value) in object {
+        match key.as_str() {
+            "_and" | "_or" => {
+                let items = value
+                    .as_array()
+                    .ok_or_else(|| FilterError::NotAnArray(key.to_owned(), value.clone()))?
+                    .iter()
+                    .map(|item| parse(item, path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                children.push(if key == "_and" {
+                    Filter::And(items)
+                } else {
+                    Filter::Or(items)
+                });
+            }
+            "_none" => {
+                parse(value, &[])?;
+                children.push(Filter::Rule(Rule {
+                    path: path.to_vec(),
+                    operator: key.to_owned(),
+                    value: value.clone(),
+                }));
+            }
+            operator if operator.starts_with('_') && !NESTING_OPERATORS.contains(&operator) => {
+                children.push(Filter::Rule(Rule {
+                    path: path.to_vec(),
+                    operator: operator.to_owned(),
+                    value: value.clone(),
+                }));
+            }
+            field => {
+                let mut path = path.to_vec();
+                path.push(field.to_owned());
+                children.push(parse(value, &path)?);
+            }
+        }
+    }
+
+    Ok(match children.len() {
+        0 => Filter::All,
+        1 => children.remove(0),
+        // Multiple keys in one object are implicitly joined by `_and`
+        _ => Filter::And(children),
+    })
+}
+
+impl Filter {
+    /// Check if everything matched by `self` is also matched by `other`.
+    ///
+    /// In other words: `other` is a superset of (or equal to) `self`.
+    ///
+    /// The check is conservative. If we can't prove that `self` implies
+    /// `other` we return `false`, although it may be true in reality.
+    /// Dynamic variables like `$CURRENT_USER` are only ever equal to themselves.
+    pub fn implies(&self, other: &Filter) -> bool {
+        match (self, other) {
+            (_, Filter::All) => true,
+            (_, Filter::And(others)) => others.iter().all(|o| self.implies(o)),
+            (Filter::Or(items), _) => items.iter().all(|i| i.implies(other)),
+            (Filter::All, Filter::Or(others)) => others.iter().any(|o| self.implies(o)),
+            (Filter::All, _) => false,
+            (Filter::And(items), _) => {
+                items.iter().any(|i| i.implies(other))
+                    || matches!(other, Filter::Or(others) if others.iter().any(|o| self.implies(o)))
+            }
+            (Filter::Rule(_), Filter::Or(others)) => others.iter().any(|o| self.implies(o)),
+            (Filter::Rule(a), Filter::Rule(b)) => a.implies(b),
+        }
+    }
+
+    /// Check if both filters match exactly the same items.
+    pub fn is_equivalent(&self, other: &Filter) -> bool {
+        self.implies(other) && other.implies(self)
+    }
+
+    /// Check if `item` matches the filter.
+    ///
+    /// Relations in `item` must be nested objects, e.g. `{ "folder": { "name": "HR" } }`.
+    /// Returns `None` if we can't tell, e.g. for dynamic variables like
+    /// `$CURRENT_USER`, unknown operators or fields missing in `item`.
+    pub fn evaluate(&self, item: &JsonValue) -> Option<bool> {
+        match self {
+            Filter::All => Some(true),
+            Filter::And(items) => {
+                let results: Vec<Option<bool>> = items.iter().map(|i| i.evaluate(item)).collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            Filter::Or(items) => {
+                let results: Vec<Option<bool>> = items.iter().map(|i| i.evaluate(item)).collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+            Filter::Rule(rule) => rule.evaluate(item),
+        }
+    }
+}
+
+/// Compare two filter Jsons semantically and fall back to their canonical Json
+/// if one of them isn't a valid filter.
+pub fn is_equivalent_json(a: &JsonValue, b: &JsonValue) -> bool {
+    match (Filter::try_from(a), Filter::try_from(b)) {
+        (Ok(a), Ok(b)) => a.is_equivalent(&b),
+        _ => canonicalize(a) == canonicalize(b),
+    }
+}
+
+impl Rule {
+    /// Check if everything matched by `self` is also matched by `other`.
+    fn implies(&self, other: &Rule) -> bool {
+        if self.path != other.path {
+            return false;
+        }
+        if self.operator == other.operator && self.value == other.value {
+            return true;
+        }
+
+        let (a, b) = (&self.value, &other.value);
+        match (self.operator.as_str(), other.operator.as_str()) {
+            ("_eq", "_in") => as_list(b).contains(a),
+            ("_eq", "_neq") => is_literal(a) && is_literal(b) && a != b,
+            ("_eq", "_nin") => is_literal(a) && as_list(b).iter().all(|v| is_literal(v) && v != a),
+            ("_eq", "_nnull") => is_literal(a) && !a.is_null(),
+            ("_eq", "_null") => a.is_null(),
+            ("_eq", op @ ("_gt" | "_gte" | "_lt" | "_lte")) => compare(a, op, b),
+            ("_eq", "_contains") => strings(a, b).is_some_and(|(a, b)| a.contains(b)),
+            ("_eq", "_starts_with") => strings(a, b).is_some_and(|(a, b)| a.starts_with(b)),
+            ("_eq", "_ends_with") => strings(a, b).is_some_and(|(a, b)| a.ends_with(b)),
+            ("_in", "_in") => as_list(a).iter().all(|v| as_list(b).contains(v)),
+            ("_in", "_eq") => {
+                let list = as_list(a);
+                !list.is_empty() && list.iter().all(|v| v == b)
+            }
+            ("_in", "_nin") => {
+                let list = as_list(b);
+                as_list(a)
+                    .iter()
+                    .all(|v| is_literal(v) && !list.contains(v))
+            }
+            ("_nin", "_nin") => as_list(b).iter().all(|v| as_list(a).contains(v)),
+            ("_nin", "_neq") => as_list(a).contains(b),
+            ("_neq", "_nin") => {
+                let list = as_list(b);
+                !list.is_empty() && list.iter().all(|v| v == a)
+            }
+            ("_gt", "_gt") | ("_gte", "_gte") | ("_gt", "_gte") => compare(a, "_gte", b),
+            ("_gte", "_gt") => compare(a, "_gt", b),
+            ("_lt", "_lt") | ("_lte", "_lte") | ("_lt", "_lte") => compare(a, "_lte", b),
+            ("_lte", "_lt") => compare(a, "_lt", b),
+            ("_contains", "_contains")
+            | ("_starts_with", "_contains")
+            | ("_ends_with", "_contains") => strings(a, b).is_some_and(|(a, b)| a.contains(b)),
+            ("_starts_with", "_starts_with") => {
+                strings(a, b).is_some_and(|(a, b)| a.starts_with(b))
+            }
+            ("_ends_with", "_ends_with") => strings(a, b).is_some_and(|(a, b)| a.ends_with(b)),
+            ("_null", "_null") | ("_nnull", "_nnull") | ("_empty", "_empty") => a == b,
+            // No related item matches `a`, so none matches anything narrower.
+            ("_none", "_none") => match (Filter::try_from(a), Filter::try_from(b)) {
+                (Ok(a), Ok(b)) => b.implies(&a),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Rule {
+    fn evaluate(&self, item: &JsonValue) -> Option<bool> {
+        let mut value = item;
+        for field in &self.path {
+            value = match value {
+                // A missing relation has no fields.
+                JsonValue::Null => break,
+                JsonValue::Object(object) => object.get(field)?,
+                _ => return None,
+            };
+        }
+        // Operators on a many-to-one field compare its key, e.g. `{"folder":{"_eq":"<id>"}}`.
+        if let JsonValue::Object(object) = value {
+            value = object.get("id").unwrap_or(value);
+        }
+
+        let expected = &self.value;
+        let is_dynamic = |v: &JsonValue| !is_literal(v);
+        if is_dynamic(expected) || as_list(expected).iter().any(is_dynamic) {
+            return None;
+        }
+        // Directus accepts flags as booleans and as strings like `"false"`.
+        let flag = match expected {
+            JsonValue::Bool(flag) => *flag,
+            JsonValue::String(flag) => flag != "false",
+            _ => true,
+        };
+        Some(match self.operator.as_str() {
+            "_eq" => loose_eq(value, expected),
+            "_neq" => !loose_eq(value, expected),
+            "_in" => as_list(expected).iter().any(|e| loose_eq(value, e)),
+            "_nin" => !as_list(expected).iter().any(|e| loose_eq(value, e)),
+            "_null" => value.is_null() == flag,
+            "_nnull" => value.is_null() != flag,
+            "_empty" => (value.is_null() || value == "") == flag,
+            "_nempty" => (value.is_null() || value == "") != flag,
+            op @ ("_gt" | "_gte" | "_lt" | "_lte") => compare(value, op, expected),
+            "_contains" => strings(value, expected).is_some_and(|(a, b)| a.contains(b)),
+            "_ncontains" => !strings(value, expected).is_some_and(|(a, b)| a.contains(b)),
+            "_starts_with" => strings(value, expected).is_some_and(|(a, b)| a.starts_with(b)),
+            "_ends_with" => strings(value, expected).is_some_and(|(a, b)| a.ends_with(b)),
+            _ => return None,
+        })
+    }
+}
+
+/// Compare scalars like Directus does, i.e. `5` equals `"5"`.
+fn loose_eq(a: &JsonValue, b: &JsonValue) -> bool {
+    let text = |v: &JsonValue| match v {
+        JsonValue::String(s) => Some(s.to_owned()),
+        JsonValue::Number(n) => Some(n.to_string()),
+        JsonValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    };
+    match (text(a), text(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Check if a value is a plain literal and not a dynamic variable like `$NOW`.
+fn is_literal(value: &JsonValue) -> bool {
+    !matches!(value, JsonValue::String(s) if s.starts_with('$'))
+}
+
+/// Directus accepts both arrays and CSV strings for `_in` and `_nin`.
+fn as_list(value: &JsonValue) -> Vec<JsonValue> {
+    match value {
+        JsonValue::Array(a) => a.clone(),
+        JsonValue::String(s) => s
+            .split(',')
+            .map(|item| JsonValue::String(item.trim().to_owned()))
+            .collect(),
+        other => vec![other.clone()],
+    }
+}
+
+fn strings<'a>(a: &'a JsonValue, b: &'a JsonValue) -> Option<(&'a str, &'a str)> {
+    match (a, b) {
+        (JsonValue::String(a), JsonValue::String(b)) if is_literal_str(a) && is_literal_str(b) => {
+            Some((a, b))
+        }
+        _ => None,
+    }
+}
+
+fn is_literal_str(s: &str) -> bool {
+    !s.starts_with('$')
+}
+
+/// Compare two numeric values with a Directus operator, e.g. `a _gte b`.
+fn compare(a: &JsonValue, operator: &str, b: &JsonValue) -> bool {
+    let (a, b) = match (as_number(a), as_number(b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+    match operator {
+        "_gt" => a > b,
+        "_gte" => a >= b,
+        "_lt" => a < b,
+        "_lte" => a <= b,
+        _ => false,
+    }
+}
+
+fn as_number(value: &JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(json: JsonValue) -> Filter {
+        Filter::try_from(&json).unwrap()
+    }
+
+    #[test]
+    fn parse_returns_all_for_empty_filters() {
+        assert_eq!(filter(json!({})), Filter::All);
+        assert_eq!(filter(JsonValue::Null), Filter::All);
+    }
+
+    #[test]
+    fn parse_resolves_relational_paths() {
+        let result = filter(json!({ "author": { "id": { "_eq": "$CURRENT_USER" } } }));
+        assert_eq!(
+            result,
+            Filter::Rule(Rule {
+                path: vec!["author".to_owned(), "id".to_owned()],
+                operator: "_eq".to_owned(),
+                value: json!("$CURRENT_USER"),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_fails_on_invalid_filters() {
+        let result = Filter::try_from(&json!({ "_and": { "id": { "_eq": 1 } } }));
+        assert!(matches!(result, Err(FilterError::NotAnArray(_, _))));
+    }
+
+    #[test]
+    fn reordered_and_is_equivalent() {
+        let a = filter(
+            json!({ "_and": [{ "id": { "_lt": 50 } }, { "status": { "_eq": "published" } }] }),
+        );
+        let b = filter(
+            json!({ "_and": [{ "status": { "_eq": "published" } }, { "id": { "_lt": 50 } }] }),
+        );
+        assert!(a.is_equivalent(&b));
+    }
+
+    #[test]
+    fn eq_and_in_with_one_value_are_equivalent() {
+        let a = filter(json!({ "status": { "_eq": "draft" } }));
+        let b = filter(json!({ "status": { "_in": ["draft"] } }));
+        assert!(a.is_equivalent(&b));
+    }
+
+    #[test]
+    fn ids_as_strings_and_numbers_are_equivalent() {
+        let a = filter(json!({ "id": { "_eq": "5" } }));
+        let b = filter(json!({ "id": { "_in": "5" } }));
+        assert!(a.is_equivalent(&b));
+    }
+
+    #[test]
+    fn narrower_filter_implies_wider_filter() {
+        let narrow =
+            filter(json!({ "_and": [{ "id": { "_lt": 10 } }, { "status": { "_eq": "draft" } }] }));
+        let wide = filter(json!({ "id": { "_lte": 50 } }));
+        assert!(narrow.implies(&wide));
+        assert!(!wide.implies(&narrow));
+    }
+
+    #[test]
+    fn none_implies_none_of_narrower_filters() {
+        let wide =
+            filter(json!({ "comments": { "_none": { "status": { "_in": ["spam", "hidden"] } } } }));
+        let narrow = filter(json!({ "comments": { "_none": { "status": { "_eq": "spam" } } } }));
+        assert!(wide.implies(&narrow));
+        assert!(!narrow.implies(&wide));
+
+        let both = filter(json!({ "comments": { "_none": {
+            "_and": [{ "status": { "_eq": "spam" } }, { "id": { "_lt": 5 } }]
+        } } }));
+        assert!(!both.implies(&narrow));
+        assert!(narrow.implies(&both));
+    }
+
+    #[test]
+    fn everything_implies_no_filter() {
+        let narrow = filter(json!({ "id": { "_lt": 10 } }));
+        assert!(narrow.implies(&Filter::All));
+        assert!(!Filter::All.implies(&narrow));
+    }
+
+    #[test]
+    fn or_branches_must_all_imply() {
+        let or = filter(
+            json!({ "_or": [{ "status": { "_eq": "draft" } }, { "status": { "_eq": "review" } }] }),
+        );
+        let wide = filter(json!({ "status": { "_in": ["draft", "review", "published"] } }));
+        assert!(or.implies(&wide));
+        assert!(!wide.implies(&or));
+    }
+
+    #[test]
+    fn evaluate_follows_relations_and_gives_up_on_variables() {
+        let item = json!({ "id": 1, "folder": { "id": "a", "parent": { "name": "HR" } } });
+        let hr = filter(json!({ "folder": { "parent": { "name": { "_eq": "HR" } } } }));
+        assert_eq!(hr.evaluate(&item), Some(true));
+        assert_eq!(
+            hr.evaluate(&json!({ "id": 2, "folder": null })),
+            Some(false)
+        );
+
+        let mine = filter(
+            json!({ "_or": [{ "uploaded_by": { "_eq": "$CURRENT_USER" } }, { "id": { "_in": "1,2" } }] }),
+        );
+        assert_eq!(mine.evaluate(&item), Some(true));
+        assert_eq!(mine.evaluate(&json!({ "id": 3, "uploaded_by": "x" })), None);
+    }
+
+    #[test]
+    fn evaluate_reads_null_flags_as_booleans_and_strings() {
+        let item = json!({ "id": 1, "deleted_at": null });
+        for (flag, expected) in [
+            (json!(true), true),
+            (json!("true"), true),
+            (json!(false), false),
+            (json!("false"), false),
+        ] {
+            let null = filter(json!({ "deleted_at": { "_null": flag } }));
+            assert_eq!(null.evaluate(&item), Some(expected));
+            let nnull = filter(json!({ "deleted_at": { "_nnull": flag } }));
+            assert_eq!(nnull.evaluate(&item), Some(!expected));
+        }
+    }
+
+    #[test]
+    fn dynamic_variables_are_only_equal_to_themselves() {
+        let a = filter(json!({ "user_created": { "_eq": "$CURRENT_USER" } }));
+        let b = filter(json!({ "user_created": { "_neq": "someone" } }));
+        assert!(!a.implies(&b));
+        assert!(a.implies(&a.clone()));
+    }
+}
@@ -1,48 +1,71 @@
-use crate::directus::Field;
-use crate::entities::directus_permissions;
+use crate::directus::{Field, PUBLIC_ROLE};
+use crate::egress::Egress;
+use crate::entities::{directus_permissions, directus_shares};
+use anyhow::Context;
+use filter::FilterError;
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::Graph;
 use petgraph::prelude::NodeIndex;
+use petgraph::visit::EdgeRef;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
+pub mod canonical;
+pub mod filter;
+
 pub fn build_graph(
     permissions: Vec<directus_permissions::Model>,
     fields: &[Field],
-) -> Graph<Node, EdgeType> {
+    shares: &[directus_shares::Model],
+    egress: &[Egress],
+) -> anyhow::Result<Graph<Node, EdgeType>> {
     let mut graph = Graph::new();
     let mut nodes: HashMap<Node, NodeIndex> = HashMap::new();
     let mut edges: HashSet<(NodeIndex, NodeIndex, EdgeType)> = HashSet::new();
 
-    for row in permissions.into_iter() {
-        if let Some((new_nodes, new_edges)) = parse_row(row, fields) {
-            for node in new_nodes {
-                get_or_create_node(node, &mut nodes, &mut graph);
-            }
-            for (source_node, target_node, edge_type) in new_edges {
-                let source_index = get_or_create_node(source_node, &mut nodes, &mut graph);
-                let target_index = get_or_create_node(target_node, &mut nodes, &mut graph);
-                add_unique_edge(
-                    (source_index, target_index, edge_type),
-                    &mut edges,
-                    Some(&mut graph),
-                );
-            }
+    let mut parsed: Vec<(Nodes, Edges)> = shares
+        .iter()
+        .filter_map(|share| parse_share(share, &permissions, fields))
+        .chain(egress.iter().filter_map(|e| parse_egress(e, fields)))
+        .collect();
+    for row in permissions {
+        parsed.extend(parse_row(row, fields)?);
+    }
+
+    for (new_nodes, new_edges) in parsed {
+        for node in new_nodes {
+            get_or_create_node(node, &mut nodes, &mut graph);
+        }
+        for (source_node, target_node, edge_type) in new_edges {
+            let source_index = get_or_create_node(source_node, &mut nodes, &mut graph);
+            let target_index = get_or_create_node(target_node, &mut nodes, &mut graph);
+            add_unique_edge(
+                (source_index, target_index, edge_type),
+                &mut edges,
+                Some(&mut graph),
+            );
         }
     }
-    graph
+    Ok(graph)
 }
 
-fn parse_row(row: directus_permissions::Model, all_fields: &[Field]) -> NodesAndEdges {
-    let fields = row.fields?;
+/// Fail on rows whose `permissions` aren't a valid filter.
+fn parse_row(
+    row: directus_permissions::Model,
+    all_fields: &[Field],
+) -> anyhow::Result<NodesAndEdges> {
+    let fields = match row.fields {
+        Some(fields) => fields,
+        None => return Ok(None),
+    };
     let mut nodes = HashSet::new();
     let mut edges: Edges = HashSet::new();
 
     // Now we create the default Nodes that each row has
-    let subject_node = Node::Subject(row.role.unwrap_or("Public".to_owned()));
+    let subject_node = Node::Subject(row.role.unwrap_or(PUBLIC_ROLE.to_owned()));
     nodes.insert(subject_node.clone());
     let action_node = Node::Action(ActionType::from(row.action));
     nodes.insert(action_node.clone());
@@ -50,27 +73,89 @@ fn parse_row(row: directus_permissions::Model, all_fields: &[Field]) -> NodesAnd
     // Adding an edge between Subject and Action
     edges.insert((subject_node, action_node.clone(), EdgeType::Allow));
 
-    // TODO: Create Caveats
-    // if row.permissions.is_some() {
-    //     let mut caveat_nodes: HashMap<Node, NodeIndex> = HashMap::new();
-    //     let mut caveat_edges: HashSet<(NodeIndex, NodeIndex, Edge)> = HashSet::new();
-    //     let caveat_last_children = caveats_from_json(
-    //         &mut caveat_nodes,
-    //         &mut caveat_edges,
-    //         &row.permissions.unwrap(),
-    //         NodeIndex::new(0),
-    //     );
-    // }
-
-    // Creating all resource nodes and connecting them to actions
-    // FIXME: This should AKCTUALLY! connect to the caveats, which we should have
-    // created first.
+    // Creating caveats from the `permissions` filter. Resources are connected
+    // to the last caveats of the chain or straight to the action.
+    let leaves = match &row.permissions {
+        Some(json) => caveats_from_json(&canonical::canonicalize(json), &action_node, &mut edges)
+            .with_context(|| {
+            format!(
+                "Invalid permissions in directus_permissions row {} ({}, {})",
+                row.id, row.collection, action_node
+            )
+        })?,
+        None => vec![action_node],
+    };
+    for (source, target, _) in edges.iter() {
+        nodes.insert(source.clone());
+        nodes.insert(target.clone());
+    }
+
+    // Creating all resource nodes and connecting them to the caveats
     let resources = create_resource_nodes(&fields, &row.collection, all_fields);
     resources.iter().for_each(|res| {
         nodes.insert(res.clone());
-        edges.insert((action_node.clone(), res.to_owned(), EdgeType::Allow));
+        for leaf in &leaves {
+            edges.insert((leaf.clone(), res.to_owned(), EdgeType::Allow));
+        }
+    });
+
+    Ok(Some((nodes, edges)))
+}
+
+/// Connect a share to the fields its role can read in the shared collection.
+///
+/// A share only exposes a single item, so it doesn't pass through the role's
+/// Action and Caveat nodes, which apply to all items of a collection.
+/// Shares whose role can't read anything in the collection are skipped.
+fn parse_share(
+    share: &directus_shares::Model,
+    permissions: &[directus_permissions::Model],
+    all_fields: &[Field],
+) -> NodesAndEdges {
+    let collection = share.collection.as_ref()?;
+    let role = share.role.map(|id| id.to_string());
+    let share_node = Node::Share(ShareLink {
+        id: share.id.to_string(),
+        collection: collection.to_owned(),
+        item: share.item.clone().unwrap_or_default(),
     });
 
+    let mut nodes = HashSet::from([share_node.clone()]);
+    let mut edges: Edges = HashSet::new();
+    for row in permissions
+        .iter()
+        .filter(|row| row.role == role && &row.collection == collection && row.action == "read")
+    {
+        let fields = row.fields.as_deref().unwrap_or("*");
+        for resource in create_resource_nodes(fields, collection, all_fields) {
+            nodes.insert(resource.clone());
+            edges.insert((share_node.clone(), resource, EdgeType::Allow));
+        }
+    }
+
+    if edges.is_empty() {
+        return None;
+    }
+    Some((nodes, edges))
+}
+
+/// Connect a host to all fields of the collections it receives.
+///
+/// Egress without payload only sends keys or events and is skipped.
+fn parse_egress(egress: &Egress, all_fields: &[Field]) -> NodesAndEdges {
+    if !egress.payload {
+        return None;
+    }
+    let egress_node = Node::Egress(egress.host.to_owned());
+    let mut nodes = HashSet::from([egress_node.clone()]);
+    let mut edges: Edges = HashSet::new();
+    for collection in &egress.collections {
+        for resource in create_resource_nodes("*", collection, all_fields) {
+            nodes.insert(resource.clone());
+            edges.insert((egress_node.clone(), resource, EdgeType::Allow));
+        }
+    }
+
     Some((nodes, edges))
 }
 
@@ -105,56 +190,58 @@ fn create_resource_nodes(fields: &str, collection: &str, all_fields: &[Field]) -
 ///
 /// Recursively resolve objects into a graph structure.
 /// The rules are:
-/// * Children of an `_and` object will be connected in a chained
+/// * Children of an `_and` object will be connected in a chain
 /// * Children of an `_or` object will be siblings
 ///
-/// Mutates `nodes` and `edges` in place.
-/// Returns a vector of sinks or leaves
+/// Adds all new edges to `edges` in place.
+/// Returns a vector of sinks or leaves. An empty filter returns `parent`.
 ///
-/// # Panics
-/// If the Json structure doesn't comply with Directus Filter Syntax
+/// Fails if the Json structure doesn't comply with Directus Filter Syntax
 /// as outlined in the [Directus Documentation about Filter Rules](https://docs.directus.io/reference/filter-rules.html#filter-rules)
-///
-
-// fn caveats_from_json(
-//     nodes: &mut HashMap<Node, NodeIndex>,
-//     edges: &mut HashSet<(NodeIndex, NodeIndex, Edge)>,
-//     json: &JsonValue,
-//     parent: NodeIndex,
-// ) -> Vec<NodeIndex> {
-//     match json {
-//         JsonValue::Object(o) => {
-//             let mut leaf_nodes = Vec::new();
-//             if let Some(_and_value) = o.get("_and") {
-//                 let mut last_node = parent;
-//                 for sub_value in _and_value
-//                     .as_array()
-//                     .expect("_and value should be an array")
-//                 {
-//                     let sub_leaf_nodes = caveats_from_json(nodes, edges, sub_value, last_node);
-//                     if let Some(last_sub_leaf_node) = sub_leaf_nodes.last() {
-//                         last_node = *last_sub_leaf_node;
-//                     }
-//                     leaf_nodes.extend(sub_leaf_nodes);
-//                 }
-//             } else if let Some(_or_value) = o.get("_or") {
-//                 for sub_value in _or_value.as_array().expect("_or value should be an array") {
-//                     let sub_leaf_nodes = caveats_from_json(nodes, edges, sub_value, parent);
-//                     leaf_nodes.extend(sub_leaf_nodes);
-//                 }
-//             } else {
-//                 let caveat_data = Node::Caveat(JsonValue::Object(o.clone()));
-//                 let caveat_node = get_or_create_node(nodes, caveat_data);
-//                 add_unique_edge((parent, caveat_node, Edge::Allow), edges);
-//                 leaf_nodes.push(caveat_node);
-//             }
-//             leaf_nodes
-//         }
-//         _ => {
-//             panic!("Json should be an object, got: {}", json);
-//         }
-//     }
-// }
+fn caveats_from_json(
+    json: &JsonValue,
+    parent: &Node,
+    edges: &mut Edges,
+) -> Result<Vec<Node>, FilterError> {
+    let as_array = |key: &str, value: &JsonValue| {
+        value
+            .as_array()
+            .cloned()
+            .ok_or_else(|| FilterError::NotAnArray(key.to_owned(), value.clone()))
+    };
+    match json {
+        JsonValue::Null => Ok(vec![parent.clone()]),
+        JsonValue::Object(o) if o.is_empty() => Ok(vec![parent.clone()]),
+        JsonValue::Object(o) => {
+            if let Some(and_value) = o.get("_and") {
+                let mut last_nodes = vec![parent.clone()];
+                for sub_value in as_array("_and", and_value)? {
+                    let mut next_nodes = Vec::new();
+                    for last_node in &last_nodes {
+                        for node in caveats_from_json(&sub_value, last_node, edges)? {
+                            if !next_nodes.contains(&node) {
+                                next_nodes.push(node);
+                            }
+                        }
+                    }
+                    last_nodes = next_nodes;
+                }
+                Ok(last_nodes)
+            } else if let Some(or_value) = o.get("_or") {
+                let mut leaves = Vec::new();
+                for sub_value in as_array("_or", or_value)? {
+                    leaves.extend(caveats_from_json(&sub_value, parent, edges)?);
+                }
+                Ok(leaves)
+            } else {
+                let caveat_node = Node::Caveat(JsonValue::Object(o.clone()));
+                edges.insert((parent.clone(), caveat_node.clone(), EdgeType::Allow));
+                Ok(vec![caveat_node])
+            }
+        }
+        _ => Err(FilterError::NotAnObject(json.clone())),
+    }
+}
 
 // Helper function to add a unique edge to `edges` and optionally to `graph`
 fn add_unique_edge(
@@ -189,13 +276,17 @@ type NodesAndEdges = Option<(Nodes, Edges)>;
 ///
 /// The possibilities are:
 /// * Subject -> Role ID
+/// * Share -> A share link, which exposes a single item under a role
+/// * Egress -> A host that receives data from webhooks or flows
 /// * Action -> JsonValue of a directus_permissions.validation filter
 /// * Caveat -> JsonValue of a directus_permissions.permissions rule
 /// * Resource -> A precise field address represented by a collection
-///     and a field.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+///   and a field.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Node {
     Subject(String),
+    Share(ShareLink),
+    Egress(String),
     Action(ActionType),
     Caveat(JsonValue),
     Resource(Resource),
@@ -222,6 +313,14 @@ pub struct Resource {
     field: String,
 }
 
+/// A row of `directus_shares`, scoped to the `item` of `collection`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShareLink {
+    id: String,
+    collection: String,
+    item: String,
+}
+
 impl From<String> for ActionType {
     fn from(string: String) -> Self {
         match string.as_str() {
@@ -251,6 +350,8 @@ impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Node::Subject(ref s) => write!(f, "Subject({})", s),
+            Node::Egress(ref host) => write!(f, "Egress({})", host),
+            Node::Share(ref s) => write!(f, "Share({}:{}, id: \"{}\")", s.collection, s.item, s.id),
             Node::Action(ref a) => write!(f, "Action({})", a),
             Node::Caveat(ref jv) => write!(f, "Caveat({})", jv),
             Node::Resource(ref r) => write!(
@@ -281,16 +382,22 @@ impl Hash for Node {
                 "Subject".hash(state);
                 s.hash(state);
             }
+            Node::Share(s) => {
+                "Share".hash(state);
+                s.hash(state);
+            }
+            Node::Egress(host) => {
+                "Egress".hash(state);
+                host.hash(state);
+            }
             Node::Action(a) => {
                 "Action".hash(state);
                 a.hash(state);
             }
             Node::Caveat(c) => {
                 "Caveat".hash(state);
-                // FIXME: still find the best way handle this. Now we naively assume
-                // that the json will always be in the right order. (Although that might
-                // not be such a bad assumption in the case of Directus).
-                c.to_string().hash(state);
+                // Logically identical filters must hash identically.
+                canonical::canonicalize(c).to_string().hash(state);
             }
             Node::Resource(r) => {
                 "Resource".hash(state);
@@ -300,6 +407,24 @@ impl Hash for Node {
     }
 }
 
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Node::Subject(a), Node::Subject(b)) => a == b,
+            (Node::Share(a), Node::Share(b)) => a == b,
+            (Node::Egress(a), Node::Egress(b)) => a == b,
+            (Node::Action(a), Node::Action(b)) => a == b,
+            (Node::Caveat(a), Node::Caveat(b)) => {
+                canonical::canonicalize(a) == canonical::canonicalize(b)
+            }
+            (Node::Resource(a), Node::Resource(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Node {}
+
 impl Hash for EdgeType {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
@@ -325,6 +450,8 @@ impl<'a> fmt::Display for NodeWrapper<'a> {
             Node::Resource(r) => write!(f, "{}.{}", r.collection, r.field),
             Node::Action(a) => write!(f, "{}", a.to_string().to_uppercase()),
             Node::Subject(s) => write!(f, "{}", s),
+            Node::Egress(host) => write!(f, "EGRESS {}", host),
+            Node::Share(s) => write!(f, "SHARE {}:{}", s.collection, s.item),
             Node::Caveat(c) => write!(f, "{}", c),
         }
     }
@@ -351,5 +478,47 @@ impl GraphToString for Graph<Node, EdgeType> {
     }
 }
 
+/// Edges that differ between two graphs, like `READ -> articles.title`.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct GraphDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl GraphDiff {
+    /// Compare two graphs by the nodes of their edges.
+    pub fn new(before: &Graph<Node, EdgeType>, after: &Graph<Node, EdgeType>) -> Self {
+        let before = edge_set(before);
+        let after = edge_set(after);
+        let to_strings = |edges: Vec<&(&Node, &Node, EdgeType)>| {
+            let mut strings: Vec<String> = edges
+                .into_iter()
+                .map(|(source, target, edge)| {
+                    let (source, target) = (NodeWrapper(source), NodeWrapper(target));
+                    format!("{} -{}> {}", source, EdgeWrapper(edge), target)
+                })
+                .collect();
+            strings.sort();
+            strings
+        };
+
+        Self {
+            added: to_strings(after.difference(&before).collect()),
+            removed: to_strings(before.difference(&after).collect()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn edge_set(graph: &Graph<Node, EdgeType>) -> HashSet<(&Node, &Node, EdgeType)> {
+    graph
+        .edge_references()
+        .map(|edge| (&graph[edge.source()], &graph[edge.target()], *edge.weight()))
+        .collect()
+}
+
 #[cfg(test)]
 mod test;
@@ -17,9 +17,12 @@ fn caveats_from_json_returns_a_simple_rule() {
             }
         ]
     });
-    let result = caveats_from_json(&test_me);
-    println!("result {:#?}", result);
-    assert_eq!(result, vec!["base:*"])
+    let action = Node::Action(ActionType::Read);
+    let mut edges: Edges = HashSet::new();
+    let result = caveats_from_json(&test_me, &action, &mut edges).unwrap();
+    let caveat = Node::Caveat(test_me["_and"][0].clone());
+    assert_eq!(result, vec![caveat.clone()]);
+    assert_eq!(edges, HashSet::from([(action, caveat, EdgeType::Allow)]));
 }
 
 #[test]
@@ -69,7 +72,118 @@ fn caveats_from_json_returns_a_complex_rule() {
         }
         ]
     });
-    let result = caveats_from_json(&test_me);
-    println!("result {:#?}", result);
-    assert_eq!(result, vec!["base:*"])
+    let action = Node::Action(ActionType::Read);
+    let mut edges: Edges = HashSet::new();
+    let result = caveats_from_json(&test_me, &action, &mut edges).unwrap();
+    // Children of `_and` are chained, children of `_or` are siblings.
+    // Both `_or` siblings lead to the last `_and` child, which is the only leaf.
+    let last = Node::Caveat(serde_json::json!({ "name": { "_ncontains": "243" } }));
+    assert_eq!(result, vec![last.clone()]);
+    assert_eq!(edges.len(), 6);
+    assert_eq!(
+        edges
+            .iter()
+            .filter(|(_, target, _)| target == &last)
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn caveats_from_json_returns_parent_for_empty_rule() {
+    let action = Node::Action(ActionType::Create);
+    let mut edges: Edges = HashSet::new();
+    let result = caveats_from_json(&serde_json::json!({}), &action, &mut edges).unwrap();
+    assert_eq!(result, vec![action]);
+    assert!(edges.is_empty());
+}
+
+#[test]
+fn build_graph_reports_rows_with_malformed_filters() {
+    let row = directus_permissions::Model {
+        id: 42,
+        role: None,
+        collection: "customers".to_owned(),
+        action: "read".to_owned(),
+        permissions: Some(serde_json::json!({ "_or": { "name": { "_eq": "a" } } })),
+        validation: None,
+        presets: None,
+        fields: Some("name".to_owned()),
+    };
+    let error = build_graph(vec![row], &[], &[], &[]).unwrap_err();
+    assert!(error.to_string().contains("row 42"));
+}
+
+#[test]
+fn caveats_with_equal_filters_are_equal_nodes() {
+    let a = Node::Caveat(serde_json::json!({
+        "_and": [{ "id": { "_eq": "5" } }, { "status": { "_in": ["b", "a"] } }]
+    }));
+    let b = Node::Caveat(serde_json::json!({
+        "_and": [{ "status": { "_in": "a,b" } }, { "_and": [{ "id": { "_eq": 5 } }] }]
+    }));
+    assert_eq!(a, b);
+    assert_eq!(HashSet::from([a, b]).len(), 1);
+}
+
+#[test]
+fn shares_connect_only_to_readable_fields_of_their_collection() {
+    let row = |collection: &str, fields: &str| directus_permissions::Model {
+        id: 0,
+        role: None,
+        collection: collection.to_owned(),
+        action: "read".to_owned(),
+        permissions: None,
+        validation: None,
+        presets: None,
+        fields: Some(fields.to_owned()),
+    };
+    let share = directus_shares::Model {
+        id: sea_orm::prelude::Uuid::nil(),
+        name: None,
+        collection: Some("customers".to_owned()),
+        item: Some("1".to_owned()),
+        role: None,
+        password: None,
+        user_created: None,
+        date_created: None,
+        date_start: None,
+        date_end: None,
+        times_used: None,
+        max_uses: None,
+    };
+    let permissions = vec![row("customers", "name"), row("orders", "total")];
+    let (nodes, edges) = parse_share(&share, &permissions, &[]).unwrap();
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(edges.len(), 1);
+    let (source, target, _) = edges.into_iter().next().unwrap();
+    assert!(matches!(source, Node::Share(s) if s.item == "1"));
+    assert_eq!(
+        target,
+        Node::Resource(Resource {
+            collection: "customers".to_owned(),
+            field: "name".to_owned()
+        })
+    );
+}
+
+#[test]
+fn graph_diff_lists_added_and_removed_edges() {
+    let row = |fields: &str| directus_permissions::Model {
+        id: 1,
+        role: Some("editor".to_owned()),
+        collection: "customers".to_owned(),
+        action: "read".to_owned(),
+        permissions: None,
+        validation: None,
+        presets: None,
+        fields: Some(fields.to_owned()),
+    };
+    let before = build_graph(vec![row("name,email")], &[], &[], &[]).unwrap();
+    let after = build_graph(vec![row("name,phone")], &[], &[], &[]).unwrap();
+
+    let diff = GraphDiff::new(&before, &after);
+    assert_eq!(diff.added, vec!["READ -> customers.phone"]);
+    assert_eq!(diff.removed, vec!["READ -> customers.email"]);
+    assert!(GraphDiff::new(&after, &after).is_empty());
 }
@@ -0,0 +1,259 @@
+//! Who changed what, from `directus_activity` and `directus_revisions`.
+//!
+//! Permissions say which roles *can* change a field. The activity log says
+//! who actually did. Revisions' `delta` tells which fields an update touched.
+use crate::cli::{History, OutputFormat};
+use crate::directus::{self, User};
+use crate::dump::{Request, RequestEntity};
+use crate::entities::{directus_activity, directus_revisions};
+use crate::output;
+use chrono::Utc;
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter, QueryOrder};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Activity actions that change data. Others like `login` or `comment` don't.
+const ACTIONS: &[&str] = &["create", "update", "delete"];
+
+/// Keep `IN (...)` lists well below the limits of all database backends.
+const CHUNK_SIZE: usize = 1000;
+
+/// 🏡 Handle logic for the `history` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `history` specific options.
+pub async fn history_entrypoint(args: &HistoryOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let mut query = directus_activity::Entity::find()
+        .filter(directus_activity::Column::Action.is_in(ACTIONS.iter().copied()))
+        .filter(
+            args.resource
+                .collection_condition(directus_activity::Column::Collection),
+        )
+        .order_by_asc(directus_activity::Column::Timestamp);
+    if let Some(since) = args.since {
+        query = query.filter(directus_activity::Column::Timestamp.gte(Utc::now() - since));
+    }
+    let activities = query.all(&db).await?;
+
+    let mut revisions = Vec::new();
+    for chunk in activities.chunks(CHUNK_SIZE) {
+        revisions.extend(
+            directus_revisions::Entity::find()
+                .filter(directus_revisions::Column::Activity.is_in(chunk.iter().map(|a| a.id)))
+                .all(&db)
+                .await?,
+        );
+    }
+
+    let users = directus::fetch_users(&db).await?;
+    let role_names = directus::fetch_role_names(&db).await?;
+    let history = AccessHistory::new(&activities, &revisions, &users, &role_names, &args.resource);
+
+    output::print(&args.output, &history)
+}
+
+#[derive(Debug)]
+pub struct HistoryOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub resource: Request,
+    pub since: Option<chrono::Duration>,
+}
+
+impl From<History> for HistoryOptions {
+    fn from(history: History) -> Self {
+        HistoryOptions {
+            url: history.global_args.url,
+            output: history.global_args.output,
+            resource: RequestEntity::from(history.resource).into(),
+            since: history.since,
+        }
+    }
+}
+
+/// A change to an item.
+///
+/// `user` is the email of the user, `role` the user's *current* role.
+/// Both are empty for changes without a user, e.g. by flows or the API.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Change {
+    pub timestamp: String,
+    pub action: String,
+    pub collection: String,
+    pub item: String,
+    pub user: Option<String>,
+    pub role: Option<String>,
+    pub ip: Option<String>,
+    pub origin: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+/// All changes and who changed each field.
+///
+/// * `changed_by` - Keys are `collection.field`, values are users
+/// * `changes` - All matching changes, oldest first
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AccessHistory {
+    pub changed_by: BTreeMap<String, BTreeSet<String>>,
+    pub changes: Vec<Change>,
+}
+
+impl AccessHistory {
+    /// Join activities with their revisions and keep changes to `resource`.
+    ///
+    /// Deletes have no delta. They are kept, because they remove every field.
+    pub fn new(
+        activities: &[directus_activity::Model],
+        revisions: &[directus_revisions::Model],
+        users: &HashMap<String, User>,
+        role_names: &HashMap<String, String>,
+        resource: &Request,
+    ) -> Self {
+        let mut fields_by_activity: HashMap<i32, BTreeSet<String>> = HashMap::new();
+        for revision in revisions {
+            if let Some(serde_json::Value::Object(delta)) = &revision.delta {
+                fields_by_activity
+                    .entry(revision.activity)
+                    .or_default()
+                    .extend(delta.keys().cloned());
+            }
+        }
+
+        let mut changed_by: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut changes = Vec::new();
+        for activity in activities {
+            if !resource.matches_collection(&activity.collection) {
+                continue;
+            }
+            let fields: Vec<String> = fields_by_activity
+                .get(&activity.id)
+                .into_iter()
+                .flatten()
+                .filter(|field| resource.matches(&activity.collection, field))
+                .cloned()
+                .collect();
+            if fields.is_empty() && activity.action != "delete" && !resource.all_fields() {
+                continue;
+            }
+
+            let user = activity.user.and_then(|id| users.get(&id.to_string()));
+            let label = user.map(User::label);
+            for field in &fields {
+                changed_by
+                    .entry(format!("{}.{}", activity.collection, field))
+                    .or_default()
+                    .insert(label.clone().unwrap_or_else(|| "unknown".to_owned()));
+            }
+
+            changes.push(Change {
+                timestamp: activity.timestamp.to_rfc3339(),
+                action: activity.action.to_owned(),
+                collection: activity.collection.to_owned(),
+                item: activity.item.to_owned(),
+                user: label,
+                role: user.map(|u| directus::role_name(u.role.as_deref(), role_names)),
+                ip: activity.ip.clone(),
+                origin: activity.origin.clone(),
+                fields,
+            });
+        }
+
+        Self {
+            changed_by,
+            changes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use sea_orm::prelude::Uuid;
+    use sea_orm::{DbBackend, QueryTrait};
+    use serde_json::json;
+
+    fn activity(id: i32, action: &str) -> directus_activity::Model {
+        directus_activity::Model {
+            id,
+            action: action.to_owned(),
+            user: Some(Uuid::nil()),
+            timestamp: DateTime::parse_from_rfc3339("2023-06-01T00:00:00Z").unwrap(),
+            ip: Some("127.0.0.1".to_owned()),
+            user_agent: None,
+            collection: "directus_users".to_owned(),
+            item: "1".to_owned(),
+            comment: None,
+            origin: None,
+        }
+    }
+
+    fn revision(activity: i32, delta: serde_json::Value) -> directus_revisions::Model {
+        directus_revisions::Model {
+            id: activity,
+            activity,
+            collection: "directus_users".to_owned(),
+            item: "1".to_owned(),
+            data: None,
+            delta: Some(delta),
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn new_lists_who_changed_a_field() {
+        let activities = vec![
+            activity(1, "update"),
+            activity(2, "update"),
+            activity(3, "delete"),
+        ];
+        let revisions = vec![
+            revision(1, json!({ "email": "new@example.com" })),
+            revision(2, json!({ "first_name": "Jane" })),
+        ];
+        let users = HashMap::from([(
+            Uuid::nil().to_string(),
+            User {
+                id: Uuid::nil().to_string(),
+                email: Some("admin@example.com".to_owned()),
+                role: Some("r1".to_owned()),
+            },
+        )]);
+        let role_names = HashMap::from([("r1".to_owned(), "Administrator".to_owned())]);
+        let resource: Request = RequestEntity::from("directus_users.email".to_owned()).into();
+
+        let history = AccessHistory::new(&activities, &revisions, &users, &role_names, &resource);
+        assert_eq!(
+            history
+                .changes
+                .iter()
+                .map(|c| c.action.as_str())
+                .collect::<Vec<_>>(),
+            vec!["update", "delete"]
+        );
+        assert_eq!(history.changes[0].fields, vec!["email"]);
+        assert_eq!(history.changes[0].role.as_deref(), Some("Administrator"));
+        assert_eq!(
+            history.changed_by["directus_users.email"],
+            BTreeSet::from(["admin@example.com".to_owned()])
+        );
+    }
+
+    #[test]
+    fn collection_is_filtered_in_sql() {
+        let sql = |resource: &str| {
+            let resource: Request = RequestEntity::from(resource.to_owned()).into();
+            directus_activity::Entity::find()
+                .filter(resource.collection_condition(directus_activity::Column::Collection))
+                .build(DbBackend::Postgres)
+                .to_string()
+        };
+        assert!(sql("directus_users.email").contains(r#""collection" = 'directus_users'"#));
+        assert!(sql("directus_*").contains(r#""collection" LIKE 'directus_%'"#));
+        assert!(!sql("*").contains('\''));
+    }
+}
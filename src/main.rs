@@ -1,20 +1,68 @@
+use crate::classify::{classify_entrypoint, ClassifyOptions};
 use crate::dump::{dump_entrypoint, DumpOptions};
-use anyhow;
+use crate::egress::{egress_entrypoint, EgressOptions};
+use crate::erase::{erase_entrypoint, EraseOptions};
+use crate::export::{export_entrypoint, ExportOptions};
+use crate::files::{files_entrypoint, FilesOptions};
+use crate::flows::{flows_entrypoint, FlowsOptions};
+use crate::history::{history_entrypoint, HistoryOptions};
+use crate::manifest::{manifest_entrypoint, ManifestOptions};
+use crate::overlaps::{overlaps_entrypoint, OverlapsOptions};
+use crate::panels::{panels_entrypoint, PanelsOptions};
+use crate::presets::{presets_entrypoint, PresetsOptions};
+use crate::promote::{promote_entrypoint, PromoteOptions};
+use crate::public::{public_entrypoint, PublicOptions};
+use crate::replace::{check_entrypoint, replace_entrypoint, ReplaceOptions};
+use crate::retention::{retention_entrypoint, RetentionOptions};
+use crate::roles::{role_entrypoint, RoleOptions};
+use crate::ropa::{ropa_entrypoint, RopaOptions};
+use crate::seed::{seed_entrypoint, SeedOptions};
+use crate::sessions::{sessions_entrypoint, SessionsOptions};
+use crate::shares::{shares_entrypoint, SharesOptions};
+use crate::templates::{patch_entrypoint, template_entrypoint, PatchOptions, TemplateOptions};
+use crate::tokens::{tokens_entrypoint, TokensOptions};
+use crate::unused::{unused_entrypoint, UnusedOptions};
 use clap::Parser;
 use cli::{Cli, Command};
 
 use futures::executor::block_on;
 
+pub mod classify;
 pub mod cli;
 pub mod config;
 pub mod directus;
 pub mod dump;
+pub mod egress;
 pub mod entities;
+pub mod erase;
+pub mod export;
+pub mod files;
+pub mod flows;
 pub mod graph;
+pub mod history;
 pub mod manifest;
+pub mod output;
+pub mod overlaps;
+pub mod panels;
+pub mod presets;
+pub mod promote;
+pub mod public;
+pub mod replace;
+pub mod retention;
 pub mod reversed_permissions;
+pub mod roles;
+pub mod ropa;
+pub mod seed;
+pub mod sessions;
+pub mod shares;
+pub mod templates;
+#[cfg(test)]
+mod test_support;
+pub mod tokens;
+pub mod unused;
 pub mod utils;
 pub mod wildcard;
+pub mod zip;
 
 fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
@@ -27,7 +75,81 @@ fn main() -> anyhow::Result<()> {
                 panic!("{}", err);
             }
         }
-        Command::Replace(_replace) => {}
+        Command::Replace(args) => {
+            block_on(replace_entrypoint(&ReplaceOptions::from(args)))?;
+        }
+        Command::Check(args) => {
+            block_on(check_entrypoint(&ReplaceOptions::from(args)))?;
+        }
+        Command::Overlaps(args) => {
+            block_on(overlaps_entrypoint(&OverlapsOptions::from(args)))?;
+        }
+        Command::Shares(args) => {
+            block_on(shares_entrypoint(&SharesOptions::from(args)))?;
+        }
+        Command::Sessions(args) => {
+            block_on(sessions_entrypoint(&SessionsOptions::from(args)))?;
+        }
+        Command::Tokens(args) => {
+            block_on(tokens_entrypoint(&TokensOptions::from(args)))?;
+        }
+        Command::Presets(args) => {
+            block_on(presets_entrypoint(&PresetsOptions::from(args)))?;
+        }
+        Command::Panels(args) => {
+            block_on(panels_entrypoint(&PanelsOptions::from(args)))?;
+        }
+        Command::Files(args) => {
+            block_on(files_entrypoint(&FilesOptions::from(args)))?;
+        }
+        Command::Public(args) => {
+            block_on(public_entrypoint(&PublicOptions::from(args)))?;
+        }
+        Command::Patch(args) => {
+            block_on(patch_entrypoint(&PatchOptions::from(args)))?;
+        }
+        Command::Template(args) => {
+            block_on(template_entrypoint(&TemplateOptions::from(args)))?;
+        }
+        Command::Seed(args) => {
+            block_on(seed_entrypoint(&SeedOptions::from(args)))?;
+        }
+        Command::Role(args) => {
+            block_on(role_entrypoint(&RoleOptions::from(args)))?;
+        }
+        Command::Promote(args) => {
+            block_on(promote_entrypoint(&PromoteOptions::from(args)))?;
+        }
+        Command::History(args) => {
+            block_on(history_entrypoint(&HistoryOptions::from(args)))?;
+        }
+        Command::Unused(args) => {
+            block_on(unused_entrypoint(&UnusedOptions::from(args)))?;
+        }
+        Command::Flows(args) => {
+            block_on(flows_entrypoint(&FlowsOptions::from(args)))?;
+        }
+        Command::Egress(args) => {
+            block_on(egress_entrypoint(&EgressOptions::from(args)))?;
+        }
+        Command::Classify(args) => {
+            block_on(classify_entrypoint(&ClassifyOptions::from(args)))?;
+        }
+        Command::Ropa(args) => {
+            block_on(ropa_entrypoint(&RopaOptions::from(args)))?;
+        }
+        Command::Export(args) => {
+            block_on(export_entrypoint(&ExportOptions::try_from(args)?))?;
+        }
+        Command::Erase(args) => {
+            block_on(erase_entrypoint(&EraseOptions::try_from(args)?))?;
+        }
+        Command::Retention(args) => {
+            block_on(retention_entrypoint(&RetentionOptions::from(args)))?;
+        }
+        Command::Manifest(args) => {
+            manifest_entrypoint(&ManifestOptions::from(args))?;
+        }
     }
     Ok(())
 }
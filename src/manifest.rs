@@ -1,16 +1,262 @@
-use cargo_metadata::MetadataCommand;
+use crate::cli::{ManifestCommand, OutputFormat};
+use crate::output;
+use crate::presets::Preset;
+use crate::reversed_permissions::CollectionRules;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value as YamlValue};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// GDPR Manifest Version, embedded from Cargo.toml by `build.rs`.
+pub const VERSION: &str = env!("GDPR_MANIFEST_VERSION");
+
+/// JSON Schema of the current manifest format.
+pub const SCHEMA: &str = include_str!("../docs/public/manifest.schema.json");
 
 /// Get GDPR Manifest Version from Cargo.toml
 pub fn get_version() -> String {
-    let metadata = MetadataCommand::new().exec().unwrap();
-    let manifest_version = metadata
-        .root_package()
-        .unwrap()
-        .metadata
-        .get("manifest_version")
-        .expect("manifest_version not found in Cargo.toml")
-        .as_str()
-        .expect("manifest_version is not a string");
-
-    return manifest_version.to_owned();
+    VERSION.to_owned()
+}
+
+/// 🏡 Handle logic for the `manifest` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `manifest` specific options.
+pub fn manifest_entrypoint(args: &ManifestOptions) -> anyhow::Result<()> {
+    match args {
+        ManifestOptions::Migrate { file, output } => {
+            let manifest = Manifest::read_and_migrate(file.as_deref())?;
+            output::print(output, &manifest)
+        }
+        ManifestOptions::Schema => {
+            println!("{}", SCHEMA);
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ManifestOptions {
+    Migrate {
+        file: Option<PathBuf>,
+        output: OutputFormat,
+    },
+    Schema,
+}
+
+impl From<ManifestCommand> for ManifestOptions {
+    fn from(command: ManifestCommand) -> Self {
+        match command {
+            ManifestCommand::Migrate(migrate) => ManifestOptions::Migrate {
+                file: migrate.file,
+                output: migrate.output,
+            },
+            ManifestCommand::Schema => ManifestOptions::Schema,
+        }
+    }
+}
+
+/// Upgrade a manifest from one version to the next.
+type Migration = fn(YamlValue) -> anyhow::Result<YamlValue>;
+
+/// All known upgrades as `(from, to, migration)`, oldest first.
+///
+/// Never change a released entry. Add a new one when the format changes.
+const MIGRATIONS: &[(&str, &str, Migration)] = &[
+    ("0.5.0/pre-alpha", "0.6.0/pre-alpha", nest_collections),
+    ("0.6.0/pre-alpha", "0.7.0/pre-alpha", add_presets),
+];
+
+/// 0.5.0 -> 0.6.0: Collections moved from the top level to `collections`
+/// and `globs` was added.
+fn nest_collections(value: YamlValue) -> anyhow::Result<YamlValue> {
+    let mapping = match value {
+        YamlValue::Mapping(mapping) => mapping,
+        _ => anyhow::bail!("Manifest is not an object"),
+    };
+    let mut collections = Mapping::new();
+    for (key, rules) in mapping {
+        if key.as_str() != Some("version") {
+            collections.insert(key, rules);
+        }
+    }
+
+    let mut migrated = Mapping::new();
+    migrated.insert("version".into(), "0.6.0/pre-alpha".into());
+    migrated.insert("collections".into(), YamlValue::Mapping(collections));
+    Ok(YamlValue::Mapping(migrated))
+}
+
+/// 0.6.0 -> 0.7.0: `presets` was added, both to action rules and as a list
+/// of `directus_presets`. Both are optional and left alone by `replace` if
+/// missing, so only the version changes.
+fn add_presets(value: YamlValue) -> anyhow::Result<YamlValue> {
+    let mut mapping = match value {
+        YamlValue::Mapping(mapping) => mapping,
+        _ => anyhow::bail!("Manifest is not an object"),
+    };
+    mapping.insert("version".into(), "0.7.0/pre-alpha".into());
+    Ok(YamlValue::Mapping(mapping))
+}
+
+/// Apply all migrations from the manifest's version up to [`VERSION`].
+///
+/// Returns the manifest unchanged if it already has the current version.
+pub fn migrate(mut value: YamlValue) -> anyhow::Result<YamlValue> {
+    let mut version = read_version(&value)?;
+    while version != VERSION {
+        let (_, to, migration) = MIGRATIONS
+            .iter()
+            .find(|(from, _, _)| *from == version)
+            .with_context(|| {
+                format!(
+                    "Manifest version `{}` is not supported. Expected `{}`.",
+                    version, VERSION
+                )
+            })?;
+        value = migration(value)
+            .with_context(|| format!("Can't migrate manifest from `{}` to `{}`", version, to))?;
+        version = to.to_string();
+    }
+    Ok(value)
+}
+
+fn read_version(value: &YamlValue) -> anyhow::Result<String> {
+    value
+        .get("version")
+        .and_then(|v| v.as_str())
+        .map(str::to_owned)
+        .context("Manifest has no `version`")
+}
+
+fn read_content(file: Option<&Path>) -> anyhow::Result<String> {
+    Ok(match file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read manifest {}", path.display()))?,
+        None => {
+            let mut buffer = String::new();
+            std::io::stdin().read_to_string(&mut buffer)?;
+            buffer
+        }
+    })
+}
+
+/// A GDPR Manifest holds all permission rules of a Directus project.
+///
+/// Field and role lists may be compressed to globs like `address_*`.
+/// `globs` records what each glob matched when the manifest was written.
+/// `presets` are the presets and bookmarks of roles and of everybody. `None`
+/// leaves them alone, an empty list means there are none.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub version: String,
+    pub collections: CollectionRules,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presets: Option<Vec<Preset>>,
+    #[serde(default, skip_serializing_if = "Globs::is_empty")]
+    pub globs: Globs,
+}
+
+/// The expanded lists of all globs in a manifest.
+///
+/// * `fields` - Keys are `collection.glob`, e.g. `customers.address_*`
+/// * `roles` - Keys are role globs, e.g. `editor_*`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Globs {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, Vec<String>>,
+}
+
+impl Globs {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.roles.is_empty()
+    }
+}
+
+impl Manifest {
+    pub fn new(collections: CollectionRules, globs: Globs) -> Self {
+        Self {
+            version: get_version(),
+            collections,
+            presets: None,
+            globs,
+        }
+    }
+
+    /// Read a yaml or json manifest from `file` or from stdin.
+    pub fn read(file: Option<&Path>) -> anyhow::Result<Self> {
+        Self::parse(&read_content(file)?)
+    }
+
+    /// Parse a manifest of the current version.
+    ///
+    /// Older versions are rejected with a hint to `gdpr manifest migrate`,
+    /// so that committed manifests are upgraded explicitly.
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let value: YamlValue = serde_yaml::from_str(content)?;
+        let version = read_version(&value)?;
+        if version != VERSION {
+            if MIGRATIONS.iter().any(|(from, _, _)| *from == version) {
+                anyhow::bail!(
+                    "Manifest version `{}` is outdated. Upgrade it to `{}` with `gdpr manifest migrate`.",
+                    version,
+                    VERSION
+                );
+            }
+            anyhow::bail!(
+                "Manifest version `{}` is not supported. Expected `{}`.",
+                version,
+                VERSION
+            );
+        }
+        Ok(serde_yaml::from_value(value)?)
+    }
+
+    /// Read a manifest of any known version and upgrade it to the current one.
+    pub fn read_and_migrate(file: Option<&Path>) -> anyhow::Result<Self> {
+        let value: YamlValue = serde_yaml::from_str(&read_content(file)?)?;
+        Ok(serde_yaml::from_value(migrate(value)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_matches_current_version() {
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(schema["properties"]["version"]["const"], VERSION);
+    }
+
+    #[test]
+    fn migrate_nests_collections_of_old_manifests() {
+        let old =
+            "version: 0.5.0/pre-alpha\ncustomers:\n  name:\n    read:\n      - roles: [editor]\n";
+        assert!(Manifest::parse(old)
+            .unwrap_err()
+            .to_string()
+            .contains("gdpr manifest migrate"));
+
+        let value = migrate(serde_yaml::from_str(old).unwrap()).unwrap();
+        let manifest: Manifest = serde_yaml::from_value(value).unwrap();
+        assert_eq!(manifest.version, VERSION);
+        assert_eq!(
+            manifest.collections["customers"]["name"].read[0].roles,
+            vec!["editor"]
+        );
+        assert!(manifest.globs.is_empty());
+    }
+
+    #[test]
+    fn migrate_rejects_unknown_versions() {
+        let value = serde_yaml::from_str("version: 0.1.0\n").unwrap();
+        assert!(migrate(value).is_err());
+    }
 }
@@ -0,0 +1,23 @@
+//! Print reports and dumps in the user's preferred output format.
+use crate::cli::OutputFormat;
+use serde::Serialize;
+
+/// Serialize `data` to json or yaml and print it to stdout.
+///
+/// # Arguments
+///
+/// * `output` - A reference to the user's preferred output format.
+/// * `data` - Anything serializable, e.g. a report.
+pub fn print<T: Serialize>(output: &OutputFormat, data: &T) -> anyhow::Result<()> {
+    let show = match output {
+        OutputFormat::Yaml => serde_yaml::to_string(data)?,
+        OutputFormat::Json => serde_json::to_string_pretty(data)?,
+        other => anyhow::bail!(
+            "Output format `{}` is not supported by this command. Choose either json or yaml.",
+            other
+        ),
+    };
+
+    println!("{:#}", show);
+    Ok(())
+}
@@ -0,0 +1,306 @@
+//! Find redundant and shadowed permission rules.
+//!
+//! Compare the rules of all roles for the same collection and action
+//! semantically, i.e. by parsing their filters instead of comparing strings.
+use crate::classify;
+use crate::cli::{OutputFormat, Overlaps};
+use crate::directus;
+use crate::dump::{Request, RequestEntity};
+use crate::entities::directus_permissions;
+use crate::graph::filter::Filter;
+use crate::output;
+use anyhow::Context;
+use sea_orm::{Database, EntityTrait};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// 🏡 Handle logic for the `overlaps` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `overlaps` specific options.
+pub async fn overlaps_entrypoint(args: &OverlapsOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+    let tagged;
+    let resources = match &args.tag {
+        Some(tag) => {
+            tagged =
+                classify::tagged_requests(&db, &args.resources, tag, args.rules.as_deref()).await?;
+            &tagged
+        }
+        None => &args.resources,
+    };
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let role_names = directus::fetch_role_names(&db).await?;
+    // Not all requests translate to SQL, e.g. `*.email`. Filter after parsing.
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+
+    let mut grants = permissions
+        .iter()
+        .map(|row| Grant::new(row, &fields, &role_names))
+        .collect::<anyhow::Result<Vec<Grant>>>()?;
+    grants.retain(|grant| grant.is_requested(resources));
+
+    output::print(&args.output, &find_overlaps(&grants))
+}
+
+#[derive(Debug)]
+pub struct OverlapsOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub resources: Vec<Request>,
+    pub tag: Option<String>,
+    pub rules: Option<PathBuf>,
+}
+
+impl From<Overlaps> for OverlapsOptions {
+    fn from(overlaps: Overlaps) -> Self {
+        OverlapsOptions {
+            url: overlaps.global_args.url,
+            output: overlaps.global_args.output,
+            resources: overlaps
+                .dump_args
+                .resource
+                .unwrap_or_default()
+                .iter()
+                .map(|req| RequestEntity::from(req.clone()).into())
+                .collect(),
+            tag: overlaps.dump_args.tag,
+            rules: overlaps.dump_args.rules,
+        }
+    }
+}
+
+/// A single `directus_permissions` row in a comparable shape.
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub role: String,
+    pub collection: String,
+    pub action: String,
+    pub fields: BTreeSet<String>,
+    pub permissions: Filter,
+    pub validation: Filter,
+}
+
+impl Grant {
+    /// Parse a row. A wildcard `*` in `fields` is expanded to all known `fields`.
+    pub fn new(
+        row: &directus_permissions::Model,
+        fields: &[directus::Field],
+        role_names: &HashMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        let row_fields = match row.fields.as_deref() {
+            Some("*") => fields
+                .iter()
+                .filter(|f| f.collection == row.collection)
+                .map(|f| f.field.to_owned())
+                .collect(),
+            Some(csv) => csv.split(',').map(|f| f.trim().to_owned()).collect(),
+            None => BTreeSet::new(),
+        };
+        let parse = |json: &Option<serde_json::Value>| {
+            json.as_ref()
+                .map_or(Ok(Filter::All), Filter::try_from)
+                .with_context(|| format!("Invalid filter in directus_permissions row {}", row.id))
+        };
+
+        Ok(Self {
+            role: directus::role_name(row.role.as_deref(), role_names),
+            collection: row.collection.to_owned(),
+            action: row.action.to_owned(),
+            fields: row_fields,
+            permissions: parse(&row.permissions)?,
+            validation: parse(&row.validation)?,
+        })
+    }
+
+    /// Check if any of `requests` covers the grant. No requests cover all.
+    ///
+    /// Grants without fields, e.g. for `delete`, cover all fields.
+    pub fn is_requested(&self, requests: &[Request]) -> bool {
+        requests.is_empty()
+            || requests.iter().any(|request| {
+                request.matches_collection(&self.collection)
+                    && (request.all_fields()
+                        || self.fields.is_empty()
+                        || self
+                            .fields
+                            .iter()
+                            .any(|field| request.matches(&self.collection, field)))
+            })
+    }
+
+    /// Check if `self` allows at least everything `other` allows.
+    pub fn covers(&self, other: &Grant) -> bool {
+        other.fields.is_subset(&self.fields)
+            && other.permissions.implies(&self.permissions)
+            && other.validation.implies(&self.validation)
+    }
+}
+
+/// How two or more grants for the same collection and action relate.
+///
+/// * `Equal` -> All `roles` have semantically equal rules
+/// * `Superset` -> The first role allows strictly more than the second
+/// * `Redundant` -> The same role has a rule that is covered by another one
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapKind {
+    Equal,
+    Superset,
+    Redundant,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Overlap {
+    pub collection: String,
+    pub action: String,
+    pub kind: OverlapKind,
+    pub roles: Vec<String>,
+}
+
+/// Compare all grants with each other and list equal and shadowed rules.
+pub fn find_overlaps(grants: &[Grant]) -> Vec<Overlap> {
+    let mut groups: BTreeMap<(&str, &str), Vec<&Grant>> = BTreeMap::new();
+    for grant in grants {
+        groups
+            .entry((&grant.collection, &grant.action))
+            .or_default()
+            .push(grant);
+    }
+
+    let mut overlaps = Vec::new();
+    for ((collection, action), group) in groups {
+        let overlap = |kind, roles| Overlap {
+            collection: collection.to_owned(),
+            action: action.to_owned(),
+            kind,
+            roles,
+        };
+
+        // Put semantically equal grants in the same class
+        let mut classes: Vec<Vec<&Grant>> = Vec::new();
+        for grant in group {
+            match classes
+                .iter_mut()
+                .find(|class| class[0].covers(grant) && grant.covers(class[0]))
+            {
+                Some(class) => class.push(grant),
+                None => classes.push(vec![grant]),
+            }
+        }
+
+        for class in &classes {
+            let roles: BTreeSet<String> = class.iter().map(|g| g.role.to_owned()).collect();
+            if roles.len() > 1 {
+                overlaps.push(overlap(OverlapKind::Equal, roles.iter().cloned().collect()));
+            }
+            if roles.len() < class.len() {
+                for role in roles {
+                    if class.iter().filter(|g| g.role == role).count() > 1 {
+                        overlaps.push(overlap(OverlapKind::Redundant, vec![role.clone(), role]));
+                    }
+                }
+            }
+        }
+
+        for wider in &classes {
+            for narrower in &classes {
+                if std::ptr::eq(wider, narrower) || !wider[0].covers(narrower[0]) {
+                    continue;
+                }
+                for w in wider {
+                    for n in narrower {
+                        let kind = if w.role == n.role {
+                            OverlapKind::Redundant
+                        } else {
+                            OverlapKind::Superset
+                        };
+                        overlaps.push(overlap(kind, vec![w.role.to_owned(), n.role.to_owned()]));
+                    }
+                }
+            }
+        }
+    }
+
+    overlaps.sort();
+    overlaps.dedup();
+    overlaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn grant(role: &str, fields: &[&str], permissions: serde_json::Value) -> Grant {
+        Grant {
+            role: role.to_owned(),
+            collection: "articles".to_owned(),
+            action: "read".to_owned(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            permissions: Filter::try_from(&permissions).unwrap(),
+            validation: Filter::All,
+        }
+    }
+
+    #[test]
+    fn is_requested_matches_fields_under_wildcard_collections() {
+        let request = |r: &str| -> Request { RequestEntity::from(r.to_owned()).into() };
+        let title = grant("editor", &["id", "title"], json!({}));
+        assert!(title.is_requested(&[]));
+        assert!(title.is_requested(&[request("*.title")]));
+        assert!(title.is_requested(&[request("art*.title")]));
+        assert!(title.is_requested(&[request("*.ti*")]));
+        assert!(!title.is_requested(&[request("*.email")]));
+        assert!(grant("editor", &[], json!({})).is_requested(&[request("*.email")]));
+    }
+
+    #[test]
+    fn find_overlaps_detects_equal_rules_written_differently() {
+        let grants = vec![
+            grant(
+                "editor",
+                &["id", "title"],
+                json!({ "_and": [{ "id": { "_lt": 5 } }, { "status": { "_eq": "draft" } }] }),
+            ),
+            grant(
+                "author",
+                &["title", "id"],
+                json!({ "status": { "_in": ["draft"] }, "id": { "_lt": 5 } }),
+            ),
+        ];
+        let result = find_overlaps(&grants);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].kind, OverlapKind::Equal);
+        assert_eq!(result[0].roles, vec!["author", "editor"]);
+    }
+
+    #[test]
+    fn find_overlaps_detects_strict_supersets() {
+        let grants = vec![
+            grant(
+                "author",
+                &["id"],
+                json!({ "user_created": { "_eq": "$CURRENT_USER" } }),
+            ),
+            grant("editor", &["id", "title"], json!({})),
+        ];
+        let result = find_overlaps(&grants);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].kind, OverlapKind::Superset);
+        assert_eq!(result[0].roles, vec!["editor", "author"]);
+    }
+
+    #[test]
+    fn find_overlaps_ignores_unrelated_rules() {
+        let grants = vec![
+            grant("author", &["id"], json!({ "status": { "_eq": "draft" } })),
+            grant("editor", &["title"], json!({})),
+        ];
+        assert!(find_overlaps(&grants).is_empty());
+    }
+}
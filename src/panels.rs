@@ -0,0 +1,313 @@
+//! Map Insights panels to the data they show.
+//!
+//! Panels in `directus_panels` query a collection with the options of their
+//! type, often as aggregates like a sum or an average. An aggregate over
+//! salaries is still personal data, so flag panels that show collections or
+//! fields some viewers of the dashboard can't read directly.
+use crate::cli::{OutputFormat, Panels};
+use crate::directus;
+use crate::entities::{directus_dashboards, directus_panels, directus_permissions};
+use crate::output;
+use crate::presets;
+use regex::Regex;
+use sea_orm::{Database, EntityTrait};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Panel options that name a field of the panel's collection.
+const FIELD_OPTIONS: [&str; 9] = [
+    "field",
+    "dateField",
+    "valueField",
+    "sortField",
+    "xAxis",
+    "yAxis",
+    "column",
+    "value",
+    "groupBy",
+];
+
+/// Panel options that hold a display template like `{{ first_name }}`.
+const TEMPLATE_OPTIONS: [&str; 2] = ["displayTemplate", "template"];
+
+/// Collections a role must read to view dashboards.
+const INSIGHTS_COLLECTIONS: [&str; 2] = ["directus_dashboards", "directus_panels"];
+
+/// 🏡 Handle logic for the `panels` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `panels` specific options.
+pub async fn panels_entrypoint(args: &PanelsOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let role_names = directus::fetch_role_names(&db).await?;
+    let admin_roles = directus::fetch_admin_roles(&db).await?;
+    let dashboards = directus_dashboards::Entity::find().all(&db).await?;
+    let panels = directus_panels::Entity::find().all(&db).await?;
+
+    let viewers = viewers(&permissions, &admin_roles);
+    let exposure = Exposure {
+        permissions: &permissions,
+        fields: &fields,
+        role_names: &role_names,
+        admin_roles: &admin_roles,
+        viewers: &viewers,
+    };
+    let report: Vec<PanelReport> = panels
+        .iter()
+        .filter_map(|panel| {
+            let dashboard = dashboards.iter().find(|d| d.id == panel.dashboard);
+            exposure.check(panel, dashboard.map(|d| d.name.as_str()))
+        })
+        .collect();
+
+    output::print(&args.output, &report)
+}
+
+#[derive(Debug)]
+pub struct PanelsOptions {
+    pub url: String,
+    pub output: OutputFormat,
+}
+
+impl From<Panels> for PanelsOptions {
+    fn from(panels: Panels) -> Self {
+        PanelsOptions {
+            url: panels.global_args.url,
+            output: panels.global_args.output,
+        }
+    }
+}
+
+/// What a panel queries.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PanelQuery {
+    pub collection: String,
+    pub fields: BTreeSet<String>,
+    /// The aggregate function, e.g. `sum` or `avg`. `None` shows items.
+    pub function: Option<String>,
+}
+
+impl PanelQuery {
+    /// `None` for panels that don't query a collection, like labels.
+    pub fn from_options(options: &Value) -> Option<Self> {
+        let collection = options.get("collection")?.as_str()?.to_owned();
+        let mut fields = BTreeSet::new();
+        for key in FIELD_OPTIONS {
+            if let Some(field) = options.get(key).and_then(Value::as_str) {
+                fields.insert(field.split('.').next().unwrap_or(field).to_owned());
+            }
+        }
+        let template = Regex::new(r"\{\{\s*([^}\s]+)\s*\}\}").unwrap();
+        for key in TEMPLATE_OPTIONS {
+            if let Some(text) = options.get(key).and_then(Value::as_str) {
+                for capture in template.captures_iter(text) {
+                    let path = &capture[1];
+                    fields.insert(path.split('.').next().unwrap_or(path).to_owned());
+                }
+            }
+        }
+        if let Some(filter) = options.get("filter") {
+            presets::filter_fields(filter, &mut fields);
+        }
+        fields.remove("*");
+
+        Some(Self {
+            collection,
+            fields,
+            function: options
+                .get("function")
+                .or_else(|| options.get("aggregateFunction"))
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        })
+    }
+}
+
+/// What's wrong with a panel.
+///
+/// * `Unreadable` -> Viewers see data they can't read in the collection itself
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PanelIssue {
+    Unreadable,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PanelReport {
+    pub dashboard: Option<String>,
+    pub panel: String,
+    pub r#type: String,
+    #[serde(flatten)]
+    pub query: PanelQuery,
+    pub viewers: Vec<String>,
+    pub issues: Vec<PanelIssue>,
+    /// Fields each viewer can't read. `*` means the whole collection.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub unreadable: BTreeMap<String, Vec<String>>,
+}
+
+/// Roles that can read dashboards and panels.
+///
+/// Admin roles can view all dashboards without permissions, so they are
+/// always viewers.
+pub fn viewers(
+    permissions: &[directus_permissions::Model],
+    admin_roles: &HashSet<String>,
+) -> BTreeSet<Option<String>> {
+    let can_read = |role: &Option<String>, collection: &str| {
+        permissions
+            .iter()
+            .any(|p| &p.role == role && p.collection == collection && p.action == "read")
+    };
+    permissions
+        .iter()
+        .map(|p| p.role.clone())
+        .filter(|role| INSIGHTS_COLLECTIONS.iter().all(|c| can_read(role, c)))
+        .chain(admin_roles.iter().cloned().map(Some))
+        .collect()
+}
+
+/// Everything needed to check panels.
+pub struct Exposure<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [directus::Field],
+    pub role_names: &'a HashMap<String, String>,
+    pub admin_roles: &'a HashSet<String>,
+    pub viewers: &'a BTreeSet<Option<String>>,
+}
+
+impl<'a> Exposure<'a> {
+    /// `None` for panels that don't query a collection.
+    pub fn check(
+        &self,
+        panel: &directus_panels::Model,
+        dashboard: Option<&str>,
+    ) -> Option<PanelReport> {
+        let query = PanelQuery::from_options(panel.options.as_ref()?)?;
+
+        let mut viewers = Vec::new();
+        let mut unreadable = BTreeMap::new();
+        for role in self.viewers.iter().map(Option::as_deref) {
+            let name = directus::role_name(role, self.role_names);
+            if role.is_some_and(|id| self.admin_roles.contains(id)) {
+                viewers.push(name);
+                continue;
+            }
+            let readable = directus::granted_fields(
+                self.permissions,
+                self.fields,
+                role,
+                &query.collection,
+                "read",
+            );
+            let missing: Vec<String> = match readable.is_empty() {
+                true => vec!["*".to_owned()],
+                false => query
+                    .fields
+                    .iter()
+                    .filter(|field| !readable.contains(*field))
+                    .cloned()
+                    .collect(),
+            };
+            if !missing.is_empty() {
+                unreadable.insert(name.to_owned(), missing);
+            }
+            viewers.push(name);
+        }
+        viewers.sort();
+
+        Some(PanelReport {
+            dashboard: dashboard.map(str::to_owned),
+            panel: panel.name.clone().unwrap_or_else(|| panel.id.to_string()),
+            r#type: panel.r#type.to_owned(),
+            query,
+            viewers,
+            issues: match unreadable.is_empty() {
+                true => Vec::new(),
+                false => vec![PanelIssue::Unreadable],
+            },
+            unreadable,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::permission;
+    use sea_orm::prelude::Uuid;
+    use serde_json::json;
+
+    fn panel(options: Value) -> directus_panels::Model {
+        directus_panels::Model {
+            id: Uuid::nil(),
+            dashboard: Uuid::nil(),
+            name: Some("Average salary".to_owned()),
+            icon: None,
+            color: None,
+            show_header: true,
+            note: None,
+            r#type: "metric".to_owned(),
+            position_x: 1,
+            position_y: 1,
+            width: 8,
+            height: 6,
+            options: Some(options),
+            date_created: None,
+            user_created: None,
+        }
+    }
+
+    #[test]
+    fn from_options_reads_fields_templates_and_filters() {
+        let query = PanelQuery::from_options(&json!({
+            "collection": "employees",
+            "field": "salary",
+            "function": "avg",
+            "displayTemplate": "{{ first_name }} {{department.name}}",
+            "filter": { "_and": [{ "status": { "_eq": "active" } }] },
+        }))
+        .unwrap();
+        assert_eq!(
+            query.fields.into_iter().collect::<Vec<String>>(),
+            vec!["department", "first_name", "salary", "status"]
+        );
+        assert_eq!(query.function.as_deref(), Some("avg"));
+        assert_eq!(PanelQuery::from_options(&json!({ "text": "Hello" })), None);
+    }
+
+    #[test]
+    fn check_flags_viewers_that_cant_read_the_panels_data() {
+        let permissions = vec![
+            permission(Some("1"), "directus_dashboards", "read", "*"),
+            permission(Some("1"), "directus_panels", "read", "*"),
+            permission(Some("1"), "employees", "read", "id,status"),
+            permission(Some("2"), "directus_panels", "read", "*"),
+        ];
+        let role_names = HashMap::from([
+            ("1".to_owned(), "Manager".to_owned()),
+            ("3".to_owned(), "Administrator".to_owned()),
+        ]);
+        let admin_roles = HashSet::from(["3".to_owned()]);
+        let viewers = viewers(&permissions, &admin_roles);
+        let exposure = Exposure {
+            permissions: &permissions,
+            fields: &[],
+            role_names: &role_names,
+            admin_roles: &admin_roles,
+            viewers: &viewers,
+        };
+
+        let options = json!({ "collection": "employees", "field": "salary", "function": "avg" });
+        let result = exposure.check(&panel(options), Some("HR")).unwrap();
+        assert_eq!(result.viewers, vec!["Administrator", "Manager"]);
+        assert_eq!(result.issues, vec![PanelIssue::Unreadable]);
+        assert_eq!(result.unreadable["Manager"], vec!["salary"]);
+    }
+}
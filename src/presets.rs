@@ -0,0 +1,457 @@
+//! Audit and version presets and bookmarks in `directus_presets`.
+//!
+//! Presets store the filter, search and layout of a collection's item list
+//! for a role, a user or everybody. Bookmarks are named presets. Presets of
+//! roles and everybody are part of a role's configuration and are included
+//! in the manifest. Presets of single users are personal and are only audited.
+use crate::classify::{self, Classifier, Rules};
+use crate::cli::{OutputFormat, Presets};
+use crate::directus;
+use crate::dump::{Request, RequestEntity};
+use crate::entities::{directus_permissions, directus_presets};
+use crate::output;
+use sea_orm::{ColumnTrait, Database, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+
+/// 🏡 Handle logic for the `presets` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `presets` specific options.
+pub async fn presets_entrypoint(args: &PresetsOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let role_names = directus::fetch_role_names(&db).await?;
+    let admin_roles = directus::fetch_admin_roles(&db).await?;
+    let users = directus::fetch_users(&db).await?;
+    let presets = directus_presets::Entity::find().all(&db).await?;
+    let mut sensitive = classify::to_requests(
+        &classify::classify_tagged(&db, &[], &args.tag, args.rules.as_deref()).await?,
+    );
+    sensitive.extend(args.sensitive.iter().cloned());
+
+    let audit = Audit {
+        permissions: &permissions,
+        fields: &fields,
+        role_names: &role_names,
+        admin_roles: &admin_roles,
+        users: &users,
+        sensitive: &sensitive,
+        classifier: Classifier::new(&Rules::read(args.rules.as_deref())?)?,
+    };
+    let findings: Vec<PresetFinding> = presets
+        .iter()
+        .map(|preset| audit.check(preset))
+        .filter(|finding| !finding.issues.is_empty())
+        .collect();
+
+    output::print(&args.output, &findings)
+}
+
+#[derive(Debug)]
+pub struct PresetsOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub tag: String,
+    pub rules: Option<PathBuf>,
+    pub sensitive: Vec<Request>,
+}
+
+impl From<Presets> for PresetsOptions {
+    fn from(presets: Presets) -> Self {
+        PresetsOptions {
+            url: presets.global_args.url,
+            output: presets.global_args.output,
+            tag: presets.tag,
+            rules: presets.rules,
+            sensitive: presets
+                .sensitive
+                .into_iter()
+                .map(|req| RequestEntity::from(req).into())
+                .collect(),
+        }
+    }
+}
+
+/// A preset of a role or of everybody, as stored in the manifest.
+///
+/// `role` is a role name. Presets without `role` apply to all users.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Preset {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    pub collection: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bookmark: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub filter: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub layout_query: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub layout_options: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+impl Preset {
+    /// `None` for presets of single users and presets without a collection.
+    pub fn from_model(
+        model: &directus_presets::Model,
+        role_names: &HashMap<String, String>,
+    ) -> Option<Self> {
+        if model.user.is_some() {
+            return None;
+        }
+        Some(Self {
+            role: model.role.map(|id| {
+                let id = id.to_string();
+                role_names.get(&id).cloned().unwrap_or(id)
+            }),
+            collection: model.collection.clone()?,
+            bookmark: model.bookmark.clone(),
+            search: model.search.clone(),
+            filter: model.filter.clone().unwrap_or(Value::Null),
+            layout: model.layout.clone(),
+            layout_query: model.layout_query.clone().unwrap_or(Value::Null),
+            layout_options: model.layout_options.clone().unwrap_or(Value::Null),
+            refresh_interval: model.refresh_interval,
+            icon: model.icon.clone(),
+            color: model.color.clone(),
+        })
+    }
+
+    /// Presets are identified by their role, collection and bookmark.
+    pub fn has_same_key(&self, other: &Preset) -> bool {
+        self.role == other.role
+            && self.collection == other.collection
+            && self.bookmark == other.bookmark
+    }
+}
+
+/// Get all presets of roles and of everybody.
+pub async fn fetch_shared(db: &DatabaseConnection) -> Result<Vec<directus_presets::Model>, DbErr> {
+    directus_presets::Entity::find()
+        .filter(directus_presets::Column::User.is_null())
+        .all(db)
+        .await
+}
+
+/// Turn presets of roles and everybody into manifest presets.
+///
+/// Only presets of collections matching `requests` are kept, all if there
+/// are no requests.
+pub fn to_manifest(
+    models: &[directus_presets::Model],
+    role_names: &HashMap<String, String>,
+    requests: &[Request],
+) -> Vec<Preset> {
+    models
+        .iter()
+        .filter_map(|model| Preset::from_model(model, role_names))
+        .filter(|preset| {
+            requests.is_empty()
+                || requests
+                    .iter()
+                    .any(|r| r.matches_collection(&preset.collection))
+        })
+        .collect()
+}
+
+/// Fields of the preset's collection used by its filter and layout.
+///
+/// Relational paths like `user.email` count as their first segment.
+pub fn referenced_fields(preset: &directus_presets::Model) -> BTreeSet<String> {
+    let mut fields = BTreeSet::new();
+    if let Some(filter) = &preset.filter {
+        filter_fields(filter, &mut fields);
+    }
+    if let Some(Value::Object(layouts)) = &preset.layout_query {
+        for query in layouts.values() {
+            for key in ["fields", "sort"] {
+                let paths: Vec<&str> = match query.get(key) {
+                    Some(Value::Array(paths)) => paths.iter().filter_map(Value::as_str).collect(),
+                    Some(Value::String(path)) => vec![path.as_str()],
+                    _ => Vec::new(),
+                };
+                for path in paths {
+                    let field = path.trim_start_matches('-').split('.').next();
+                    if let Some(field) = field.filter(|f| !f.is_empty() && *f != "*") {
+                        fields.insert(field.to_owned());
+                    }
+                }
+            }
+        }
+    }
+    fields
+}
+
+/// Collect the field keys of a filter. Keys starting with `_` are operators.
+pub fn filter_fields(filter: &Value, fields: &mut BTreeSet<String>) {
+    match filter {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match key.starts_with('_') {
+                    true => filter_fields(value, fields),
+                    false => {
+                        fields.insert(key.to_owned());
+                    }
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| filter_fields(item, fields)),
+        _ => (),
+    }
+}
+
+/// Collect `(field, value)` of all literal string values of a filter.
+///
+/// Dynamic variables like `$CURRENT_USER` aren't literals.
+fn filter_values<'a>(
+    filter: &'a Value,
+    field: Option<&'a str>,
+    values: &mut Vec<(&'a str, &'a str)>,
+) {
+    match filter {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let field = match key.starts_with('_') {
+                    true => field,
+                    false => Some(key.as_str()),
+                };
+                filter_values(value, field, values);
+            }
+        }
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| filter_values(item, field, values)),
+        Value::String(value) if !value.starts_with('$') => {
+            if let Some(field) = field {
+                values.push((field, value));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// What's wrong with a preset.
+///
+/// * `UnreadableFields` -> The filter or layout uses fields the role can't read
+/// * `SensitiveValues` -> A bookmark stores personal data in its search or filter
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PresetIssue {
+    UnreadableFields,
+    SensitiveValues,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PresetFinding {
+    pub id: i32,
+    pub role: Option<String>,
+    pub user: Option<String>,
+    pub collection: Option<String>,
+    pub bookmark: Option<String>,
+    pub issues: Vec<PresetIssue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unreadable_fields: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sensitive_values: Vec<String>,
+}
+
+/// Everything needed to check presets.
+pub struct Audit<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [directus::Field],
+    pub role_names: &'a HashMap<String, String>,
+    pub admin_roles: &'a HashSet<String>,
+    pub users: &'a HashMap<String, directus::User>,
+    pub sensitive: &'a [Request],
+    pub classifier: Classifier,
+}
+
+impl<'a> Audit<'a> {
+    pub fn check(&self, preset: &directus_presets::Model) -> PresetFinding {
+        let user = preset.user.and_then(|id| self.users.get(&id.to_string()));
+        // Presets of users apply with the user's role.
+        let role = match user {
+            Some(user) => user.role.clone(),
+            None => preset.role.map(|id| id.to_string()),
+        };
+        let mut issues = Vec::new();
+
+        let unreadable_fields: Vec<String> = match (&role, &preset.collection) {
+            (Some(role), Some(collection)) if !self.admin_roles.contains(role) => {
+                let readable = directus::granted_fields(
+                    self.permissions,
+                    self.fields,
+                    Some(role),
+                    collection,
+                    "read",
+                );
+                referenced_fields(preset)
+                    .into_iter()
+                    .filter(|field| !readable.contains(field))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        if !unreadable_fields.is_empty() {
+            issues.push(PresetIssue::UnreadableFields);
+        }
+
+        let sensitive_values = match preset.bookmark {
+            Some(_) => self.sensitive_values(preset),
+            None => Vec::new(),
+        };
+        if !sensitive_values.is_empty() {
+            issues.push(PresetIssue::SensitiveValues);
+        }
+
+        PresetFinding {
+            id: preset.id,
+            role: role
+                .as_deref()
+                .map(|id| directus::role_name(Some(id), self.role_names)),
+            user: user.map(directus::User::label),
+            collection: preset.collection.clone(),
+            bookmark: preset.bookmark.clone(),
+            issues,
+            unreadable_fields,
+            sensitive_values,
+        }
+    }
+
+    /// Where a preset stores values that look personal or filter sensitive fields.
+    fn sensitive_values(&self, preset: &directus_presets::Model) -> Vec<String> {
+        let mut found = Vec::new();
+        if let Some(kind) = preset
+            .search
+            .as_deref()
+            .and_then(|search| self.classifier.value_kind(search))
+        {
+            found.push(format!("search looks like {}", kind));
+        }
+
+        let collection = preset.collection.as_deref().unwrap_or_default();
+        let mut values = Vec::new();
+        if let Some(filter) = &preset.filter {
+            filter_values(filter, None, &mut values);
+        }
+        for (field, value) in values {
+            if let Some(kind) = self.classifier.value_kind(value) {
+                found.push(format!("filter on `{}` looks like {}", field, kind));
+            } else if self.sensitive.iter().any(|s| s.matches(collection, field)) {
+                found.push(format!("filter on sensitive `{}`", field));
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::prelude::Uuid;
+    use serde_json::json;
+
+    fn preset(
+        bookmark: Option<&str>,
+        filter: Value,
+        layout_query: Value,
+    ) -> directus_presets::Model {
+        directus_presets::Model {
+            id: 1,
+            bookmark: bookmark.map(str::to_owned),
+            user: None,
+            role: Some(Uuid::from_u128(1)),
+            collection: Some("customers".to_owned()),
+            search: None,
+            layout: Some("tabular".to_owned()),
+            layout_query: Some(layout_query),
+            layout_options: None,
+            refresh_interval: None,
+            filter: Some(filter),
+            icon: None,
+            color: None,
+        }
+    }
+
+    fn audit<'a>(
+        permissions: &'a [directus_permissions::Model],
+        role_names: &'a HashMap<String, String>,
+        admin_roles: &'a HashSet<String>,
+        users: &'a HashMap<String, directus::User>,
+    ) -> Audit<'a> {
+        Audit {
+            permissions,
+            fields: &[],
+            role_names,
+            admin_roles,
+            users,
+            sensitive: &[],
+            classifier: Classifier::new(&Rules::default()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn referenced_fields_reads_filter_and_layout() {
+        let preset = preset(
+            None,
+            json!({ "_and": [{ "status": { "_eq": "active" } }, { "user": { "email": { "_nnull": true } } }] }),
+            json!({ "tabular": { "fields": ["id", "user.email"], "sort": ["-date_created"] } }),
+        );
+        assert_eq!(
+            referenced_fields(&preset)
+                .into_iter()
+                .collect::<Vec<String>>(),
+            vec!["date_created", "id", "status", "user"]
+        );
+    }
+
+    #[test]
+    fn check_flags_unreadable_fields_and_personal_bookmarks() {
+        let permissions = vec![directus_permissions::Model {
+            id: 1,
+            role: Some(Uuid::from_u128(1).to_string()),
+            collection: "customers".to_owned(),
+            action: "read".to_owned(),
+            permissions: None,
+            validation: None,
+            presets: None,
+            fields: Some("id,email".to_owned()),
+        }];
+        let role_names = HashMap::from([(Uuid::from_u128(1).to_string(), "Support".to_owned())]);
+        let (admin_roles, users) = (HashSet::new(), HashMap::new());
+        let preset = preset(
+            Some("Jane"),
+            json!({ "email": { "_eq": "jane@example.com" } }),
+            json!({ "tabular": { "fields": ["id", "notes"] } }),
+        );
+
+        let result = audit(&permissions, &role_names, &admin_roles, &users).check(&preset);
+        assert_eq!(result.role.as_deref(), Some("Support"));
+        assert_eq!(
+            result.issues,
+            vec![PresetIssue::UnreadableFields, PresetIssue::SensitiveValues]
+        );
+        assert_eq!(result.unreadable_fields, vec!["notes"]);
+        assert_eq!(
+            result.sensitive_values,
+            vec!["filter on `email` looks like email"]
+        );
+    }
+}
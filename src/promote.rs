@@ -0,0 +1,227 @@
+//! Copy the permissions of roles from one database to another.
+//!
+//! Role ids differ between environments, so roles are mapped by name. The
+//! target must have all roles, collections and fields the copied rows refer
+//! to, including the fields of their filters. The roles get exactly the
+//! permissions they have in the source.
+use crate::cli::{OutputFormat, Promote};
+use crate::entities::directus_permissions;
+use crate::output;
+use crate::replace::{self, Diff, PermissionRow, Schema};
+use crate::utils;
+use crate::wildcard;
+use sea_orm::{Database, EntityTrait};
+use std::collections::BTreeSet;
+
+/// 🏡 Handle logic for the `promote` command.
+///
+/// Print the diff and apply it after confirmation.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `promote` specific options.
+pub async fn promote_entrypoint(args: &PromoteOptions) -> anyhow::Result<()> {
+    let source_db = Database::connect(&args.from).await?;
+    let target_db = Database::connect(&args.to).await?;
+
+    let source = Schema::fetch(&source_db).await?;
+    let target = Schema::fetch(&target_db).await?;
+    let mut roles = BTreeSet::new();
+    for pattern in &args.roles {
+        let matches = wildcard::find_with(pattern, &source.role_names());
+        if matches.is_empty() {
+            anyhow::bail!("No role matches `{}`", pattern);
+        }
+        roles.extend(matches);
+    }
+
+    let promotion = Promotion {
+        source: &source,
+        target: &target,
+    };
+    let source_permissions = directus_permissions::Entity::find().all(&source_db).await?;
+    let target_permissions = directus_permissions::Entity::find().all(&target_db).await?;
+    let diff = promotion.plan(&roles, &source_permissions, &target_permissions)?;
+    output::print(&args.output, &diff)?;
+
+    if diff.is_empty() {
+        return Ok(());
+    }
+    let question = format!(
+        "Apply {} change(s) to the target?",
+        diff.create.len() + diff.update.len() + diff.delete.len()
+    );
+    match args.yes || utils::confirm(&question)? {
+        true => Ok(replace::apply(&target_db, &diff).await?),
+        false => {
+            eprintln!("Nothing applied.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PromoteOptions {
+    pub from: String,
+    pub to: String,
+    pub output: OutputFormat,
+    pub roles: Vec<String>,
+    pub yes: bool,
+}
+
+impl From<Promote> for PromoteOptions {
+    fn from(promote: Promote) -> Self {
+        PromoteOptions {
+            from: promote.from,
+            to: promote.to,
+            output: promote.output,
+            roles: promote.roles,
+            yes: promote.yes,
+        }
+    }
+}
+
+/// The source and target of a promotion.
+pub struct Promotion<'a> {
+    pub source: &'a Schema,
+    pub target: &'a Schema,
+}
+
+impl<'a> Promotion<'a> {
+    /// Compare the roles' permissions in the target with the source.
+    pub fn plan(
+        &self,
+        roles: &BTreeSet<String>,
+        source_permissions: &[directus_permissions::Model],
+        target_permissions: &[directus_permissions::Model],
+    ) -> anyhow::Result<Diff> {
+        let mut current = Vec::new();
+        let mut desired = Vec::new();
+        for role in roles {
+            let source_id = self.source.roles.get(role).cloned().flatten();
+            let target_id = match self.target.roles.get(role) {
+                Some(id) => id.clone(),
+                None => anyhow::bail!("Role `{}` doesn't exist in the target", role),
+            };
+            desired.extend(
+                source_permissions
+                    .iter()
+                    .filter(|p| p.role == source_id)
+                    .map(|p| PermissionRow {
+                        id: None,
+                        role_id: target_id.clone(),
+                        ..PermissionRow::from_model(p, self.source)
+                    }),
+            );
+            current.extend(
+                target_permissions
+                    .iter()
+                    .filter(|p| p.role == target_id)
+                    .map(|p| PermissionRow::from_model(p, self.target)),
+            );
+        }
+
+        let missing = self.target.missing(&desired);
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "The target is missing collections or fields:\n{}",
+                missing.into_iter().collect::<Vec<String>>().join("\n")
+            );
+        }
+        Ok(Diff::new(&current, &desired, self.target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directus::Field;
+    use crate::test_support;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn schema(role_id: &str, fields: &[&str]) -> Schema {
+        let fields: Vec<Field> = fields
+            .iter()
+            .map(|f| Field {
+                collection: "articles".to_owned(),
+                field: f.to_string(),
+            })
+            .collect();
+        let role_names = HashMap::from([(role_id.to_owned(), "editor".to_owned())]);
+        Schema::new(&fields, &role_names)
+    }
+
+    fn permission(id: i32, role: &str, fields: &str) -> directus_permissions::Model {
+        directus_permissions::Model {
+            id,
+            permissions: Some(json!({ "status": { "_eq": "published" } })),
+            ..test_support::permission(Some(role), "articles", "read", fields)
+        }
+    }
+
+    #[test]
+    fn plan_maps_roles_by_name() {
+        let source = schema("dev-id", &["id", "title", "status"]);
+        let target = schema("prod-id", &["id", "title", "status"]);
+        let promotion = Promotion {
+            source: &source,
+            target: &target,
+        };
+        let roles = BTreeSet::from(["editor".to_owned()]);
+
+        let diff = promotion
+            .plan(
+                &roles,
+                &[permission(1, "dev-id", "id,title")],
+                &[permission(7, "prod-id", "id")],
+            )
+            .unwrap();
+        assert!(diff.create.is_empty() && diff.delete.is_empty());
+        assert_eq!(diff.update.len(), 1);
+        assert_eq!(diff.update[0].after.id, Some(7));
+        assert_eq!(diff.update[0].after.role_id.as_deref(), Some("prod-id"));
+        assert_eq!(diff.update[0].after.fields, "id,title");
+    }
+
+    #[test]
+    fn plan_fails_on_missing_fields_and_roles() {
+        let source = schema("dev-id", &["id", "title", "status"]);
+        let target = schema("prod-id", &["id", "title"]);
+        let promotion = Promotion {
+            source: &source,
+            target: &target,
+        };
+        let permissions = [permission(1, "dev-id", "id,subtitle")];
+
+        let missing = target.missing(&[PermissionRow::from_model(&permissions[0], &source)]);
+        assert_eq!(
+            missing.into_iter().collect::<Vec<String>>(),
+            vec!["articles.status", "articles.subtitle"]
+        );
+        let roles = BTreeSet::from(["editor".to_owned()]);
+        assert!(promotion.plan(&roles, &permissions, &[]).is_err());
+        let roles = BTreeSet::from(["admin".to_owned()]);
+        assert!(promotion.plan(&roles, &permissions, &[]).is_err());
+    }
+
+    #[test]
+    fn plan_promotes_system_collections() {
+        let source = schema("dev-id", &["id"]);
+        let target = schema("prod-id", &["id"]);
+        let promotion = Promotion {
+            source: &source,
+            target: &target,
+        };
+        let roles = BTreeSet::from(["editor".to_owned()]);
+        let users = directus_permissions::Model {
+            collection: "directus_users".to_owned(),
+            permissions: Some(json!({ "id": { "_eq": "$CURRENT_USER" } })),
+            ..permission(1, "dev-id", "first_name,last_name")
+        };
+
+        let diff = promotion.plan(&roles, &[users], &[]).unwrap();
+        assert_eq!(diff.create.len(), 1);
+        assert_eq!(diff.create[0].collection, "directus_users");
+    }
+}
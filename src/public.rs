@@ -0,0 +1,253 @@
+//! Harden the public role.
+//!
+//! The public role is `role = NULL` in `directus_permissions` and applies to
+//! everybody without login. List all it can do and fail on what it usually
+//! shouldn't: changing data, reading system collections and reading fields
+//! classified as personal data.
+use crate::classify;
+use crate::cli::{OutputFormat, Public};
+use crate::config::DIRECTUS_SYSTEM_COLLECTIONS;
+use crate::directus;
+use crate::dump::{Request, RequestEntity};
+use crate::entities::directus_permissions;
+use crate::output;
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Actions the public role should never have.
+const WRITE_ACTIONS: [&str; 3] = ["create", "update", "delete"];
+
+/// 🏡 Handle logic for the `public` command.
+///
+/// Fail if the public role has findings that aren't allowed.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `public` specific options.
+pub async fn public_entrypoint(args: &PublicOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let permissions = directus_permissions::Entity::find()
+        .filter(directus_permissions::Column::Role.is_null())
+        .all(&db)
+        .await?;
+    let classified: BTreeSet<(String, String)> =
+        classify::classify_tagged(&db, &[], &args.tag, args.rules.as_deref())
+            .await?
+            .into_iter()
+            .map(|c| (c.collection, c.field))
+            .collect();
+
+    let policy = Policy {
+        fields: &fields,
+        classified: &classified,
+        allow: &args.allow,
+    };
+    let report = policy.check(&permissions);
+    output::print(&args.output, &report)?;
+
+    let failed = report.findings.iter().filter(|f| !f.allowed).count();
+    if failed > 0 {
+        anyhow::bail!("The public role has {} finding(s).", failed);
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct PublicOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub tag: String,
+    pub rules: Option<PathBuf>,
+    pub allow: Vec<Request>,
+}
+
+impl From<Public> for PublicOptions {
+    fn from(public: Public) -> Self {
+        PublicOptions {
+            url: public.global_args.url,
+            output: public.global_args.output,
+            tag: public.tag,
+            rules: public.rules,
+            allow: public
+                .allow
+                .into_iter()
+                .map(|req| RequestEntity::from(req).into())
+                .collect(),
+        }
+    }
+}
+
+/// A `directus_permissions` row of the public role.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Grant {
+    pub collection: String,
+    pub action: String,
+    pub fields: Vec<String>,
+    pub permissions: Value,
+    pub validation: Value,
+}
+
+/// What the public role shouldn't do.
+///
+/// * `Write` -> Anybody can create, update or delete items
+/// * `SystemCollection` -> Anybody can read a Directus system collection
+/// * `Classified` -> Anybody can read fields classified as personal data
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicIssue {
+    Write,
+    SystemCollection,
+    Classified,
+}
+
+/// A finding is `allowed` if `--allow` covers all of its fields.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Finding {
+    pub issue: PublicIssue,
+    pub collection: String,
+    pub action: String,
+    pub fields: Vec<String>,
+    pub allowed: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PublicReport {
+    pub grants: Vec<Grant>,
+    pub findings: Vec<Finding>,
+}
+
+/// The checks for the public role.
+pub struct Policy<'a> {
+    pub fields: &'a [directus::Field],
+    /// Fields tagged as personal data as `(collection, field)`
+    pub classified: &'a BTreeSet<(String, String)>,
+    pub allow: &'a [Request],
+}
+
+impl<'a> Policy<'a> {
+    pub fn check(&self, permissions: &[directus_permissions::Model]) -> PublicReport {
+        let mut grants = Vec::new();
+        let mut findings = Vec::new();
+        for row in permissions.iter().filter(|row| row.role.is_none()) {
+            let collection = &row.collection;
+            let fields: Vec<String> = directus::granted_fields(
+                std::slice::from_ref(row),
+                self.fields,
+                None,
+                collection,
+                &row.action,
+            )
+            .into_iter()
+            .collect();
+
+            let mut finding = |issue: PublicIssue, fields: Vec<String>| {
+                let allowed = match fields.is_empty() {
+                    true => self.allow.iter().any(|a| a.matches_collection(collection)),
+                    false => fields
+                        .iter()
+                        .all(|field| self.allow.iter().any(|a| a.matches(collection, field))),
+                };
+                findings.push(Finding {
+                    issue,
+                    collection: collection.to_owned(),
+                    action: row.action.to_owned(),
+                    fields,
+                    allowed,
+                });
+            };
+            if WRITE_ACTIONS.contains(&row.action.as_str()) {
+                finding(PublicIssue::Write, fields.clone());
+            }
+            if row.action == "read" {
+                if DIRECTUS_SYSTEM_COLLECTIONS.contains(&collection.as_str()) {
+                    finding(PublicIssue::SystemCollection, fields.clone());
+                }
+                let classified: Vec<String> = fields
+                    .iter()
+                    .filter(|f| {
+                        self.classified
+                            .contains(&(collection.to_owned(), f.to_string()))
+                    })
+                    .cloned()
+                    .collect();
+                if !classified.is_empty() {
+                    finding(PublicIssue::Classified, classified);
+                }
+            }
+
+            grants.push(Grant {
+                collection: collection.to_owned(),
+                action: row.action.to_owned(),
+                fields,
+                permissions: row.permissions.clone().unwrap_or(Value::Null),
+                validation: row.validation.clone().unwrap_or(Value::Null),
+            });
+        }
+        findings.sort_by(|a, b| (a.issue, &a.collection).cmp(&(b.issue, &b.collection)));
+
+        PublicReport { grants, findings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::permission;
+
+    #[test]
+    fn check_flags_writes_system_collections_and_classified_fields() {
+        let permissions = vec![
+            permission(None, "articles", "read", "title,author_email"),
+            permission(None, "comments", "create", "name,body"),
+            permission(None, "directus_users", "read", "first_name"),
+        ];
+        let classified = BTreeSet::from([("articles".to_owned(), "author_email".to_owned())]);
+        let policy = Policy {
+            fields: &[],
+            classified: &classified,
+            allow: &[],
+        };
+
+        let report = policy.check(&permissions);
+        assert_eq!(report.grants.len(), 3);
+        let issues: Vec<(PublicIssue, &str)> = report
+            .findings
+            .iter()
+            .map(|f| (f.issue, f.collection.as_str()))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (PublicIssue::Write, "comments"),
+                (PublicIssue::SystemCollection, "directus_users"),
+                (PublicIssue::Classified, "articles"),
+            ]
+        );
+        assert_eq!(report.findings[2].fields, vec!["author_email"]);
+    }
+
+    #[test]
+    fn check_allows_findings_covered_by_allow() {
+        let permissions = vec![
+            permission(None, "comments", "create", "name,body"),
+            permission(None, "directus_files", "read", "id,title"),
+        ];
+        let allow: Vec<Request> = vec![RequestEntity::from("comments.*".to_owned()).into()];
+        let classified = BTreeSet::new();
+        let policy = Policy {
+            fields: &[],
+            classified: &classified,
+            allow: &allow,
+        };
+
+        let report = policy.check(&permissions);
+        let allowed: Vec<bool> = report.findings.iter().map(|f| f.allowed).collect();
+        assert_eq!(allowed, vec![true, false]);
+    }
+}
@@ -0,0 +1,899 @@
+//! Apply a GDPR Manifest to a Directus database.
+//!
+//! Globs in a manifest are always expanded at apply time against the live
+//! `directus_fields` and `directus_roles`. The expansion recorded in the
+//! manifest's `globs` is only used to warn, or with `--frozen` to refuse,
+//! if a glob now matches a different set than when it was written.
+use crate::cli::{Check, OutputFormat, Replace};
+use crate::config::DIRECTUS_SYSTEM_COLLECTIONS;
+use crate::directus::{self, Field};
+use crate::entities::{directus_permissions, directus_presets};
+use crate::graph::canonical::canonicalize;
+use crate::graph::filter::is_equivalent_json;
+use crate::manifest::Manifest;
+use crate::output;
+use crate::presets::{self, Preset};
+use crate::reversed_permissions::FieldRule;
+use crate::utils;
+use crate::wildcard;
+use sea_orm::{entity::*, query::*};
+use sea_orm::{Database, DatabaseConnection, DatabaseTransaction, DbErr};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// 🏡 Handle logic for the `replace` command.
+///
+/// Print the diff and apply it after confirmation.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `replace` specific options.
+pub async fn replace_entrypoint(args: &ReplaceOptions) -> anyhow::Result<()> {
+    if args.file.is_none() && !args.yes {
+        anyhow::bail!("Can't ask for confirmation while reading the manifest from stdin. Use `-f` or `--yes`.");
+    }
+    let manifest = Manifest::read(args.file.as_deref())?;
+    let db = Database::connect(&args.url).await?;
+    let schema = Schema::fetch(&db).await?;
+
+    let diff = plan(&db, &manifest, &schema, args.frozen).await?;
+    output::print(&args.output, &diff)?;
+
+    if diff.is_empty() {
+        return Ok(());
+    }
+    let question = format!("Apply {} change(s)?", diff.len());
+    match args.yes || utils::confirm(&question)? {
+        true => Ok(apply(&db, &diff).await?),
+        false => {
+            eprintln!("Nothing applied.");
+            Ok(())
+        }
+    }
+}
+
+/// 🏡 Handle logic for the `check` command.
+///
+/// Fail if the permissions in the database differ from the manifest.
+pub async fn check_entrypoint(args: &ReplaceOptions) -> anyhow::Result<()> {
+    let manifest = Manifest::read(args.file.as_deref())?;
+    let db = Database::connect(&args.url).await?;
+    let schema = Schema::fetch(&db).await?;
+
+    let diff = plan(&db, &manifest, &schema, args.frozen).await?;
+    output::print(&args.output, &diff)?;
+    if !diff.is_empty() {
+        anyhow::bail!("Permissions in the database differ from the manifest.");
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct ReplaceOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub file: Option<PathBuf>,
+    pub frozen: bool,
+    pub yes: bool,
+}
+
+impl From<Replace> for ReplaceOptions {
+    fn from(replace: Replace) -> Self {
+        ReplaceOptions {
+            url: replace.global_args.url,
+            output: replace.global_args.output,
+            file: replace.manifest_args.file,
+            frozen: replace.manifest_args.frozen,
+            yes: replace.yes,
+        }
+    }
+}
+
+impl From<Check> for ReplaceOptions {
+    fn from(check: Check) -> Self {
+        ReplaceOptions {
+            url: check.global_args.url,
+            output: check.global_args.output,
+            file: check.manifest_args.file,
+            frozen: check.manifest_args.frozen,
+            yes: false,
+        }
+    }
+}
+
+/// Everything in the live database that globs are expanded against.
+#[derive(Debug, Default)]
+pub struct Schema {
+    /// All fields by collection
+    pub fields: BTreeMap<String, Vec<String>>,
+    /// Role names to role ids. The public role has no id.
+    pub roles: HashMap<String, Option<String>>,
+}
+
+impl Schema {
+    pub async fn fetch(db: &DatabaseConnection) -> Result<Self, DbErr> {
+        let collections = directus::fetch_collections(db).await?;
+        let fields = directus::fetch_fields(db, &collections).await?;
+        let role_names = directus::fetch_role_names(db).await?;
+        Ok(Self::new(&fields, &role_names))
+    }
+
+    pub fn new(fields: &[Field], role_names: &HashMap<String, String>) -> Self {
+        let mut by_collection: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for field in fields {
+            by_collection
+                .entry(field.collection.to_owned())
+                .or_default()
+                .push(field.field.to_owned());
+        }
+        let mut roles: HashMap<String, Option<String>> = role_names
+            .iter()
+            .map(|(id, name)| (name.to_owned(), Some(id.to_owned())))
+            .collect();
+        roles.insert(directus::PUBLIC_ROLE.to_owned(), None);
+
+        Self {
+            fields: by_collection,
+            roles,
+        }
+    }
+
+    fn known_fields(&self, collection: &str) -> &[String] {
+        self.fields.get(collection).map_or(&[], |f| f.as_slice())
+    }
+
+    /// Turn a set of fields into a `fields` column. All known fields are `*`.
+    pub fn fields_column(&self, collection: &str, fields: BTreeSet<String>) -> String {
+        let known_fields = self.known_fields(collection);
+        if fields.contains("*")
+            || known_fields.len() == fields.len() && known_fields.iter().all(|f| fields.contains(f))
+        {
+            "*".to_owned()
+        } else {
+            fields.into_iter().collect::<Vec<String>>().join(",")
+        }
+    }
+
+    pub fn role_names(&self) -> Vec<String> {
+        self.roles.keys().cloned().collect()
+    }
+
+    /// Resolve a `fields` column to a set of fields. `*` and `NULL` are all fields.
+    pub fn field_set(&self, collection: &str, fields: Option<&str>) -> BTreeSet<String> {
+        match fields {
+            Some("*") | None => self.known_fields(collection).iter().cloned().collect(),
+            Some(csv) => csv.split(',').map(|f| f.trim().to_owned()).collect(),
+        }
+    }
+
+    /// Collections and fields of the rows that don't exist, like `articles`
+    /// or `articles.subtitle`. Fields of filters count too.
+    ///
+    /// System collections are skipped, the schema doesn't know their fields.
+    pub fn missing(&self, rows: &[PermissionRow]) -> BTreeSet<String> {
+        let mut missing = BTreeSet::new();
+        for row in rows {
+            if DIRECTUS_SYSTEM_COLLECTIONS.contains(&row.collection.as_str()) {
+                continue;
+            }
+            let known = match self.fields.get(&row.collection) {
+                Some(known) => known,
+                None => {
+                    missing.insert(row.collection.to_owned());
+                    continue;
+                }
+            };
+            let mut fields = match row.fields.as_str() {
+                "*" => BTreeSet::new(),
+                csv => self.field_set(&row.collection, Some(csv)),
+            };
+            presets::filter_fields(&row.permissions, &mut fields);
+            presets::filter_fields(&row.validation, &mut fields);
+            missing.extend(
+                fields
+                    .into_iter()
+                    .filter(|field| !known.contains(field))
+                    .map(|field| format!("{}.{}", row.collection, field)),
+            );
+        }
+        missing
+    }
+}
+
+/// A `directus_permissions` row in terms of role names.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PermissionRow {
+    #[serde(skip)]
+    pub id: Option<i32>,
+    #[serde(skip)]
+    pub role_id: Option<String>,
+    pub role: String,
+    pub collection: String,
+    pub action: String,
+    pub fields: String,
+    pub permissions: Value,
+    pub validation: Value,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub presets: Value,
+}
+
+impl PermissionRow {
+    pub fn from_model(model: &directus_permissions::Model, schema: &Schema) -> Self {
+        let role = schema
+            .roles
+            .iter()
+            .find(|(_, id)| id.as_deref() == model.role.as_deref())
+            .map(|(name, _)| name.to_owned())
+            .unwrap_or_else(|| directus::role_name(model.role.as_deref(), &HashMap::new()));
+
+        Self {
+            id: Some(model.id),
+            role_id: model.role.clone(),
+            role,
+            collection: model.collection.to_owned(),
+            action: model.action.to_owned(),
+            fields: model.fields.clone().unwrap_or_else(|| "*".to_owned()),
+            permissions: model.permissions.clone().unwrap_or(Value::Null),
+            validation: model.validation.clone().unwrap_or(Value::Null),
+            presets: model.presets.clone().unwrap_or(Value::Null),
+        }
+    }
+
+    fn has_same_rules(&self, other: &PermissionRow) -> bool {
+        is_equivalent_json(&self.permissions, &other.permissions)
+            && is_equivalent_json(&self.validation, &other.validation)
+            && self.presets == other.presets
+    }
+
+    fn has_same_key(&self, other: &PermissionRow) -> bool {
+        self.role_id == other.role_id
+            && self.collection == other.collection
+            && self.action == other.action
+    }
+}
+
+/// Role, collection, action, permissions, validation and presets of a row.
+type RowKey = (String, String, String, String, String, String);
+
+/// Expand all globs in a manifest and turn it into `directus_permissions` rows.
+///
+/// # Arguments
+///
+/// * `manifest` - The manifest to expand.
+/// * `schema` - The live fields and roles to expand globs against.
+/// * `frozen` - Fail if a glob matches a different set than recorded in
+///   the manifest, or if there's no recorded set for a glob.
+pub fn expand(
+    manifest: &Manifest,
+    schema: &Schema,
+    frozen: bool,
+) -> anyhow::Result<Vec<PermissionRow>> {
+    let mut mismatches: Vec<String> = Vec::new();
+    let mut check_glob = |name: &str, matches: &[String], recorded: Option<&Vec<String>>| {
+        let mut matches = matches.to_vec();
+        matches.sort();
+        match recorded {
+            Some(recorded) if recorded == &matches => (),
+            Some(recorded) => {
+                let message = format!(
+                    "`{}` matches {:?}, but matched {:?} when the manifest was written",
+                    name, matches, recorded
+                );
+                if !frozen {
+                    eprintln!("Warning: {}", message);
+                }
+                mismatches.push(message);
+            }
+            None if frozen => mismatches.push(format!("`{}` has no recorded matches", name)),
+            None => (),
+        }
+    };
+
+    let role_names = schema.role_names();
+    let mut rows: BTreeMap<RowKey, PermissionRow> = BTreeMap::new();
+    let mut row_fields: BTreeMap<RowKey, BTreeSet<String>> = BTreeMap::new();
+
+    for (collection, field_rules) in &manifest.collections {
+        let known_fields = schema.known_fields(collection);
+        for (key, rule) in field_rules {
+            let mut fields: BTreeSet<String> = BTreeSet::new();
+            for pattern in key.split(',').map(str::trim) {
+                // System collections have no known fields, `*` stays `*`.
+                if pattern == "*" && known_fields.is_empty() {
+                    fields.insert(pattern.to_owned());
+                    continue;
+                }
+                let matches = wildcard::find_with(pattern, known_fields);
+                if pattern.contains('*') {
+                    let name = format!("{}.{}", collection, pattern);
+                    check_glob(&name, &matches, manifest.globs.fields.get(&name));
+                }
+                fields.extend(matches);
+            }
+
+            for action in FieldRule::iter_keys() {
+                for action_rule in rule.action(action).into_iter().flatten() {
+                    for role_pattern in &action_rule.roles {
+                        let roles = wildcard::find_with(role_pattern, &role_names);
+                        if role_pattern.contains('*') {
+                            check_glob(
+                                role_pattern,
+                                &roles,
+                                manifest.globs.roles.get(role_pattern),
+                            );
+                        }
+                        for role in roles {
+                            let role_id = match schema.roles.get(&role) {
+                                Some(id) => id.clone(),
+                                None => anyhow::bail!("Role `{}` doesn't exist", role),
+                            };
+                            let permissions = canonicalize(&action_rule.permissions);
+                            let validation = canonicalize(&action_rule.validation);
+                            let row_key = (
+                                role.to_owned(),
+                                collection.to_owned(),
+                                action.to_owned(),
+                                permissions.to_string(),
+                                validation.to_string(),
+                                action_rule.presets.to_string(),
+                            );
+                            row_fields
+                                .entry(row_key.clone())
+                                .or_default()
+                                .extend(fields.iter().cloned());
+                            rows.entry(row_key).or_insert(PermissionRow {
+                                id: None,
+                                role_id,
+                                role,
+                                collection: collection.to_owned(),
+                                action: action.to_owned(),
+                                fields: String::new(),
+                                permissions,
+                                validation,
+                                presets: action_rule.presets.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if frozen && !mismatches.is_empty() {
+        anyhow::bail!(
+            "Refusing to apply a frozen manifest:\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(key, mut row)| {
+            let fields = row_fields.remove(&key).unwrap_or_default();
+            if fields.is_empty() {
+                return None;
+            }
+            row.fields = schema.fields_column(&row.collection, fields);
+            Some(row)
+        })
+        .collect())
+}
+
+/// A `directus_presets` row of a role or of everybody.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PresetRow {
+    #[serde(skip)]
+    pub id: Option<i32>,
+    #[serde(skip)]
+    pub role_id: Option<String>,
+    #[serde(flatten)]
+    pub preset: Preset,
+}
+
+impl PresetRow {
+    fn has_same_settings(&self, other: &PresetRow) -> bool {
+        let (a, b) = (&self.preset, &other.preset);
+        self.role_id == other.role_id
+            && is_equivalent_json(&a.filter, &b.filter)
+            && Preset {
+                filter: Value::Null,
+                ..a.clone()
+            } == Preset {
+                filter: Value::Null,
+                ..b.clone()
+            }
+    }
+}
+
+/// Resolve the role names of the manifest's presets.
+///
+/// Presets without a role apply to all users. The public role can't log
+/// into the app and has no presets.
+pub fn expand_presets(manifest: &Manifest, schema: &Schema) -> anyhow::Result<Vec<PresetRow>> {
+    manifest
+        .presets
+        .iter()
+        .flatten()
+        .map(|preset| {
+            let role_id = match &preset.role {
+                None => None,
+                Some(role) => match schema.roles.get(role) {
+                    Some(Some(id)) => Some(id.to_owned()),
+                    Some(None) => anyhow::bail!("The `{}` role can't have presets", role),
+                    None => anyhow::bail!("Role `{}` doesn't exist", role),
+                },
+            };
+            Ok(PresetRow {
+                id: None,
+                role_id,
+                preset: preset.clone(),
+            })
+        })
+        .collect()
+}
+
+/// A change of an existing row
+#[derive(Serialize, Debug)]
+pub struct Change<T = PermissionRow> {
+    pub before: T,
+    pub after: T,
+}
+
+/// All changes needed to get from the current to the desired permissions.
+#[derive(Serialize, Debug, Default)]
+pub struct Diff {
+    pub create: Vec<PermissionRow>,
+    pub update: Vec<Change>,
+    pub delete: Vec<PermissionRow>,
+    #[serde(skip_serializing_if = "PresetDiff::is_empty")]
+    pub presets: PresetDiff,
+}
+
+/// All changes needed to get from the current to the desired presets.
+#[derive(Serialize, Debug, Default)]
+pub struct PresetDiff {
+    pub create: Vec<PresetRow>,
+    pub update: Vec<Change<PresetRow>>,
+    pub delete: Vec<PresetRow>,
+}
+
+impl PresetDiff {
+    /// Compare current and desired presets by role, collection and bookmark.
+    pub fn new(current: &[PresetRow], desired: &[PresetRow]) -> Self {
+        let mut unmatched: Vec<&PresetRow> = current.iter().collect();
+        let mut diff = PresetDiff::default();
+        for row in desired {
+            match unmatched
+                .iter()
+                .position(|c| c.preset.has_same_key(&row.preset))
+            {
+                Some(index) => {
+                    let before = unmatched.remove(index).clone();
+                    if !before.has_same_settings(row) {
+                        let mut after = row.clone();
+                        after.id = before.id;
+                        diff.update.push(Change { before, after });
+                    }
+                }
+                None => diff.create.push(row.clone()),
+            }
+        }
+        diff.delete = unmatched.into_iter().cloned().collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+impl Diff {
+    /// Compare current and desired rows.
+    ///
+    /// Rows are equal if their role, collection, action and set of fields are
+    /// equal and their permissions and validation are semantically equal.
+    pub fn new(current: &[PermissionRow], desired: &[PermissionRow], schema: &Schema) -> Self {
+        let fields = |row: &PermissionRow| schema.field_set(&row.collection, Some(&row.fields));
+        let mut unmatched: Vec<&PermissionRow> = current.iter().collect();
+        let mut diff = Diff::default();
+
+        let mut changed = Vec::new();
+        for row in desired {
+            match unmatched.iter().position(|c| {
+                c.has_same_key(row) && c.has_same_rules(row) && fields(c) == fields(row)
+            }) {
+                Some(index) => {
+                    unmatched.remove(index);
+                }
+                None => changed.push(row),
+            }
+        }
+        for row in changed {
+            match unmatched.iter().position(|c| c.has_same_key(row)) {
+                Some(index) => {
+                    let before = unmatched.remove(index).clone();
+                    let mut after = row.clone();
+                    after.id = before.id;
+                    diff.update.push(Change { before, after });
+                }
+                None => diff.create.push(row.clone()),
+            }
+        }
+        diff.delete = unmatched.into_iter().cloned().collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of changed rows, including presets.
+    pub fn len(&self) -> usize {
+        self.create.len()
+            + self.update.len()
+            + self.delete.len()
+            + self.presets.create.len()
+            + self.presets.update.len()
+            + self.presets.delete.len()
+    }
+}
+
+/// Compare the manifest with the permissions of its collections in the database.
+///
+/// Presets are compared for the manifest's collections and the collections
+/// of its presets, unless the manifest has no `presets`.
+pub async fn plan(
+    db: &DatabaseConnection,
+    manifest: &Manifest,
+    schema: &Schema,
+    frozen: bool,
+) -> anyhow::Result<Diff> {
+    let collections: Vec<&String> = manifest.collections.keys().collect();
+    let current: Vec<PermissionRow> = directus_permissions::Entity::find()
+        .filter(directus_permissions::Column::Collection.is_in(collections))
+        .all(db)
+        .await?
+        .iter()
+        .map(|model| PermissionRow::from_model(model, schema))
+        .collect();
+
+    let current_presets: Vec<PresetRow> = match &manifest.presets {
+        Some(presets) => {
+            let scope: BTreeSet<&String> = manifest
+                .collections
+                .keys()
+                .chain(presets.iter().map(|preset| &preset.collection))
+                .collect();
+            let role_names: HashMap<String, String> = schema
+                .roles
+                .iter()
+                .filter_map(|(name, id)| Some((id.clone()?, name.to_owned())))
+                .collect();
+            presets::fetch_shared(db)
+                .await?
+                .iter()
+                .filter_map(|model| {
+                    let preset = Preset::from_model(model, &role_names)?;
+                    scope.contains(&preset.collection).then(|| PresetRow {
+                        id: Some(model.id),
+                        role_id: model.role.map(|id| id.to_string()),
+                        preset,
+                    })
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    compare(manifest, schema, frozen, &current, &current_presets)
+}
+
+/// Compare the manifest with the current rows and presets of its collections.
+///
+/// Action rules without `presets` keep the presets of the rows they replace,
+/// e.g. in manifests migrated from before `presets` existed.
+pub fn compare(
+    manifest: &Manifest,
+    schema: &Schema,
+    frozen: bool,
+    current: &[PermissionRow],
+    current_presets: &[PresetRow],
+) -> anyhow::Result<Diff> {
+    let mut desired = expand(manifest, schema, frozen)?;
+    keep_presets(current, &mut desired);
+
+    let mut diff = Diff::new(current, &desired, schema);
+    if manifest.presets.is_some() {
+        diff.presets = PresetDiff::new(current_presets, &expand_presets(manifest, schema)?);
+    }
+    Ok(diff)
+}
+
+/// Give desired rows without `presets` the presets of the row they replace.
+pub fn keep_presets(current: &[PermissionRow], desired: &mut [PermissionRow]) {
+    for row in desired.iter_mut().filter(|row| row.presets.is_null()) {
+        if let Some(before) = current.iter().find(|c| c.has_same_key(row)) {
+            row.presets = before.presets.clone();
+        }
+    }
+}
+
+pub fn json_or_null(value: &Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        other => Some(other.clone()),
+    }
+}
+
+fn preset_model(id: ActiveValue<i32>, row: &PresetRow) -> directus_presets::ActiveModel {
+    let preset = &row.preset;
+    directus_presets::ActiveModel {
+        id,
+        bookmark: Set(preset.bookmark.clone()),
+        user: Set(None),
+        role: Set(row.role_id.as_deref().and_then(|id| id.parse().ok())),
+        collection: Set(Some(preset.collection.to_owned())),
+        search: Set(preset.search.clone()),
+        layout: Set(preset.layout.clone()),
+        layout_query: Set(json_or_null(&preset.layout_query)),
+        layout_options: Set(json_or_null(&preset.layout_options)),
+        refresh_interval: Set(preset.refresh_interval),
+        filter: Set(json_or_null(&preset.filter)),
+        icon: Set(preset.icon.clone()),
+        color: Set(preset.color.clone()),
+    }
+}
+
+/// Apply all changes in a single transaction.
+pub async fn apply(db: &DatabaseConnection, diff: &Diff) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    apply_in(&txn, diff).await?;
+    txn.commit().await
+}
+
+/// Apply all changes in a transaction of the caller.
+pub async fn apply_in(txn: &DatabaseTransaction, diff: &Diff) -> Result<(), DbErr> {
+    for row in &diff.delete {
+        if let Some(id) = row.id {
+            directus_permissions::Entity::delete_by_id(id)
+                .exec(txn)
+                .await?;
+        }
+    }
+    for change in &diff.update {
+        let row = &change.after;
+        directus_permissions::ActiveModel {
+            id: Unchanged(change.before.id.unwrap_or_default()),
+            fields: Set(Some(row.fields.to_owned())),
+            permissions: Set(json_or_null(&row.permissions)),
+            validation: Set(json_or_null(&row.validation)),
+            presets: Set(json_or_null(&row.presets)),
+            ..Default::default()
+        }
+        .update(txn)
+        .await?;
+    }
+    for row in &diff.create {
+        directus_permissions::ActiveModel {
+            role: Set(row.role_id.clone()),
+            collection: Set(row.collection.to_owned()),
+            action: Set(row.action.to_owned()),
+            fields: Set(Some(row.fields.to_owned())),
+            permissions: Set(json_or_null(&row.permissions)),
+            validation: Set(json_or_null(&row.validation)),
+            presets: Set(json_or_null(&row.presets)),
+            ..Default::default()
+        }
+        .insert(txn)
+        .await?;
+    }
+
+    for row in &diff.presets.delete {
+        if let Some(id) = row.id {
+            directus_presets::Entity::delete_by_id(id).exec(txn).await?;
+        }
+    }
+    for change in &diff.presets.update {
+        preset_model(
+            Unchanged(change.before.id.unwrap_or_default()),
+            &change.after,
+        )
+        .update(txn)
+        .await?;
+    }
+    for row in &diff.presets.create {
+        preset_model(NotSet, row).insert(txn).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{migrate, Globs};
+    use crate::reversed_permissions::{self, ActionRule};
+    use crate::test_support;
+    use serde_json::json;
+
+    fn schema(fields: &[&str]) -> Schema {
+        let fields: Vec<Field> = fields
+            .iter()
+            .map(|f| Field {
+                collection: "customers".to_owned(),
+                field: f.to_string(),
+            })
+            .collect();
+        let roles = HashMap::from([
+            ("1".to_owned(), "editor_de".to_owned()),
+            ("2".to_owned(), "editor_en".to_owned()),
+        ]);
+        Schema::new(&fields, &roles)
+    }
+
+    fn manifest() -> Manifest {
+        let rule = FieldRule {
+            read: vec![ActionRule {
+                roles: vec!["editor_*".to_owned()],
+                permissions: json!({}),
+                validation: Value::Null,
+                presets: Value::Null,
+            }],
+            ..Default::default()
+        };
+        Manifest {
+            version: "test".to_owned(),
+            collections: BTreeMap::from([(
+                "customers".to_owned(),
+                BTreeMap::from([("address_*".to_owned(), rule)]),
+            )]),
+            presets: None,
+            globs: Globs {
+                fields: BTreeMap::from([(
+                    "customers.address_*".to_owned(),
+                    vec!["address_city".to_owned(), "address_zip".to_owned()],
+                )]),
+                roles: BTreeMap::from([(
+                    "editor_*".to_owned(),
+                    vec!["editor_de".to_owned(), "editor_en".to_owned()],
+                )]),
+            },
+        }
+    }
+
+    #[test]
+    fn expand_resolves_globs_against_the_live_schema() {
+        let schema = schema(&["id", "address_city", "address_zip"]);
+        let rows = expand(&manifest(), &schema, true).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].role, "editor_de");
+        assert_eq!(rows[0].fields, "address_city,address_zip");
+    }
+
+    #[test]
+    fn expand_refuses_frozen_globs_that_match_new_fields() {
+        let schema = schema(&["id", "address_city", "address_zip", "address_secret"]);
+        assert!(expand(&manifest(), &schema, true).is_err());
+        let rows = expand(&manifest(), &schema, false).unwrap();
+        assert_eq!(rows[0].fields, "address_city,address_secret,address_zip");
+    }
+
+    #[test]
+    fn expand_keeps_wildcards_of_collections_without_known_fields() {
+        let permissions = vec![
+            test_support::permission(Some("1"), "directus_users", "read", "first_name"),
+            directus_permissions::Model {
+                fields: None,
+                ..test_support::permission(Some("2"), "directus_users", "delete", "")
+            },
+        ];
+        let schema = schema(&[]);
+        let role_names = HashMap::from([
+            ("1".to_owned(), "editor_de".to_owned()),
+            ("2".to_owned(), "editor_en".to_owned()),
+        ]);
+        let dump = reversed_permissions::Builder::new(&permissions, &[], &role_names).build();
+
+        let rows = expand(&dump, &schema, true).unwrap();
+        let fields: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|row| (row.action.as_str(), row.fields.as_str()))
+            .collect();
+        assert_eq!(fields, vec![("read", "first_name"), ("delete", "*")]);
+    }
+
+    #[test]
+    fn diff_ignores_semantically_equal_rows() {
+        let schema = schema(&["id", "address_city", "address_zip"]);
+        let desired = expand(&manifest(), &schema, true).unwrap();
+        let mut current = desired.clone();
+        current[0].id = Some(1);
+        current[0].fields = "address_zip,address_city".to_owned();
+        current[0].permissions = json!({ "_and": [] });
+        current.remove(1);
+
+        let diff = Diff::new(&current, &desired, &schema);
+        assert!(diff.update.is_empty() && diff.delete.is_empty());
+        assert_eq!(diff.create.len(), 1);
+        assert_eq!(diff.create[0].role, "editor_en");
+    }
+
+    #[test]
+    fn preset_diff_matches_presets_by_role_collection_and_bookmark() {
+        let mut manifest = manifest();
+        let preset: Preset = serde_yaml::from_str(
+            "role: editor_de\ncollection: customers\nbookmark: Open\nfilter: { status: { _eq: open } }\n",
+        )
+        .unwrap();
+        manifest.presets = Some(vec![preset.clone()]);
+        let desired = expand_presets(&manifest, &schema(&["id"])).unwrap();
+        assert_eq!(desired[0].role_id.as_deref(), Some("1"));
+
+        let current = vec![
+            PresetRow {
+                id: Some(7),
+                role_id: Some("1".to_owned()),
+                preset: Preset {
+                    filter: json!({ "_and": [{ "status": { "_eq": "open" } }] }),
+                    ..preset.clone()
+                },
+            },
+            PresetRow {
+                id: Some(8),
+                role_id: None,
+                preset: Preset {
+                    role: None,
+                    ..preset
+                },
+            },
+        ];
+        let diff = PresetDiff::new(&current, &desired);
+        assert!(diff.create.is_empty() && diff.update.is_empty());
+        assert_eq!(diff.delete.len(), 1);
+        assert_eq!(diff.delete[0].id, Some(8));
+    }
+
+    #[test]
+    fn compare_leaves_presets_of_migrated_manifests_alone() {
+        let old = r#"
+version: 0.6.0/pre-alpha
+collections:
+  customers:
+    address_*:
+      read:
+      - roles: [editor_*]
+        permissions: {}
+globs:
+  fields:
+    customers.address_*: [address_city, address_zip]
+  roles:
+    editor_*: [editor_de, editor_en]
+"#;
+        let manifest: Manifest =
+            serde_yaml::from_value(migrate(serde_yaml::from_str(old).unwrap()).unwrap()).unwrap();
+        assert_eq!(manifest.presets, None);
+
+        let schema = schema(&["id", "address_city", "address_zip"]);
+        let mut current = expand(&manifest, &schema, true).unwrap();
+        for (id, row) in current.iter_mut().enumerate() {
+            row.id = Some(id as i32);
+            row.presets = json!({ "country": "DE" });
+        }
+        let shared: Preset = serde_yaml::from_str(
+            "collection: customers
+bookmark: Open
+",
+        )
+        .unwrap();
+        let current_presets = vec![PresetRow {
+            id: Some(1),
+            role_id: None,
+            preset: shared,
+        }];
+
+        let diff = compare(&manifest, &schema, true, &current, &current_presets).unwrap();
+        assert!(diff.is_empty());
+    }
+}
@@ -0,0 +1,357 @@
+//! Check and enforce how long data is kept.
+//!
+//! Rules are declared per collection in a yaml file:
+//!
+//! ```yaml
+//! orders: 10y from date_created
+//! directus_activity: 90d
+//! directus_sessions: expired
+//! ```
+//!
+//! Without `from`, the field with the `date-created` (or `date-updated`)
+//! special is used. System collections have known timestamp fields.
+use crate::classify::fetch_field_meta;
+use crate::cli::{OutputFormat, Retention};
+use crate::entities::directus_fields;
+use crate::output;
+use crate::utils;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::{Alias, Expr, Query};
+use sea_orm::{ConnectionTrait, Database, DbBackend, FromQueryResult, Statement, TransactionTrait};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Timestamp fields of system collections, which have no `special` in `directus_fields`.
+const SYSTEM_TIMESTAMPS: &[(&str, &str)] = &[
+    ("directus_activity", "timestamp"),
+    ("directus_notifications", "timestamp"),
+    ("directus_shares", "date_created"),
+    ("directus_users", "last_access"),
+];
+
+/// Collections whose rows are people. Deleting them leaves their references
+/// behind, so they are erased one by one with `gdpr erase`.
+const NOT_PURGEABLE: &[&str] = &["directus_users"];
+
+/// Expiry fields for `expired` rules.
+const EXPIRY_FIELDS: &[(&str, &str)] = &[
+    ("directus_sessions", "expires"),
+    ("directus_shares", "date_end"),
+];
+
+/// 🏡 Handle logic for the `retention` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `retention` specific options.
+pub async fn retention_entrypoint(args: &RetentionOptions) -> anyhow::Result<()> {
+    let rules = read_rules(&args.file)?;
+    let db = Database::connect(&args.url).await?;
+    let meta = fetch_field_meta(&db).await?;
+    let backend = db.get_database_backend();
+    let now = Utc::now();
+
+    let mut checks = Vec::new();
+    for (collection, rule) in &rules {
+        let field = rule.field(collection, &meta)?;
+        let cutoff = rule.cutoff(now);
+        let statement = count_statement(backend, collection, &field, cutoff);
+        let rows = JsonValue::find_by_statement(statement)
+            .one(&db)
+            .await?
+            .and_then(|row| row.get("count").and_then(count))
+            .unwrap_or_default();
+        checks.push(Check {
+            collection: collection.to_owned(),
+            field,
+            cutoff: cutoff.to_rfc3339(),
+            rows,
+            purged: false,
+        });
+    }
+
+    let violations: Vec<&Check> = checks.iter().filter(|c| c.rows > 0).collect();
+    if args.purge && !violations.is_empty() {
+        if let Some(check) = violations.iter().find(|c| !can_purge(&c.collection)) {
+            anyhow::bail!(
+                "`{}` can't be purged, other collections reference it. Use `gdpr erase` for each item.",
+                check.collection
+            );
+        }
+        let question = format!(
+            "Delete {} row(s) from {}?",
+            violations.iter().map(|c| c.rows).sum::<u64>(),
+            violations
+                .iter()
+                .map(|c| c.collection.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+        if !(args.yes || utils::confirm(&question)?) {
+            eprintln!("Nothing applied.");
+            return output::print(&args.output, &checks);
+        }
+
+        let txn = db.begin().await?;
+        for check in checks.iter_mut().filter(|c| c.rows > 0) {
+            let cutoff = rules[&check.collection].cutoff(now);
+            txn.execute(purge_statement(
+                backend,
+                &check.collection,
+                &check.field,
+                cutoff,
+            ))
+            .await?;
+            check.purged = true;
+        }
+        txn.commit().await?;
+    }
+
+    output::print(&args.output, &checks)
+}
+
+#[derive(Debug)]
+pub struct RetentionOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub file: PathBuf,
+    pub purge: bool,
+    pub yes: bool,
+}
+
+impl From<Retention> for RetentionOptions {
+    fn from(retention: Retention) -> Self {
+        RetentionOptions {
+            url: retention.global_args.url,
+            output: retention.global_args.output,
+            file: retention.file,
+            purge: retention.purge,
+            yes: retention.yes,
+        }
+    }
+}
+
+/// How long items of a collection are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// Items older than `max_age`, measured by `field` or the detected timestamp
+    Age {
+        max_age: chrono::Duration,
+        field: Option<String>,
+    },
+    /// Items whose expiry date has passed
+    Expired,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "expired" {
+            return Ok(Rule::Expired);
+        }
+        let (duration, field) = match s.split_once(" from ") {
+            Some((duration, field)) => (duration, Some(field.trim().to_owned())),
+            None => (s, None),
+        };
+        Ok(Rule::Age {
+            max_age: utils::parse_duration(duration)?,
+            field,
+        })
+    }
+}
+
+impl Rule {
+    /// Resolve the timestamp field of `collection`.
+    pub fn field(
+        &self,
+        collection: &str,
+        meta: &[directus_fields::Model],
+    ) -> anyhow::Result<String> {
+        let field = match self {
+            Rule::Age {
+                field: Some(field), ..
+            } => Some(field.to_owned()),
+            Rule::Age { field: None, .. } => timestamp_field(collection, meta),
+            Rule::Expired => known(EXPIRY_FIELDS, collection),
+        };
+        field.with_context(|| {
+            format!(
+                "Can't find a timestamp field in `{}`. Use e.g. `1y from date_created`.",
+                collection
+            )
+        })
+    }
+
+    /// Items with a timestamp before the cutoff violate the rule.
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Rule::Age { max_age, .. } => now - *max_age,
+            Rule::Expired => now,
+        }
+    }
+}
+
+/// The field of a collection that tells the age of an item.
+///
+/// System collections have known timestamp fields. Otherwise the field with
+/// the `date-created` (or `date-updated`) special is used.
+pub fn timestamp_field(collection: &str, meta: &[directus_fields::Model]) -> Option<String> {
+    let special = |name: &str| {
+        meta.iter()
+            .find(|m| {
+                m.collection == collection
+                    && m.special
+                        .as_deref()
+                        .is_some_and(|s| s.split(',').any(|s| s == name))
+            })
+            .map(|m| m.field.to_owned())
+    };
+    known(SYSTEM_TIMESTAMPS, collection)
+        .or_else(|| special("date-created"))
+        .or_else(|| special("date-updated"))
+}
+
+fn known(list: &[(&str, &str)], collection: &str) -> Option<String> {
+    list.iter()
+        .find(|(c, _)| *c == collection)
+        .map(|(_, f)| f.to_string())
+}
+
+/// The result of a rule.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Check {
+    pub collection: String,
+    pub field: String,
+    pub cutoff: String,
+    /// Rows older than `cutoff`
+    pub rows: u64,
+    pub purged: bool,
+}
+
+/// Check if rows of `collection` may be deleted with a plain `DELETE`.
+pub fn can_purge(collection: &str) -> bool {
+    !NOT_PURGEABLE.contains(&collection)
+}
+
+/// Read rules by collection from a yaml file.
+pub fn read_rules(path: &Path) -> anyhow::Result<BTreeMap<String, Rule>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Can't read retention rules {}", path.display()))?;
+    parse_rules(&content)
+}
+
+pub fn parse_rules(content: &str) -> anyhow::Result<BTreeMap<String, Rule>> {
+    let raw: BTreeMap<String, String> = serde_yaml::from_str(content)?;
+    raw.into_iter()
+        .map(|(collection, rule)| {
+            let rule = Rule::from_str(&rule)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("Invalid retention rule for `{}`", collection))?;
+            Ok((collection, rule))
+        })
+        .collect()
+}
+
+/// Postgres returns `COUNT(*)` as a number, MySQL may return it as a string.
+fn count(value: &JsonValue) -> Option<u64> {
+    match value {
+        JsonValue::Number(n) => n.as_u64(),
+        JsonValue::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn count_statement(
+    backend: DbBackend,
+    collection: &str,
+    field: &str,
+    cutoff: DateTime<Utc>,
+) -> Statement {
+    backend.build(
+        Query::select()
+            .expr_as(Expr::cust("COUNT(*)"), Alias::new("count"))
+            .from(Alias::new(collection))
+            .and_where(Expr::col(Alias::new(field)).lt(cutoff)),
+    )
+}
+
+fn purge_statement(
+    backend: DbBackend,
+    collection: &str,
+    field: &str,
+    cutoff: DateTime<Utc>,
+) -> Statement {
+    backend.build(
+        Query::delete()
+            .from_table(Alias::new(collection))
+            .and_where(Expr::col(Alias::new(field)).lt(cutoff)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn parse_rules_supports_ages_fields_and_expiry() {
+        let rules = parse_rules(
+            "orders: 10y from date_created\ndirectus_activity: 90d\ndirectus_sessions: expired\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules["orders"],
+            Rule::Age {
+                max_age: chrono::Duration::days(3650),
+                field: Some("date_created".to_owned())
+            }
+        );
+        assert_eq!(rules["directus_sessions"], Rule::Expired);
+        assert!(parse_rules("orders: forever\n").is_err());
+    }
+
+    #[test]
+    fn field_uses_system_timestamps_and_specials() {
+        let rules = parse_rules("directus_activity: 90d\narticles: 1y\ntags: 1y\n").unwrap();
+        let meta = vec![test_support::field_meta(
+            "articles",
+            "created_on",
+            Some("date-created,cast-timestamp"),
+        )];
+        assert_eq!(
+            rules["directus_activity"]
+                .field("directus_activity", &meta)
+                .unwrap(),
+            "timestamp"
+        );
+        assert_eq!(
+            rules["articles"].field("articles", &meta).unwrap(),
+            "created_on"
+        );
+        assert!(rules["tags"].field("tags", &meta).is_err());
+    }
+
+    #[test]
+    fn purge_statement_deletes_rows_before_cutoff() {
+        let cutoff = DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            purge_statement(DbBackend::Postgres, "directus_sessions", "expires", cutoff)
+                .to_string(),
+            r#"DELETE FROM "directus_sessions" WHERE "expires" < '2023-01-01 00:00:00 +00:00'"#
+        );
+    }
+
+    #[test]
+    fn users_can_not_be_purged() {
+        assert!(can_purge("directus_sessions"));
+        assert!(!can_purge("directus_users"));
+    }
+}
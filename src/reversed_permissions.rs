@@ -3,146 +3,395 @@
 // and a potential `output` module
 
 //! Build a collection of Permission Rules
+use crate::directus::{self, Field};
 use crate::dump;
 use crate::entities::directus_permissions;
-use indexmap::IndexMap;
+use crate::graph::canonical::canonicalize;
+use crate::graph::filter::is_equivalent_json;
+use crate::manifest::{Globs, Manifest};
+use crate::wildcard;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-pub type CollectionRules = HashMap<String, FieldRule>;
+/// All rules of a collection by field. Keys can be a CSV of fields or globs.
+pub type FieldRules = BTreeMap<String, FieldRule>;
+
+/// All rules by collection.
+pub type CollectionRules = BTreeMap<String, FieldRules>;
+
+/// The `permissions`, `validation` and `presets` of a permission row.
+type RowRules = (Value, Value, Value);
 
 /// Represent access permissions for a resource in Directus.
 ///
 /// `ResourceRule` represents all allowed actions on a resource:
 /// 'create', 'read', 'update', 'delete', and 'share'.
 /// Each action is defined by a unique `ActionRule`.
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
 pub struct FieldRule {
-    create: Vec<ActionRule>,
-    read: Vec<ActionRule>,
-    update: Vec<ActionRule>,
-    delete: Vec<ActionRule>,
-    share: Vec<ActionRule>,
+    pub create: Vec<ActionRule>,
+    pub read: Vec<ActionRule>,
+    pub update: Vec<ActionRule>,
+    pub delete: Vec<ActionRule>,
+    pub share: Vec<ActionRule>,
 }
 
 /// Represent an action (or verb) on a Directus Resource.
 ///
-/// An `ActionRule` contains roles (UUID), permissions, and
+/// An `ActionRule` contains role names, permissions, and
 /// validation rules. Both permissions and validation are
 /// potentially deeply nested JSON objects, chaining multiple
 /// rules with logical AND/OR operators.
 ///
 /// # Properties
 /// * `roles` - A vector of rules, potentially deduplicated if the same
-/// permissions and validation are valid for multiple roles.
+///   permissions and validation are valid for multiple roles.
 /// * `permissions` - A deeply nested JSON Object reflecting WHO can
-/// read a resource or WHICH resources are in scope of that rule.
-/// Can potentially contain references to other fields.
+///   read a resource or WHICH resources are in scope of that rule.
+///   Can potentially contain references to other fields.
 /// * `validation` - A deeply nested JSON Object reflecting HOW
-/// a resource can look.
-#[derive(Clone, Serialize, Deserialize, Debug)]
+///   a resource can look.
+/// * `presets` - Default values Directus forces on created or
+///   updated items.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ActionRule {
-    roles: Vec<String>,
-    // TODO: use serde json
-    permissions: Value,
-    validation: Value,
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Value,
+    #[serde(default)]
+    pub validation: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub presets: Value,
 }
 
 impl FieldRule {
-    fn new() -> Self {
-        Self {
-            create: Vec::new(),
-            read: Vec::new(),
-            update: Vec::new(),
-            delete: Vec::new(),
-            share: Vec::new(),
-        }
-    }
-
     pub fn iter_keys() -> impl Iterator<Item = &'static str> {
         ["create", "read", "update", "delete", "share"].into_iter()
     }
-}
 
-// impl From<dump::Request> for FieldRule {
-//     fn from(resource: dump::Request) -> Self {
-//         println!("test")
-//     }
-// }
+    /// Get the rules of an action like `read`.
+    pub fn action(&self, key: &str) -> Option<&Vec<ActionRule>> {
+        match key {
+            "create" => Some(&self.create),
+            "read" => Some(&self.read),
+            "update" => Some(&self.update),
+            "delete" => Some(&self.delete),
+            "share" => Some(&self.share),
+            _ => None,
+        }
+    }
 
-pub struct Builder {
-    requests: Vec<String>,
-    context: String,
-}
+    fn action_mut(&mut self, key: &str) -> Option<&mut Vec<ActionRule>> {
+        match key {
+            "create" => Some(&mut self.create),
+            "read" => Some(&mut self.read),
+            "update" => Some(&mut self.update),
+            "delete" => Some(&mut self.delete),
+            "share" => Some(&mut self.share),
+            _ => None,
+        }
+    }
 
-impl Builder {
-    fn request(resources: &[dump::Request]) -> Self {
-        Self {
-            requests: vec![],
-            context: "ha".to_owned(),
+    /// Add a role's rule or append the role to a semantically equal rule.
+    fn add(&mut self, action: &str, role: &str, row: &RowRules) {
+        let (permissions, validation, presets) = row;
+        let rules = match self.action_mut(action) {
+            Some(rules) => rules,
+            None => return,
+        };
+        match rules.iter_mut().find(|rule| {
+            is_equivalent_json(&rule.permissions, permissions)
+                && is_equivalent_json(&rule.validation, validation)
+                && &rule.presets == presets
+        }) {
+            Some(rule) if !rule.roles.iter().any(|r| r == role) => rule.roles.push(role.to_owned()),
+            Some(_) => (),
+            None => rules.push(ActionRule {
+                roles: vec![role.to_owned()],
+                permissions: canonicalize(permissions),
+                validation: canonicalize(validation),
+                presets: presets.clone(),
+            }),
         }
     }
+}
 
-    /// Organize the database permissions into GDPR's base format.
-    ///
+/// Organize the database permissions into GDPR's base format.
+///
+/// Roles with equal permissions and validation are deduplicated.
+/// Lists of roles and fields are compressed to globs like `address_*`, but
+/// only if a glob matches exactly the listed roles or fields. The manifest
+/// records what each glob matched, see [`Globs`].
+pub struct Builder<'a> {
+    permissions: &'a [directus_permissions::Model],
+    fields: &'a [Field],
+    role_names: &'a HashMap<String, String>,
+    requests: &'a [dump::Request],
+}
+
+impl<'a> Builder<'a> {
     /// # Arguments
     ///
-    /// * `args` - A reference to the user's settings used by the dump command.
     /// * `permissions` - A reference to a vector of `directus_permissions::Model` objects
     ///   containing the permissions to be organized.
-    pub fn build(
-        resources: &[dump::Request],
-        input: &Vec<directus_permissions::Model>,
-    ) -> CollectionRules {
-        let mut dump_all = HashMap::new();
-        for field_name in resources {
-            let mut field = FieldRule::new();
-
-            // loop over each action
+    /// * `fields` - All known fields, used to expand and compress fields.
+    /// * `role_names` - A map of role ids to role names.
+    pub fn new(
+        permissions: &'a [directus_permissions::Model],
+        fields: &'a [Field],
+        role_names: &'a HashMap<String, String>,
+    ) -> Self {
+        Self {
+            permissions,
+            fields,
+            role_names,
+            requests: &[],
+        }
+    }
+
+    /// Only include fields that match the user's requested resources.
+    pub fn requests(mut self, requests: &'a [dump::Request]) -> Self {
+        self.requests = requests;
+        self
+    }
+
+    pub fn build(&self) -> Manifest {
+        let mut by_field: BTreeMap<String, BTreeMap<String, FieldRule>> = BTreeMap::new();
+        for permission in self.permissions {
+            let role = directus::role_name(permission.role.as_deref(), self.role_names);
+            let rules: RowRules = (
+                permission.permissions.clone().unwrap_or(Value::Null),
+                permission.validation.clone().unwrap_or(Value::Null),
+                permission.presets.clone().unwrap_or(Value::Null),
+            );
+
+            for field in self.expand_fields(permission) {
+                if !self.requests.is_empty()
+                    && !self
+                        .requests
+                        .iter()
+                        .any(|r| r.matches(&permission.collection, &field))
+                {
+                    continue;
+                }
+                by_field
+                    .entry(permission.collection.to_owned())
+                    .or_default()
+                    .entry(field)
+                    .or_default()
+                    .add(&permission.action, &role, &rules);
+            }
+        }
+
+        let mut all_roles: Vec<String> = self.role_names.values().cloned().collect();
+        all_roles.push(directus::PUBLIC_ROLE.to_owned());
+
+        let mut globs = Globs::default();
+        let collections = by_field
+            .into_iter()
+            .map(|(collection, fields)| {
+                let rules = self.group_fields(&collection, fields, &all_roles, &mut globs);
+                (collection, rules)
+            })
+            .collect();
+
+        Manifest::new(collections, globs)
+    }
+
+    /// Resolve the `fields` column. A wildcard `*` means all known fields.
+    /// So does `NULL`, which Directus uses for actions like `delete`.
+    ///
+    /// System collections have no known fields, their wildcards stay `*`.
+    fn expand_fields(&self, permission: &directus_permissions::Model) -> Vec<String> {
+        match permission.fields.as_deref() {
+            Some("*") | None => match self.known_fields(&permission.collection) {
+                known if known.is_empty() => vec!["*".to_owned()],
+                known => known,
+            },
+            Some(csv) => csv.split(',').map(|f| f.trim().to_owned()).collect(),
+        }
+    }
+
+    fn known_fields(&self, collection: &str) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|f| f.collection == collection)
+            .map(|f| f.field.to_owned())
+            .collect()
+    }
+
+    /// Merge fields with equal rules and compress their names and roles.
+    fn group_fields(
+        &self,
+        collection: &str,
+        fields: BTreeMap<String, FieldRule>,
+        all_roles: &[String],
+        globs: &mut Globs,
+    ) -> FieldRules {
+        let mut groups: Vec<(FieldRule, Vec<String>)> = Vec::new();
+        for (field, mut rule) in fields {
             for key in FieldRule::iter_keys() {
-                let role_permission = input
-                    .iter()
-                    .filter(|permission| permission.action == key)
-                    .map(|permission| {
-                        let mut map = IndexMap::new();
-                        map.insert(
-                            "roles".to_owned(),
-                            Value::String(permission.role.clone().unwrap_or("public".to_owned())),
-                        );
-                        map.insert(
-                            "permissions".to_owned(),
-                            permission.permissions.clone().unwrap_or(Value::Null),
-                        );
-                        map.insert(
-                            "validation".to_owned(),
-                            permission.validation.clone().unwrap_or(Value::Null),
-                        );
-                        map
-                    })
-                    .collect::<Vec<_>>();
-
-                for permission in role_permission {
-                    let dump_operation = ActionRule {
-                        roles: vec![permission["roles"].as_str().unwrap().to_owned()],
-                        permissions: permission["permissions"].clone(),
-                        validation: permission["validation"].clone(),
-                    };
-
-                    match key {
-                        "create" => field.create.push(dump_operation),
-                        "read" => field.read.push(dump_operation),
-                        "update" => field.update.push(dump_operation),
-                        "delete" => field.delete.push(dump_operation),
-                        "share" => field.share.push(dump_operation),
-                        _ => (),
+                if let Some(action_rules) = rule.action_mut(key) {
+                    for action_rule in action_rules.iter_mut() {
+                        let roles = action_rule.roles.iter().map(String::as_str).collect();
+                        action_rule.roles = wildcard::compress_with(roles, all_roles);
+                        for pattern in action_rule.roles.iter().filter(|r| r.contains('*')) {
+                            let mut matches = wildcard::find_with(pattern, all_roles);
+                            matches.sort();
+                            globs.roles.insert(pattern.to_owned(), matches);
+                        }
                     }
+                    action_rules.sort_by(|a, b| a.roles.cmp(&b.roles));
                 }
             }
+            match groups.iter_mut().find(|(r, _)| r == &rule) {
+                Some((_, names)) => names.push(field),
+                None => groups.push((rule, vec![field])),
+            }
+        }
+
+        let known_fields = self.known_fields(collection);
+        groups
+            .into_iter()
+            .map(|(rule, names)| {
+                if names.iter().any(|name| name == "*") {
+                    return ("*".to_owned(), rule);
+                }
+                let all_known = !known_fields.is_empty()
+                    && known_fields.len() == names.len()
+                    && known_fields.iter().all(|f| names.contains(f));
+                let patterns = if all_known {
+                    vec!["*".to_owned()]
+                } else {
+                    let names = names.iter().map(String::as_str).collect();
+                    wildcard::compress_with(names, &known_fields)
+                };
+                for pattern in patterns.iter().filter(|p| p.contains('*')) {
+                    let mut matches = wildcard::find_with(pattern, &known_fields);
+                    matches.sort();
+                    globs
+                        .fields
+                        .insert(format!("{}.{}", collection, pattern), matches);
+                }
+                (patterns.join(","), rule)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::json;
 
-            // dump_all.insert(field_name.to_owned(), field);
+    fn permission(
+        role: &str,
+        action: &str,
+        fields: &str,
+        permissions: Value,
+    ) -> directus_permissions::Model {
+        directus_permissions::Model {
+            permissions: Some(permissions),
+            ..test_support::permission(Some(role), "customers", action, fields)
         }
+    }
+
+    fn fields(names: &[&str]) -> Vec<Field> {
+        names
+            .iter()
+            .map(|name| Field {
+                collection: "customers".to_owned(),
+                field: name.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn build_dedupes_roles_with_equal_rules() {
+        let permissions = vec![
+            permission(
+                "1",
+                "read",
+                "name",
+                json!({ "_and": [{ "id": { "_eq": "5" } }] }),
+            ),
+            permission("2", "read", "name", json!({ "id": { "_in": [5] } })),
+        ];
+        let fields = fields(&["id", "name"]);
+        let roles = HashMap::from([
+            ("1".to_owned(), "editor".to_owned()),
+            ("2".to_owned(), "author".to_owned()),
+        ]);
+        let result = Builder::new(&permissions, &fields, &roles).build();
+        let read = &result.collections["customers"]["name"].read;
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].roles, vec!["author", "editor"]);
+    }
+
+    #[test]
+    fn build_compresses_fields_only_if_exact() {
+        let permissions = vec![
+            permission(
+                "1",
+                "read",
+                "address_street,address_city,address_zip",
+                json!({}),
+            ),
+            permission("2", "read", "*", json!({})),
+        ];
+        let roles = HashMap::from([
+            ("1".to_owned(), "editor".to_owned()),
+            ("2".to_owned(), "admin".to_owned()),
+        ]);
+
+        let exact = fields(&["address_street", "address_city", "address_zip", "name"]);
+        let result = Builder::new(&permissions, &exact, &roles).build();
+        let keys: Vec<&String> = result.collections["customers"].keys().collect();
+        assert_eq!(keys, vec!["address_*", "name"]);
+        assert_eq!(
+            result.globs.fields["customers.address_*"],
+            vec!["address_city", "address_street", "address_zip"]
+        );
+
+        let secret = fields(&[
+            "address_street",
+            "address_city",
+            "address_zip",
+            "address_secret",
+        ]);
+        let result = Builder::new(&permissions, &secret, &roles).build();
+        let keys: Vec<&String> = result.collections["customers"].keys().collect();
+        assert_eq!(
+            keys,
+            vec!["address_city,address_street,address_zip", "address_secret"]
+        );
+    }
+
+    #[test]
+    fn build_keeps_wildcards_of_collections_without_known_fields() {
+        let users = |role: &str, action: &str, fields: Option<&str>| directus_permissions::Model {
+            collection: "directus_users".to_owned(),
+            fields: fields.map(str::to_owned),
+            ..permission(role, action, "", json!({}))
+        };
+        let permissions = vec![
+            users("1", "read", Some("first_name")),
+            users("2", "read", Some("*")),
+            users("2", "delete", None),
+        ];
+        let roles = HashMap::from([
+            ("1".to_owned(), "editor".to_owned()),
+            ("2".to_owned(), "admin".to_owned()),
+        ]);
 
-        return dump_all;
+        let result = Builder::new(&permissions, &[], &roles).build();
+        let users = &result.collections["directus_users"];
+        assert_eq!(users["first_name"].read[0].roles, vec!["editor"]);
+        assert_eq!(users["*"].read[0].roles, vec!["admin"]);
+        assert_eq!(users["*"].delete[0].roles, vec!["admin"]);
+        assert!(result.globs.fields.is_empty());
     }
 }
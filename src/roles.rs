@@ -0,0 +1,418 @@
+//! Derive roles from existing roles.
+//!
+//! `clone` copies a role and all its permissions. `merge` unions the
+//! permissions of two roles into a new or an existing role. Rows for the same
+//! collection and action are combined if their filters are equal. Otherwise
+//! they conflict, the first role's row is kept and the conflict reported.
+//! Conflicting merges are only applied with `--keep-first`.
+//!
+//! The preview is the diff of the target role's permission graph.
+use crate::cli::{OutputFormat, RoleCommand};
+use crate::directus::{self, Field};
+use crate::entities::{directus_permissions, directus_roles};
+use crate::graph::filter::is_equivalent_json;
+use crate::graph::{build_graph, GraphDiff};
+use crate::output;
+use crate::replace::{self, json_or_null, Diff, PermissionRow, Schema};
+use crate::seed::Role;
+use crate::utils;
+use sea_orm::prelude::Uuid;
+use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, EntityTrait, TransactionTrait};
+use serde::Serialize;
+
+/// 🏡 Handle logic for the `role` command.
+///
+/// Print the plan and apply it after confirmation.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `role` specific options.
+pub async fn role_entrypoint(args: &RoleOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let schema = Schema::new(&fields, &directus::fetch_role_names(&db).await?);
+    let roles = directus_roles::Entity::find().all(&db).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+
+    let derive = Derive {
+        roles: &roles,
+        permissions: &permissions,
+        fields: &fields,
+        schema: &schema,
+    };
+    let (plan, keep_first) = match &args.action {
+        RoleAction::Clone { source, name } => (derive.clone(source, name)?, false),
+        RoleAction::Merge {
+            a,
+            b,
+            into,
+            keep_first,
+        } => (derive.merge(a, b, into)?, *keep_first),
+    };
+    output::print(&args.output, &plan)?;
+
+    if args.dry_run || (!plan.created && plan.permissions.is_empty()) {
+        return Ok(());
+    }
+    if !plan.conflicts.is_empty() && !keep_first {
+        anyhow::bail!(
+            "{} conflict(s), nothing applied. Pass `--keep-first` to keep the rows of the first role.",
+            plan.conflicts.len()
+        );
+    }
+    let question = match plan.created {
+        true => format!(
+            "Create role `{}` with {} permission(s)?",
+            plan.role.name,
+            plan.permissions.len()
+        ),
+        false => format!("Apply {} change(s)?", plan.permissions.len()),
+    };
+    match args.yes || utils::confirm(&question)? {
+        true => apply(&db, &plan).await,
+        false => {
+            eprintln!("Nothing applied.");
+            Ok(())
+        }
+    }
+}
+
+/// Create the role if needed, then change its permissions, in one transaction.
+async fn apply(db: &DatabaseConnection, plan: &RolePlan) -> anyhow::Result<()> {
+    let txn = db.begin().await?;
+    if plan.created {
+        plan.role.model().insert(&txn).await?;
+    }
+    replace::apply_in(&txn, &plan.permissions).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct RoleOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub dry_run: bool,
+    pub yes: bool,
+    pub action: RoleAction,
+}
+
+#[derive(Debug)]
+pub enum RoleAction {
+    Clone {
+        source: String,
+        name: String,
+    },
+    Merge {
+        a: String,
+        b: String,
+        into: String,
+        keep_first: bool,
+    },
+}
+
+impl From<RoleCommand> for RoleOptions {
+    fn from(command: RoleCommand) -> Self {
+        match command {
+            RoleCommand::Clone(clone) => RoleOptions {
+                url: clone.global_args.url,
+                output: clone.global_args.output,
+                dry_run: clone.dry_run,
+                yes: clone.yes,
+                action: RoleAction::Clone {
+                    source: clone.source,
+                    name: clone.name,
+                },
+            },
+            RoleCommand::Merge(merge) => RoleOptions {
+                url: merge.global_args.url,
+                output: merge.global_args.output,
+                dry_run: merge.dry_run,
+                yes: merge.yes,
+                action: RoleAction::Merge {
+                    a: merge.a,
+                    b: merge.b,
+                    into: merge.into,
+                    keep_first: merge.keep_first,
+                },
+            },
+        }
+    }
+}
+
+/// Rows of both roles for the same collection and action with different
+/// filters. Only `kept` ends up in the merged role.
+#[derive(Serialize, Debug)]
+pub struct Conflict {
+    pub collection: String,
+    pub action: String,
+    pub kept: PermissionRow,
+    pub dropped: PermissionRow,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RolePlan {
+    pub role: Role,
+    /// `false` if the role exists already
+    pub created: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Conflict>,
+    /// Changes of the role's permission graph
+    pub changes: GraphDiff,
+    #[serde(skip)]
+    pub permissions: Diff,
+}
+
+/// Everything needed to derive roles.
+pub struct Derive<'a> {
+    pub roles: &'a [directus_roles::Model],
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [Field],
+    pub schema: &'a Schema,
+}
+
+impl<'a> Derive<'a> {
+    /// Copy `source` and its permissions to a new role `name`.
+    pub fn clone(&self, source: &str, name: &str) -> anyhow::Result<RolePlan> {
+        let source = self.find(source)?;
+        if self.roles.iter().any(|role| role.name == name) {
+            anyhow::bail!("Role `{}` exists already", name);
+        }
+        let role = Role {
+            id: Some(Uuid::new_v4()),
+            name: name.to_owned(),
+            ..Role::from_model(source)
+        };
+        let desired = self.rows(source);
+
+        self.plan(role, true, desired, Vec::new())
+    }
+
+    /// Union the permissions of `a` and `b` into `into`.
+    ///
+    /// A new role `into` gets the settings of `a`. An existing role `into`
+    /// keeps its settings, but loses all permissions that neither `a` nor `b`
+    /// have.
+    pub fn merge(&self, a: &str, b: &str, into: &str) -> anyhow::Result<RolePlan> {
+        let (a, b) = (self.find(a)?, self.find(b)?);
+        let (role, created) = match self.roles.iter().find(|role| role.name == into) {
+            Some(model) => (Role::from_model(model), false),
+            None => {
+                let role = Role {
+                    id: Some(Uuid::new_v4()),
+                    name: into.to_owned(),
+                    ..Role::from_model(a)
+                };
+                (role, true)
+            }
+        };
+
+        let mut desired = self.rows(a);
+        let mut conflicts = Vec::new();
+        for row in self.rows(b) {
+            match desired
+                .iter_mut()
+                .find(|d| d.collection == row.collection && d.action == row.action)
+            {
+                Some(kept) if has_same_rules(kept, &row) => {
+                    // `*` covers fields the schema doesn't know yet, keep it.
+                    if kept.fields != "*" && row.fields != "*" {
+                        let mut fields =
+                            self.schema.field_set(&kept.collection, Some(&kept.fields));
+                        fields.extend(self.schema.field_set(&row.collection, Some(&row.fields)));
+                        kept.fields = self.schema.fields_column(&kept.collection, fields);
+                    } else {
+                        kept.fields = "*".to_owned();
+                    }
+                }
+                Some(kept) => conflicts.push(Conflict {
+                    collection: row.collection.to_owned(),
+                    action: row.action.to_owned(),
+                    kept: kept.clone(),
+                    dropped: row,
+                }),
+                None => desired.push(row),
+            }
+        }
+
+        self.plan(role, created, desired, conflicts)
+    }
+
+    fn find(&self, name: &str) -> anyhow::Result<&'a directus_roles::Model> {
+        match self.roles.iter().find(|role| role.name == name) {
+            Some(role) => Ok(role),
+            None => anyhow::bail!("Role `{}` doesn't exist", name),
+        }
+    }
+
+    /// The permissions of a role, without ids.
+    fn rows(&self, role: &directus_roles::Model) -> Vec<PermissionRow> {
+        let role_id = role.id.to_string();
+        self.permissions
+            .iter()
+            .filter(|p| p.role.as_deref() == Some(role_id.as_str()))
+            .map(|p| PermissionRow {
+                id: None,
+                ..PermissionRow::from_model(p, self.schema)
+            })
+            .collect()
+    }
+
+    fn plan(
+        &self,
+        role: Role,
+        created: bool,
+        mut desired: Vec<PermissionRow>,
+        conflicts: Vec<Conflict>,
+    ) -> anyhow::Result<RolePlan> {
+        let role_id = role.id.unwrap_or_default().to_string();
+        for row in desired.iter_mut() {
+            row.role_id = Some(role_id.to_owned());
+            row.role = role.name.to_owned();
+        }
+        let current: Vec<PermissionRow> = self
+            .permissions
+            .iter()
+            .filter(|p| p.role.as_deref() == Some(role_id.as_str()))
+            .map(|p| PermissionRow::from_model(p, self.schema))
+            .collect();
+
+        let graph = |rows: &[PermissionRow]| {
+            build_graph(rows.iter().map(model).collect(), self.fields, &[], &[])
+        };
+        Ok(RolePlan {
+            changes: GraphDiff::new(&graph(&current)?, &graph(&desired)?),
+            permissions: Diff::new(&current, &desired, self.schema),
+            role,
+            created,
+            conflicts,
+        })
+    }
+}
+
+fn has_same_rules(a: &PermissionRow, b: &PermissionRow) -> bool {
+    is_equivalent_json(&a.permissions, &b.permissions)
+        && is_equivalent_json(&a.validation, &b.validation)
+        && a.presets == b.presets
+}
+
+fn model(row: &PermissionRow) -> directus_permissions::Model {
+    directus_permissions::Model {
+        id: row.id.unwrap_or_default(),
+        role: row.role_id.clone(),
+        collection: row.collection.to_owned(),
+        action: row.action.to_owned(),
+        permissions: json_or_null(&row.permissions),
+        validation: json_or_null(&row.validation),
+        presets: json_or_null(&row.presets),
+        fields: Some(row.fields.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn role(id: u128, name: &str) -> directus_roles::Model {
+        directus_roles::Model {
+            id: Uuid::from_u128(id),
+            name: name.to_owned(),
+            icon: "edit".to_owned(),
+            description: None,
+            ip_access: None,
+            enforce_tfa: true,
+            admin_access: false,
+            app_access: true,
+        }
+    }
+
+    fn permission(
+        role: u128,
+        collection: &str,
+        fields: &str,
+        filter: serde_json::Value,
+    ) -> directus_permissions::Model {
+        let role = Uuid::from_u128(role).to_string();
+        directus_permissions::Model {
+            permissions: Some(filter),
+            ..test_support::permission(Some(&role), collection, "read", fields)
+        }
+    }
+
+    fn schema() -> Schema {
+        let role_names = HashMap::from([
+            (Uuid::from_u128(1).to_string(), "Editor".to_owned()),
+            (Uuid::from_u128(2).to_string(), "Reviewer".to_owned()),
+        ]);
+        Schema::new(&[], &role_names)
+    }
+
+    #[test]
+    fn clone_copies_settings_and_permissions() {
+        let roles = vec![role(1, "Editor")];
+        let permissions = vec![permission(1, "articles", "title,body", json!({}))];
+        let schema = schema();
+        let derive = Derive {
+            roles: &roles,
+            permissions: &permissions,
+            fields: &[],
+            schema: &schema,
+        };
+
+        let plan = derive.clone("Editor", "Editor Lite").unwrap();
+        assert!(plan.created && plan.role.enforce_tfa);
+        assert_eq!(plan.role.name, "Editor Lite");
+        assert_eq!(plan.permissions.create.len(), 1);
+        assert_eq!(plan.permissions.create[0].role, "Editor Lite");
+        assert_eq!(plan.changes.added.len(), 3);
+        assert!(derive.clone("Editor", "Editor").is_err());
+    }
+
+    #[test]
+    fn merge_unions_fields_and_reports_conflicts() {
+        let roles = vec![role(1, "Editor"), role(2, "Reviewer")];
+        let own = json!({ "user_created": { "_eq": "$CURRENT_USER" } });
+        let permissions = vec![
+            permission(1, "articles", "title", json!({})),
+            permission(2, "articles", "body", json!({})),
+            permission(1, "comments", "body", json!({})),
+            permission(2, "comments", "body", own),
+            permission(1, "tags", "*", json!({})),
+            permission(2, "tags", "name", json!({})),
+            permission(1, "authors", "name", json!({})),
+            permission(2, "authors", "*", json!({})),
+        ];
+        let schema = schema();
+        let derive = Derive {
+            roles: &roles,
+            permissions: &permissions,
+            fields: &[],
+            schema: &schema,
+        };
+
+        let plan = derive.merge("Editor", "Reviewer", "Staff").unwrap();
+        let created: Vec<(&str, &str)> = plan
+            .permissions
+            .create
+            .iter()
+            .map(|row| (row.collection.as_str(), row.fields.as_str()))
+            .collect();
+        assert_eq!(
+            created,
+            vec![
+                ("articles", "body,title"),
+                ("comments", "body"),
+                ("tags", "*"),
+                ("authors", "*")
+            ]
+        );
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].collection, "comments");
+        assert_eq!(plan.conflicts[0].dropped.role, "Reviewer");
+    }
+}
@@ -0,0 +1,378 @@
+//! Generate a Record of Processing Activities (GDPR Art. 30).
+//!
+//! List every field with personal data, who can access it, where it's sent
+//! and whether it can be deleted by age.
+use crate::classify::{self, Classification};
+use crate::cli::{OutputFormat, Ropa};
+use crate::directus::{self, Field, User};
+use crate::egress::{self, Egress};
+use crate::entities::{directus_fields, directus_permissions};
+use crate::output;
+use crate::retention;
+use crate::reversed_permissions::FieldRule;
+use chrono::Utc;
+use sea_orm::{Database, EntityTrait};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+
+/// 🏡 Handle logic for the `ropa` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `ropa` specific options.
+pub async fn ropa_entrypoint(args: &RopaOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let classifications =
+        classify::classify_tagged(&db, &[], &args.tag, args.rules.as_deref()).await?;
+    let collections = directus::fetch_collections(&db).await?;
+    let sources = Sources {
+        permissions: &directus_permissions::Entity::find().all(&db).await?,
+        fields: &directus::fetch_fields(&db, &collections).await?,
+        meta: &classify::fetch_field_meta(&db).await?,
+        role_names: &directus::fetch_role_names(&db).await?,
+        admin_roles: &directus::fetch_admin_roles(&db).await?,
+        users: &directus::fetch_users(&db).await?,
+        egress: &egress::fetch(&db).await?,
+    };
+    let entries = sources.entries(&classifications);
+
+    match args.output {
+        OutputFormat::Markdown => print!("{}", to_markdown(&entries)),
+        OutputFormat::Html => print!("{}", to_html(&entries)),
+        OutputFormat::Csv => print!("{}", to_csv(&entries)),
+        _ => output::print(&args.output, &entries)?,
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct RopaOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub tag: String,
+    pub rules: Option<PathBuf>,
+}
+
+impl From<Ropa> for RopaOptions {
+    fn from(ropa: Ropa) -> Self {
+        RopaOptions {
+            url: ropa.global_args.url,
+            output: ropa.global_args.output,
+            tag: ropa.tag,
+            rules: ropa.rules,
+        }
+    }
+}
+
+/// A role's access to a field.
+///
+/// `users` is the number of users with the role. Admin roles have all actions.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RoleAccess {
+    pub role: String,
+    pub users: usize,
+    pub actions: Vec<String>,
+}
+
+/// One field with personal data.
+///
+/// * `categories` - The kind of data, i.e. all tags but `pii`
+/// * `recipients` - Hosts that receive the field's collection
+/// * `retention` - A hint whether items can be deleted by age
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RopaEntry {
+    pub collection: String,
+    pub field: String,
+    pub categories: Vec<String>,
+    pub access: Vec<RoleAccess>,
+    pub recipients: Vec<String>,
+    pub retention: String,
+}
+
+/// Everything a RoPA is built from.
+pub struct Sources<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [Field],
+    /// Tells the timestamp fields by their `special`
+    pub meta: &'a [directus_fields::Model],
+    pub role_names: &'a HashMap<String, String>,
+    pub admin_roles: &'a HashSet<String>,
+    pub users: &'a HashMap<String, User>,
+    pub egress: &'a [Egress],
+}
+
+impl<'a> Sources<'a> {
+    pub fn entries(&self, classifications: &[Classification]) -> Vec<RopaEntry> {
+        let mut roles: Vec<(Option<&str>, String)> = self
+            .role_names
+            .iter()
+            .map(|(id, name)| (Some(id.as_str()), name.to_owned()))
+            .collect();
+        roles.push((None, directus::PUBLIC_ROLE.to_owned()));
+        roles.sort_by(|a, b| a.1.cmp(&b.1));
+
+        classifications
+            .iter()
+            .map(|c| RopaEntry {
+                collection: c.collection.to_owned(),
+                field: c.field.to_owned(),
+                categories: c.tags.iter().filter(|t| *t != "pii").cloned().collect(),
+                access: roles
+                    .iter()
+                    .filter_map(|(id, name)| self.access(*id, name, c))
+                    .collect(),
+                recipients: self
+                    .egress
+                    .iter()
+                    .filter(|e| e.payload && e.collections.contains(&c.collection))
+                    .map(|e| e.host.to_owned())
+                    .collect::<BTreeSet<String>>()
+                    .into_iter()
+                    .collect(),
+                retention: self.retention(&c.collection),
+            })
+            .collect()
+    }
+
+    fn access(&self, id: Option<&str>, name: &str, c: &Classification) -> Option<RoleAccess> {
+        let admin = id.is_some_and(|id| self.admin_roles.contains(id));
+        let actions: Vec<String> = FieldRule::iter_keys()
+            .filter(|action| {
+                admin
+                    || directus::granted_fields(
+                        self.permissions,
+                        self.fields,
+                        id,
+                        &c.collection,
+                        action,
+                    )
+                    .contains(&c.field)
+            })
+            .map(str::to_owned)
+            .collect();
+        if actions.is_empty() {
+            return None;
+        }
+        Some(RoleAccess {
+            role: name.to_owned(),
+            users: self
+                .users
+                .values()
+                .filter(|u| id.is_some() && u.role.as_deref() == id)
+                .count(),
+            actions,
+        })
+    }
+
+    fn retention(&self, collection: &str) -> String {
+        match retention::timestamp_field(collection, self.meta) {
+            Some(field) => format!("Items can be deleted by age of `{}`", field),
+            None => "No timestamp field. Items can't be deleted by age.".to_owned(),
+        }
+    }
+}
+
+fn describe_access(access: &[RoleAccess]) -> Vec<String> {
+    access
+        .iter()
+        .map(|a| format!("{} ({} users): {}", a.role, a.users, a.actions.join(", ")))
+        .collect()
+}
+
+const HEADERS: &[&str] = &[
+    "Collection",
+    "Field",
+    "Categories",
+    "Access",
+    "Recipients",
+    "Retention",
+];
+
+/// Cells of a row in `HEADERS` order. Lists are joined with `separator`.
+fn cells(entry: &RopaEntry, separator: &str) -> Vec<String> {
+    vec![
+        entry.collection.to_owned(),
+        entry.field.to_owned(),
+        entry.categories.join(", "),
+        describe_access(&entry.access).join(separator),
+        entry.recipients.join(separator),
+        entry.retention.to_owned(),
+    ]
+}
+
+pub fn to_markdown(entries: &[RopaEntry]) -> String {
+    let escape = |s: String| s.replace('|', "\\|");
+    let mut md = format!(
+        "# Record of Processing Activities\n\nGenerated on {}.\n\n| {} |\n|{}\n",
+        Utc::now().format("%Y-%m-%d"),
+        HEADERS.join(" | "),
+        " --- |".repeat(HEADERS.len())
+    );
+    for entry in entries {
+        let row: Vec<String> = cells(entry, "<br>").into_iter().map(escape).collect();
+        md.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    md
+}
+
+pub fn to_html(entries: &[RopaEntry]) -> String {
+    let escape = |s: &str| {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Record of Processing Activities</title>\n</head>\n<body>\n<h1>Record of Processing Activities</h1>\n<p>Generated on {}.</p>\n<table>\n<tr>{}</tr>\n",
+        Utc::now().format("%Y-%m-%d"),
+        HEADERS
+            .iter()
+            .map(|h| format!("<th>{}</th>", h))
+            .collect::<String>()
+    );
+    for entry in entries {
+        let row: String = cells(entry, "\n")
+            .iter()
+            .map(|cell| format!("<td>{}</td>", escape(cell).replace('\n', "<br>")))
+            .collect();
+        html.push_str(&format!("<tr>{}</tr>\n", row));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// One line per field. Lists in a cell are separated by `; `.
+pub fn to_csv(entries: &[RopaEntry]) -> String {
+    let quote = |s: &str| {
+        if s.contains([',', '"', '\n', ';']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_owned()
+        }
+    };
+    let mut csv = HEADERS.join(",") + "\n";
+    for entry in entries {
+        let row: Vec<String> = cells(entry, "; ").iter().map(|c| quote(c)).collect();
+        csv.push_str(&(row.join(",") + "\n"));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn entry() -> RopaEntry {
+        RopaEntry {
+            collection: "customers".to_owned(),
+            field: "email".to_owned(),
+            categories: vec!["email".to_owned()],
+            access: vec![RoleAccess {
+                role: "editor".to_owned(),
+                users: 2,
+                actions: vec!["read".to_owned(), "update".to_owned()],
+            }],
+            recipients: vec!["crm.example.com".to_owned()],
+            retention: "No timestamp field. Items can't be deleted by age.".to_owned(),
+        }
+    }
+
+    #[test]
+    fn entries_list_roles_users_and_recipients() {
+        let permissions = vec![directus_permissions::Model {
+            id: 1,
+            role: Some("r1".to_owned()),
+            collection: "customers".to_owned(),
+            action: "read".to_owned(),
+            permissions: None,
+            validation: None,
+            presets: None,
+            fields: Some("*".to_owned()),
+        }];
+        let fields = vec![
+            Field {
+                collection: "customers".to_owned(),
+                field: "email".to_owned(),
+            },
+            Field {
+                collection: "customers".to_owned(),
+                field: "date_created".to_owned(),
+            },
+            Field {
+                collection: "customers".to_owned(),
+                field: "signed_up".to_owned(),
+            },
+        ];
+        let meta = vec![test_support::field_meta(
+            "customers",
+            "signed_up",
+            Some("date-created"),
+        )];
+        let role_names = HashMap::from([
+            ("r1".to_owned(), "editor".to_owned()),
+            ("r2".to_owned(), "admin".to_owned()),
+        ]);
+        let users = HashMap::from([(
+            "u1".to_owned(),
+            User {
+                id: "u1".to_owned(),
+                email: None,
+                role: Some("r1".to_owned()),
+            },
+        )]);
+        let sources = Sources {
+            permissions: &permissions,
+            fields: &fields,
+            meta: &meta,
+            role_names: &role_names,
+            admin_roles: &HashSet::from(["r2".to_owned()]),
+            users: &users,
+            egress: &[],
+        };
+        let classification = Classification {
+            collection: "customers".to_owned(),
+            field: "email".to_owned(),
+            tags: BTreeSet::from(["pii".to_owned(), "email".to_owned()]),
+            reasons: vec![],
+        };
+
+        let entries = sources.entries(&[classification]);
+        assert_eq!(entries[0].categories, vec!["email"]);
+        assert_eq!(
+            entries[0].access,
+            vec![
+                RoleAccess {
+                    role: "admin".to_owned(),
+                    users: 0,
+                    actions: FieldRule::iter_keys().map(str::to_owned).collect(),
+                },
+                RoleAccess {
+                    role: "editor".to_owned(),
+                    users: 1,
+                    actions: vec!["read".to_owned()],
+                },
+            ]
+        );
+        // By `special`, not by name
+        assert!(entries[0].retention.contains("`signed_up`"));
+    }
+
+    #[test]
+    fn to_csv_quotes_lists() {
+        let csv = to_csv(&[entry()]);
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "customers,email,email,\"editor (2 users): read, update\",crm.example.com,No timestamp field. Items can't be deleted by age."
+        );
+    }
+
+    #[test]
+    fn to_html_escapes_cells() {
+        let mut entry = entry();
+        entry.field = "<script>".to_owned();
+        assert!(to_html(&[entry]).contains("<td>&lt;script&gt;</td>"));
+    }
+}
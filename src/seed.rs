@@ -0,0 +1,422 @@
+//! Seed roles and their permissions from a yaml file.
+//!
+//! ```yaml
+//! roles:
+//! - name: Editor
+//!   icon: edit
+//!   app_access: true
+//!   enforce_tfa: true
+//!   ip_access: [10.0.0.0/8]
+//!   templates: [app-minimal]
+//!   permissions:
+//!   - collection: articles
+//!     action: read
+//!     fields: title,body
+//!     presets: { status: draft }
+//! ```
+//!
+//! Roles are matched by name and updated in place, so seeding twice changes
+//! nothing. A seeded role gets exactly the permissions of its templates and
+//! its `permissions`, all other rows of the role are deleted. Rows without
+//! `presets` keep their current presets. `permissions` on other than system
+//! collections may only use collections and fields that exist.
+use crate::cli::{OutputFormat, Seed};
+use crate::directus;
+use crate::entities::{directus_permissions, directus_roles};
+use crate::output;
+use crate::replace::{self, Change, Diff, PermissionRow, Schema};
+use crate::templates::{Template, TemplateRow};
+use crate::utils;
+use anyhow::Context;
+use sea_orm::prelude::Uuid;
+use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, EntityTrait, Set, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The icon Directus gives new roles.
+const DEFAULT_ICON: &str = "supervised_user_circle";
+
+/// 🏡 Handle logic for the `seed` command.
+///
+/// Print the plan and apply it after confirmation.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `seed` specific options.
+pub async fn seed_entrypoint(args: &SeedOptions) -> anyhow::Result<()> {
+    let seed = read_seed(&args.file)?;
+    let db = Database::connect(&args.url).await?;
+
+    let schema = Schema::fetch(&db).await?;
+    let roles = directus_roles::Entity::find().all(&db).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let plan = SeedPlan::new(&seed, &roles, &permissions, &schema)?;
+    output::print(&args.output, &plan)?;
+
+    if args.dry_run || plan.is_empty() {
+        return Ok(());
+    }
+    let question = format!("Apply {} change(s)?", plan.len());
+    match args.yes || utils::confirm(&question)? {
+        true => apply(&db, &plan).await,
+        false => {
+            eprintln!("Nothing applied.");
+            Ok(())
+        }
+    }
+}
+
+/// Create and update the roles, then their permissions, in one transaction.
+async fn apply(db: &DatabaseConnection, plan: &SeedPlan) -> anyhow::Result<()> {
+    let txn = db.begin().await?;
+    for role in &plan.roles.create {
+        role.model().insert(&txn).await?;
+    }
+    for change in &plan.roles.update {
+        change.after.model().update(&txn).await?;
+    }
+    replace::apply_in(&txn, &plan.permissions).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct SeedOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub file: PathBuf,
+    pub dry_run: bool,
+    pub yes: bool,
+}
+
+impl From<Seed> for SeedOptions {
+    fn from(seed: Seed) -> Self {
+        SeedOptions {
+            url: seed.global_args.url,
+            output: seed.global_args.output,
+            file: seed.file,
+            dry_run: seed.dry_run,
+            yes: seed.yes,
+        }
+    }
+}
+
+/// A seed file.
+#[derive(Deserialize, Debug)]
+pub struct SeedFile {
+    pub roles: Vec<RoleSeed>,
+}
+
+/// A role with its permissions.
+#[derive(Deserialize, Debug)]
+pub struct RoleSeed {
+    #[serde(flatten)]
+    pub role: Role,
+    /// Built-in templates, see `gdpr template list`
+    #[serde(default)]
+    pub templates: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<TemplateRow>,
+}
+
+/// The settings of a `directus_roles` row.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Role {
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    pub name: String,
+    #[serde(default = "default_icon")]
+    pub icon: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default = "default_app_access")]
+    pub app_access: bool,
+    #[serde(default)]
+    pub admin_access: bool,
+    #[serde(default)]
+    pub enforce_tfa: bool,
+    /// IPs or ranges the role may log in from. Empty allows all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ip_access: Vec<String>,
+}
+
+fn default_icon() -> String {
+    DEFAULT_ICON.to_owned()
+}
+
+fn default_app_access() -> bool {
+    true
+}
+
+impl Role {
+    pub fn from_model(model: &directus_roles::Model) -> Self {
+        Self {
+            id: Some(model.id),
+            name: model.name.to_owned(),
+            icon: model.icon.to_owned(),
+            description: model.description.clone(),
+            app_access: model.app_access,
+            admin_access: model.admin_access,
+            enforce_tfa: model.enforce_tfa,
+            ip_access: model
+                .ip_access
+                .iter()
+                .flat_map(|csv| csv.split(','))
+                .map(str::trim)
+                .filter(|ip| !ip.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+
+    /// Compare everything but the id.
+    fn has_same_settings(&self, other: &Role) -> bool {
+        let without_id = |role: &Role| Role {
+            id: None,
+            ..role.clone()
+        };
+        without_id(self) == without_id(other)
+    }
+
+    pub fn model(&self) -> directus_roles::ActiveModel {
+        directus_roles::ActiveModel {
+            id: Set(self.id.unwrap_or_default()),
+            name: Set(self.name.to_owned()),
+            icon: Set(self.icon.to_owned()),
+            description: Set(self.description.clone()),
+            ip_access: Set(match self.ip_access.is_empty() {
+                true => None,
+                false => Some(self.ip_access.join(",")),
+            }),
+            enforce_tfa: Set(self.enforce_tfa),
+            admin_access: Set(self.admin_access),
+            app_access: Set(self.app_access),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RoleDiff {
+    pub create: Vec<Role>,
+    pub update: Vec<Change<Role>>,
+}
+
+/// All changes needed to seed a file.
+#[derive(Serialize, Debug)]
+pub struct SeedPlan {
+    pub roles: RoleDiff,
+    pub permissions: Diff,
+}
+
+impl SeedPlan {
+    /// Compare the seed with all roles and permissions. New roles get a new id.
+    pub fn new(
+        seed: &SeedFile,
+        roles: &[directus_roles::Model],
+        permissions: &[directus_permissions::Model],
+        schema: &Schema,
+    ) -> anyhow::Result<Self> {
+        let mut names = BTreeSet::new();
+        let mut role_diff = RoleDiff::default();
+        let mut current: Vec<PermissionRow> = Vec::new();
+        let mut desired: Vec<PermissionRow> = Vec::new();
+
+        for role_seed in &seed.roles {
+            let mut role = role_seed.role.clone();
+            if role.name == directus::PUBLIC_ROLE {
+                anyhow::bail!("The public role can't be seeded, use `gdpr replace` instead");
+            }
+            if !names.insert(role_seed.role.name.as_str()) {
+                anyhow::bail!("Role `{}` is seeded twice", role.name);
+            }
+
+            match roles.iter().find(|model| model.name == role.name) {
+                Some(model) => {
+                    let before = Role::from_model(model);
+                    role.id = before.id;
+                    let role_id = model.id.to_string();
+                    current.extend(
+                        permissions
+                            .iter()
+                            .filter(|p| p.role.as_deref() == Some(role_id.as_str()))
+                            .map(|p| PermissionRow::from_model(p, schema)),
+                    );
+                    if !before.has_same_settings(&role) {
+                        role_diff.update.push(Change {
+                            before,
+                            after: role.clone(),
+                        });
+                    }
+                }
+                None => {
+                    role.id = Some(Uuid::new_v4());
+                    role_diff.create.push(role.clone());
+                }
+            }
+
+            let role_id = role.id.unwrap_or_default().to_string();
+            for name in &role_seed.templates {
+                desired.extend(Template::get(name)?.rows(&role.name, &role_id));
+            }
+            let rows: Vec<PermissionRow> = role_seed
+                .permissions
+                .iter()
+                .map(|row| row.row(&role.name, &role_id))
+                .collect();
+            let missing = schema.missing(&rows);
+            if !missing.is_empty() {
+                anyhow::bail!(
+                    "Role `{}` has permissions on missing collections or fields:\n{}",
+                    role.name,
+                    missing.into_iter().collect::<Vec<String>>().join("\n")
+                );
+            }
+            desired.extend(rows);
+        }
+        replace::keep_presets(&current, &mut desired);
+
+        Ok(Self {
+            roles: role_diff,
+            permissions: Diff::new(&current, &desired, schema),
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of changed roles and permission rows.
+    pub fn len(&self) -> usize {
+        self.roles.create.len() + self.roles.update.len() + self.permissions.len()
+    }
+}
+
+/// Read a seed file.
+pub fn read_seed(path: &Path) -> anyhow::Result<SeedFile> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Can't read seed file {}", path.display()))?;
+    Ok(serde_yaml::from_str(&content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directus::Field;
+    use crate::test_support;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    const SEED: &str = r#"
+roles:
+- name: Editor
+  enforce_tfa: true
+  ip_access: [10.0.0.0/8]
+  templates: [insights]
+  permissions:
+  - collection: articles
+    action: read
+    fields: title,body
+    presets: { status: draft }
+  - collection: customers
+    action: read
+- name: Reviewer
+"#;
+
+    fn schema() -> Schema {
+        let fields: Vec<Field> = [
+            ("articles", "title"),
+            ("articles", "body"),
+            ("customers", "name"),
+        ]
+        .iter()
+        .map(|(collection, field)| Field {
+            collection: collection.to_string(),
+            field: field.to_string(),
+        })
+        .collect();
+        Schema::new(&fields, &HashMap::new())
+    }
+
+    fn role(id: u128, name: &str) -> directus_roles::Model {
+        directus_roles::Model {
+            id: Uuid::from_u128(id),
+            name: name.to_owned(),
+            icon: DEFAULT_ICON.to_owned(),
+            description: None,
+            ip_access: Some("10.0.0.0/8".to_owned()),
+            enforce_tfa: false,
+            admin_access: false,
+            app_access: true,
+        }
+    }
+
+    fn permission(id: i32, role: u128, collection: &str) -> directus_permissions::Model {
+        let role = Uuid::from_u128(role).to_string();
+        directus_permissions::Model {
+            id,
+            permissions: Some(json!({})),
+            ..test_support::permission(Some(&role), collection, "read", "*")
+        }
+    }
+
+    #[test]
+    fn plan_creates_missing_and_updates_existing_roles() {
+        let seed: SeedFile = serde_yaml::from_str(SEED).unwrap();
+        let roles = vec![role(1, "Editor")];
+        let permissions = vec![
+            permission(1, 1, "directus_dashboards"),
+            directus_permissions::Model {
+                presets: Some(json!({ "country": "DE" })),
+                ..permission(2, 1, "customers")
+            },
+            permission(3, 1, "orders"),
+            permission(4, 2, "customers"),
+        ];
+        let plan = SeedPlan::new(&seed, &roles, &permissions, &schema()).unwrap();
+
+        assert_eq!(plan.roles.create.len(), 1);
+        assert_eq!(plan.roles.create[0].name, "Reviewer");
+        assert_eq!(plan.roles.update.len(), 1);
+        assert!(plan.roles.update[0].after.enforce_tfa);
+        assert_eq!(plan.roles.update[0].after.id, Some(Uuid::from_u128(1)));
+
+        let created: Vec<&str> = plan
+            .permissions
+            .create
+            .iter()
+            .map(|row| row.collection.as_str())
+            .collect();
+        assert_eq!(created, vec!["directus_panels", "articles"]);
+        assert_eq!(
+            plan.permissions.create[1].presets,
+            json!({ "status": "draft" })
+        );
+        assert_eq!(plan.permissions.delete.len(), 1);
+        assert_eq!(plan.permissions.delete[0].id, Some(3));
+        // Rows without presets keep theirs.
+        assert!(plan.permissions.update.is_empty());
+    }
+
+    #[test]
+    fn plan_refuses_missing_fields() {
+        let seed: SeedFile = serde_yaml::from_str(
+            "roles: [{ name: Editor, permissions: [{ collection: articles, action: read, fields: \"title,subtitle\" }] }]",
+        )
+        .unwrap();
+        let err = SeedPlan::new(&seed, &[], &[], &schema()).unwrap_err();
+        assert!(err.to_string().contains("articles.subtitle"));
+    }
+
+    #[test]
+    fn plan_is_empty_when_seeded_twice() {
+        let seed: SeedFile =
+            serde_yaml::from_str("roles: [{ name: Editor, ip_access: [10.0.0.0/8] }]").unwrap();
+        let plan = SeedPlan::new(&seed, &[role(1, "Editor")], &[], &Schema::default()).unwrap();
+        assert!(plan.roles.create.is_empty() && plan.roles.update.is_empty());
+        assert!(plan.permissions.is_empty());
+        assert!(plan.is_empty());
+
+        let public: SeedFile = serde_yaml::from_str("roles: [{ name: Public }]").unwrap();
+        assert!(SeedPlan::new(&public, &[], &[], &Schema::default()).is_err());
+    }
+}
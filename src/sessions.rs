@@ -0,0 +1,371 @@
+//! Report how roles can be logged into and used.
+//!
+//! Permissions only matter once somebody can act on them. List the active
+//! sessions and static tokens per role and flag roles whose login is weaker
+//! than their access, i.e. missing two-factor auth or IP restrictions.
+use crate::classify;
+use crate::cli::{OutputFormat, Sessions};
+use crate::directus;
+use crate::dump::{Request, RequestEntity};
+use crate::entities::{
+    directus_permissions, directus_roles, directus_sessions, directus_shares, directus_users,
+};
+use crate::output;
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{prelude::Uuid, Database, EntityTrait};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// 🏡 Handle logic for the `sessions` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `sessions` specific options.
+pub async fn sessions_entrypoint(args: &SessionsOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let roles = directus_roles::Entity::find().all(&db).await?;
+    let users = directus_users::Entity::find().all(&db).await?;
+    let sessions = directus_sessions::Entity::find().all(&db).await?;
+    let shares = directus::fetch_shares(&db).await?;
+    let mut sensitive = classify::to_requests(
+        &classify::classify_tagged(&db, &[], &args.tag, args.rules.as_deref()).await?,
+    );
+    sensitive.extend(args.sensitive.iter().cloned());
+
+    let exposure = Exposure {
+        permissions: &permissions,
+        fields: &fields,
+        users: &users,
+        sessions: &sessions,
+        shares: &shares,
+        sensitive: &sensitive,
+        now: Utc::now().into(),
+    };
+    let mut report: Vec<RoleExposure> = roles.iter().map(|role| exposure.role(role)).collect();
+    report.push(exposure.public());
+
+    output::print(&args.output, &report)
+}
+
+#[derive(Debug)]
+pub struct SessionsOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub tag: String,
+    pub rules: Option<PathBuf>,
+    pub sensitive: Vec<Request>,
+}
+
+impl From<Sessions> for SessionsOptions {
+    fn from(sessions: Sessions) -> Self {
+        SessionsOptions {
+            url: sessions.global_args.url,
+            output: sessions.global_args.output,
+            tag: sessions.tag,
+            rules: sessions.rules,
+            sensitive: sessions
+                .sensitive
+                .into_iter()
+                .map(|req| RequestEntity::from(req).into())
+                .collect(),
+        }
+    }
+}
+
+/// What's weak about logging into a role.
+///
+/// * `StaticToken` -> Users of the role have a static API token, which never expires
+/// * `MissingTfa` -> The role enforces two-factor auth, but privileged users haven't set it up
+/// * `NoIpRestriction` -> The role has admin or sensitive access from any IP
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionIssue {
+    StaticToken,
+    MissingTfa,
+    NoIpRestriction,
+}
+
+/// An active session, without its token.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Session {
+    pub user: Option<String>,
+    pub share: Option<String>,
+    pub expires: DateTime<FixedOffset>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RoleExposure {
+    pub role: String,
+    pub admin_access: bool,
+    pub enforce_tfa: bool,
+    pub ip_access: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sensitive_fields: Vec<String>,
+    pub sessions: Vec<Session>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub token_users: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing_tfa: Vec<String>,
+    pub issues: Vec<SessionIssue>,
+}
+
+/// Everything needed to report how roles can be used.
+pub struct Exposure<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [directus::Field],
+    pub users: &'a [directus_users::Model],
+    pub sessions: &'a [directus_sessions::Model],
+    pub shares: &'a [directus_shares::Model],
+    pub sensitive: &'a [Request],
+    pub now: DateTime<FixedOffset>,
+}
+
+impl<'a> Exposure<'a> {
+    pub fn role(&self, role: &directus_roles::Model) -> RoleExposure {
+        let id = role.id.to_string();
+        let users: Vec<&directus_users::Model> = self
+            .users
+            .iter()
+            .filter(|user| user.role == Some(role.id))
+            .collect();
+        let sensitive_fields = self.sensitive_fields(Some(&id));
+        let privileged = role.admin_access || !sensitive_fields.is_empty();
+
+        let token_users: Vec<String> = users
+            .iter()
+            .filter(|user| user.token.as_deref().is_some_and(|t| !t.is_empty()))
+            .map(|user| label(user))
+            .collect();
+        let missing_tfa: Vec<String> = match role.enforce_tfa && privileged {
+            true => users
+                .iter()
+                .filter(|user| user.tfa_secret.as_deref().unwrap_or_default().is_empty())
+                .map(|user| label(user))
+                .collect(),
+            false => Vec::new(),
+        };
+        let ip_access = role.ip_access.clone().filter(|ips| !ips.trim().is_empty());
+
+        let mut issues = Vec::new();
+        if !token_users.is_empty() {
+            issues.push(SessionIssue::StaticToken);
+        }
+        if !missing_tfa.is_empty() {
+            issues.push(SessionIssue::MissingTfa);
+        }
+        if privileged && ip_access.is_none() {
+            issues.push(SessionIssue::NoIpRestriction);
+        }
+
+        let sessions = self
+            .sessions
+            .iter()
+            .filter(|session| match (session.user, session.share) {
+                (Some(user), _) => users.iter().any(|u| u.id == user),
+                (None, Some(share)) => self.share_role(share) == Some(role.id),
+                (None, None) => false,
+            })
+            .filter_map(|session| self.session(session))
+            .collect();
+
+        RoleExposure {
+            role: role.name.clone(),
+            admin_access: role.admin_access,
+            enforce_tfa: role.enforce_tfa,
+            ip_access,
+            sensitive_fields,
+            sessions,
+            token_users,
+            missing_tfa,
+            issues,
+        }
+    }
+
+    /// The public role can't log in, but shares without a role open sessions as public.
+    pub fn public(&self) -> RoleExposure {
+        let sessions = self
+            .sessions
+            .iter()
+            .filter(|session| {
+                session.user.is_none()
+                    && session
+                        .share
+                        .is_some_and(|share| self.share_role(share).is_none())
+            })
+            .filter_map(|session| self.session(session))
+            .collect();
+
+        RoleExposure {
+            role: directus::PUBLIC_ROLE.to_owned(),
+            admin_access: false,
+            enforce_tfa: false,
+            ip_access: None,
+            sensitive_fields: self.sensitive_fields(None),
+            sessions,
+            token_users: Vec::new(),
+            missing_tfa: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    /// Sensitive fields `role` can read, as `collection.field`.
+    fn sensitive_fields(&self, role: Option<&str>) -> Vec<String> {
+        let collections: BTreeSet<&str> = self
+            .permissions
+            .iter()
+            .filter(|p| p.role.as_deref() == role)
+            .map(|p| p.collection.as_str())
+            .collect();
+
+        collections
+            .into_iter()
+            .flat_map(|collection| {
+                directus::granted_fields(self.permissions, self.fields, role, collection, "read")
+                    .into_iter()
+                    .filter(|field| self.sensitive.iter().any(|s| s.matches(collection, field)))
+                    .map(move |field| format!("{}.{}", collection, field))
+            })
+            .collect()
+    }
+
+    fn share_role(&self, share: Uuid) -> Option<Uuid> {
+        self.shares
+            .iter()
+            .find(|s| s.id == share)
+            .and_then(|s| s.role)
+    }
+
+    /// `None` if the session has expired.
+    fn session(&self, session: &directus_sessions::Model) -> Option<Session> {
+        if session.expires <= self.now {
+            return None;
+        }
+        Some(Session {
+            user: session.user.map(|id| {
+                self.users
+                    .iter()
+                    .find(|user| user.id == id)
+                    .map(label)
+                    .unwrap_or_else(|| id.to_string())
+            }),
+            share: session.share.map(|id| id.to_string()),
+            expires: session.expires,
+            ip: session.ip.clone(),
+            user_agent: session.user_agent.clone(),
+        })
+    }
+}
+
+fn label(user: &directus_users::Model) -> String {
+    user.email.clone().unwrap_or_else(|| user.id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: &str = "2023-06-01T00:00:00Z";
+
+    fn role(admin_access: bool, enforce_tfa: bool) -> directus_roles::Model {
+        directus_roles::Model {
+            id: Uuid::from_u128(1),
+            name: "Editor".to_owned(),
+            icon: "edit".to_owned(),
+            description: None,
+            ip_access: None,
+            enforce_tfa,
+            admin_access,
+            app_access: true,
+        }
+    }
+
+    fn user(id: u128, token: Option<&str>, tfa_secret: Option<&str>) -> directus_users::Model {
+        directus_users::Model {
+            id: Uuid::from_u128(id),
+            first_name: None,
+            last_name: None,
+            email: Some(format!("user{}@example.com", id)),
+            password: None,
+            location: None,
+            title: None,
+            description: None,
+            tags: None,
+            avatar: None,
+            language: None,
+            theme: None,
+            tfa_secret: tfa_secret.map(str::to_owned),
+            status: "active".to_owned(),
+            role: Some(Uuid::from_u128(1)),
+            token: token.map(str::to_owned),
+            last_access: None,
+            last_page: None,
+            provider: "default".to_owned(),
+            external_identifier: None,
+            auth_data: None,
+            email_notifications: None,
+        }
+    }
+
+    fn session(user: u128, expires: &str) -> directus_sessions::Model {
+        directus_sessions::Model {
+            token: format!("token-{}", expires),
+            user: Some(Uuid::from_u128(user)),
+            expires: DateTime::parse_from_rfc3339(expires).unwrap(),
+            ip: Some("127.0.0.1".to_owned()),
+            user_agent: None,
+            share: None,
+            origin: None,
+        }
+    }
+
+    fn exposure<'a>(
+        users: &'a [directus_users::Model],
+        sessions: &'a [directus_sessions::Model],
+    ) -> Exposure<'a> {
+        Exposure {
+            permissions: &[],
+            fields: &[],
+            users,
+            sessions,
+            shares: &[],
+            sensitive: &[],
+            now: DateTime::parse_from_rfc3339(NOW).unwrap(),
+        }
+    }
+
+    #[test]
+    fn role_lists_active_sessions_and_static_tokens() {
+        let users = vec![user(2, Some("static"), None), user(3, None, None)];
+        let sessions = vec![
+            session(2, "2023-06-02T00:00:00Z"),
+            session(3, "2023-05-01T00:00:00Z"),
+        ];
+        let result = exposure(&users, &sessions).role(&role(false, true));
+        assert_eq!(result.sessions.len(), 1);
+        assert_eq!(
+            result.sessions[0].user.as_deref(),
+            Some("user2@example.com")
+        );
+        assert_eq!(result.token_users, vec!["user2@example.com"]);
+        assert!(result.missing_tfa.is_empty());
+        assert_eq!(result.issues, vec![SessionIssue::StaticToken]);
+    }
+
+    #[test]
+    fn role_flags_privileged_users_without_tfa() {
+        let users = vec![user(2, None, None), user(3, None, Some("secret"))];
+        let result = exposure(&users, &[]).role(&role(true, true));
+        assert_eq!(result.missing_tfa, vec!["user2@example.com"]);
+        assert_eq!(
+            result.issues,
+            vec![SessionIssue::MissingTfa, SessionIssue::NoIpRestriction]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Audit share links in `directus_shares`.
+//!
+//! A share gives anyone with the link read access to a single item under
+//! the share's role. Shares outlive their purpose easily, so flag the ones
+//! that are expired, unprotected or expose sensitive fields.
+use crate::cli::{OutputFormat, Shares};
+use crate::directus;
+use crate::dump::{Request, RequestEntity};
+use crate::entities::{directus_permissions, directus_shares};
+use crate::output;
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{Database, EntityTrait};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// 🏡 Handle logic for the `shares` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `shares` specific options.
+pub async fn shares_entrypoint(args: &SharesOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let role_names = directus::fetch_role_names(&db).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let shares = directus::fetch_shares(&db).await?;
+
+    let audit = Audit {
+        permissions: &permissions,
+        fields: &fields,
+        role_names: &role_names,
+        sensitive: &args.sensitive,
+        now: Utc::now().into(),
+    };
+    let findings: Vec<ShareFinding> = shares
+        .iter()
+        .map(|share| audit.check(share))
+        .filter(|finding| !finding.issues.is_empty())
+        .collect();
+
+    output::print(&args.output, &findings)
+}
+
+#[derive(Debug)]
+pub struct SharesOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub sensitive: Vec<Request>,
+}
+
+impl From<Shares> for SharesOptions {
+    fn from(shares: Shares) -> Self {
+        SharesOptions {
+            url: shares.global_args.url,
+            output: shares.global_args.output,
+            sensitive: shares
+                .sensitive
+                .into_iter()
+                .map(|req| RequestEntity::from(req).into())
+                .collect(),
+        }
+    }
+}
+
+/// What's wrong with a share.
+///
+/// * `Expired` -> `date_end` has passed, but the share still exists
+/// * `Exhausted` -> `times_used` reached `max_uses`, but the share still exists
+/// * `NoPassword` -> Anyone with the link can open it
+/// * `NoExpiry` -> The share has no `date_end`
+/// * `Sensitive` -> The share's role can read a sensitive field of the item
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareIssue {
+    Expired,
+    Exhausted,
+    NoPassword,
+    NoExpiry,
+    Sensitive,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ShareFinding {
+    pub id: String,
+    pub name: Option<String>,
+    pub collection: Option<String>,
+    pub item: Option<String>,
+    pub role: String,
+    pub issues: Vec<ShareIssue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sensitive_fields: Vec<String>,
+}
+
+/// Everything needed to check shares.
+pub struct Audit<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [directus::Field],
+    pub role_names: &'a HashMap<String, String>,
+    pub sensitive: &'a [Request],
+    pub now: DateTime<FixedOffset>,
+}
+
+impl<'a> Audit<'a> {
+    pub fn check(&self, share: &directus_shares::Model) -> ShareFinding {
+        let mut issues = Vec::new();
+        if share.date_end.is_some_and(|end| end <= self.now) {
+            issues.push(ShareIssue::Expired);
+        }
+        if let (Some(used), Some(max)) = (share.times_used, share.max_uses) {
+            if used >= max {
+                issues.push(ShareIssue::Exhausted);
+            }
+        }
+        if share.password.as_deref().unwrap_or_default().is_empty() {
+            issues.push(ShareIssue::NoPassword);
+        }
+        if share.date_end.is_none() {
+            issues.push(ShareIssue::NoExpiry);
+        }
+
+        let role = share.role.map(|id| id.to_string());
+        let sensitive_fields: Vec<String> = match &share.collection {
+            Some(collection) => directus::granted_fields(
+                self.permissions,
+                self.fields,
+                role.as_deref(),
+                collection,
+                "read",
+            )
+            .into_iter()
+            .filter(|field| self.sensitive.iter().any(|s| s.matches(collection, field)))
+            .collect(),
+            None => Vec::new(),
+        };
+        if !sensitive_fields.is_empty() {
+            issues.push(ShareIssue::Sensitive);
+        }
+
+        ShareFinding {
+            id: share.id.to_string(),
+            name: share.name.clone(),
+            collection: share.collection.clone(),
+            item: share.item.clone(),
+            role: directus::role_name(role.as_deref(), self.role_names),
+            issues,
+            sensitive_fields,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::prelude::Uuid;
+
+    fn share(password: Option<&str>, date_end: Option<&str>) -> directus_shares::Model {
+        directus_shares::Model {
+            id: Uuid::nil(),
+            name: None,
+            collection: Some("customers".to_owned()),
+            item: Some("1".to_owned()),
+            role: None,
+            password: password.map(str::to_owned),
+            user_created: None,
+            date_created: None,
+            date_start: None,
+            date_end: date_end.map(|d| DateTime::parse_from_rfc3339(d).unwrap()),
+            times_used: Some(3),
+            max_uses: Some(3),
+        }
+    }
+
+    fn audit<'a>(
+        permissions: &'a [directus_permissions::Model],
+        sensitive: &'a [Request],
+        role_names: &'a HashMap<String, String>,
+    ) -> Audit<'a> {
+        Audit {
+            permissions,
+            fields: &[],
+            role_names,
+            sensitive,
+            now: DateTime::parse_from_rfc3339("2023-06-01T00:00:00Z").unwrap(),
+        }
+    }
+
+    #[test]
+    fn check_flags_unprotected_and_used_up_shares() {
+        let role_names = HashMap::new();
+        let result = audit(&[], &[], &role_names).check(&share(None, Some("2023-01-01T00:00:00Z")));
+        assert_eq!(
+            result.issues,
+            vec![
+                ShareIssue::Expired,
+                ShareIssue::Exhausted,
+                ShareIssue::NoPassword
+            ]
+        );
+        assert_eq!(result.role, directus::PUBLIC_ROLE);
+    }
+
+    #[test]
+    fn check_flags_sensitive_fields_of_the_shared_collection() {
+        let permissions = vec![directus_permissions::Model {
+            id: 1,
+            role: None,
+            collection: "customers".to_owned(),
+            action: "read".to_owned(),
+            permissions: None,
+            validation: None,
+            presets: None,
+            fields: Some("name,email".to_owned()),
+        }];
+        let sensitive: Vec<Request> = vec![RequestEntity::from("*.email".to_owned()).into()];
+        let role_names = HashMap::new();
+        let result = audit(&permissions, &sensitive, &role_names)
+            .check(&share(Some("secret"), Some("2024-01-01T00:00:00Z")));
+        assert_eq!(
+            result.issues,
+            vec![ShareIssue::Exhausted, ShareIssue::Sensitive]
+        );
+        assert_eq!(result.sensitive_fields, vec!["email"]);
+    }
+}
@@ -0,0 +1,342 @@
+//! Built-in permission templates for Directus system collections.
+//!
+//! Hand-crafting permissions on `directus_*` collections is error-prone.
+//! A template holds the rows a kind of role needs, e.g. `app-minimal` for
+//! roles with app access. `gdpr patch` applies a template to roles and
+//! `gdpr template check` reports roles that deviate from it.
+//!
+//! Templates only cover their own collections. Rows of a role in these
+//! collections that aren't part of the template are deviations. `patch` only
+//! creates and updates rows and leaves them to `template check`.
+use crate::cli::{OutputFormat, Patch, TemplateCommand};
+use crate::entities::{directus_permissions, directus_roles};
+use crate::output;
+use crate::replace::{self, Change, Diff, PermissionRow, Schema};
+use crate::utils;
+use crate::wildcard;
+use anyhow::Context;
+use sea_orm::{ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// All built-in templates as `(name, yaml)`.
+const TEMPLATES: &[(&str, &str)] = &[
+    ("app-minimal", include_str!("templates/app-minimal.yaml")),
+    ("insights", include_str!("templates/insights.yaml")),
+];
+
+/// 🏡 Handle logic for the `patch` command.
+///
+/// Print the changes and apply them after confirmation.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `patch` specific options.
+pub async fn patch_entrypoint(args: &PatchOptions) -> anyhow::Result<()> {
+    let template = match &args.template {
+        Some(name) => Template::get(name)?,
+        None => anyhow::bail!("Patching single fields is not implemented yet. Use `--template`."),
+    };
+    let db = Database::connect(&args.url).await?;
+    let schema = Schema::fetch(&db).await?;
+
+    let mut roles = BTreeSet::new();
+    for pattern in &args.roles {
+        let matches = wildcard::find_with(pattern, &schema.role_names());
+        if matches.is_empty() {
+            anyhow::bail!("No role matches `{}`", pattern);
+        }
+        roles.extend(matches);
+    }
+
+    let mut diff = Diff::default();
+    for role in &roles {
+        let role_id = match schema.roles.get(role) {
+            Some(Some(id)) => id.to_owned(),
+            Some(None) => anyhow::bail!("The `{}` role can't log into the app", role),
+            None => anyhow::bail!("Role `{}` doesn't exist", role),
+        };
+        // Extra rows are left alone, `template check` reports them.
+        let role_diff = template.plan(&db, &schema, role, &role_id).await?;
+        diff.create.extend(role_diff.create);
+        diff.update.extend(role_diff.update);
+    }
+    output::print(&args.output, &diff)?;
+
+    if args.dry_run || diff.is_empty() {
+        return Ok(());
+    }
+    let question = format!("Apply {} change(s)?", diff.len());
+    match args.yes || utils::confirm(&question)? {
+        true => Ok(replace::apply(&db, &diff).await?),
+        false => {
+            eprintln!("Nothing applied.");
+            Ok(())
+        }
+    }
+}
+
+/// 🏡 Handle logic for the `template` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `template` specific options.
+pub async fn template_entrypoint(args: &TemplateOptions) -> anyhow::Result<()> {
+    match args {
+        TemplateOptions::List { output } => {
+            let templates = names()
+                .into_iter()
+                .map(Template::get)
+                .collect::<anyhow::Result<Vec<Template>>>()?;
+            output::print(output, &templates)
+        }
+        TemplateOptions::Check {
+            url,
+            output,
+            template,
+        } => {
+            let template = Template::get(template)?;
+            let db = Database::connect(url).await?;
+            let schema = Schema::fetch(&db).await?;
+            let roles = directus_roles::Entity::find()
+                .filter(directus_roles::Column::AppAccess.eq(true))
+                .filter(directus_roles::Column::AdminAccess.eq(false))
+                .all(&db)
+                .await?;
+
+            let mut deviations = Vec::new();
+            for role in roles {
+                let diff = template
+                    .plan(&db, &schema, &role.name, &role.id.to_string())
+                    .await?;
+                if !diff.is_empty() {
+                    deviations.push(Deviation::new(&role.name, diff));
+                }
+            }
+            output::print(output, &deviations)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PatchOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub template: Option<String>,
+    pub roles: Vec<String>,
+    pub dry_run: bool,
+    pub yes: bool,
+}
+
+impl From<Patch> for PatchOptions {
+    fn from(patch: Patch) -> Self {
+        PatchOptions {
+            url: patch.global_args.url,
+            output: patch.global_args.output,
+            template: patch.template,
+            roles: patch.role,
+            dry_run: patch.dry_run,
+            yes: patch.yes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TemplateOptions {
+    List {
+        output: OutputFormat,
+    },
+    Check {
+        url: String,
+        output: OutputFormat,
+        template: String,
+    },
+}
+
+impl From<TemplateCommand> for TemplateOptions {
+    fn from(command: TemplateCommand) -> Self {
+        match command {
+            TemplateCommand::List(list) => TemplateOptions::List {
+                output: list.output,
+            },
+            TemplateCommand::Check(check) => TemplateOptions::Check {
+                url: check.global_args.url,
+                output: check.global_args.output,
+                template: check.template,
+            },
+        }
+    }
+}
+
+/// A set of permission rows for system collections.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<TemplateRow>,
+}
+
+/// A permission row without a role. `fields` is a CSV or `*`.
+///
+/// Rows without `presets` keep the presets of the row they replace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemplateRow {
+    pub collection: String,
+    pub action: String,
+    #[serde(default = "all_fields")]
+    pub fields: String,
+    #[serde(default = "no_filter")]
+    pub permissions: Value,
+    #[serde(default)]
+    pub validation: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub presets: Value,
+}
+
+impl TemplateRow {
+    pub fn row(&self, role: &str, role_id: &str) -> PermissionRow {
+        PermissionRow {
+            id: None,
+            role_id: Some(role_id.to_owned()),
+            role: role.to_owned(),
+            collection: self.collection.to_owned(),
+            action: self.action.to_owned(),
+            fields: self.fields.to_owned(),
+            permissions: self.permissions.clone(),
+            validation: self.validation.clone(),
+            presets: self.presets.clone(),
+        }
+    }
+}
+
+fn all_fields() -> String {
+    "*".to_owned()
+}
+
+fn no_filter() -> Value {
+    json!({})
+}
+
+impl Template {
+    /// Get a built-in template by name.
+    pub fn get(name: &str) -> anyhow::Result<Self> {
+        let (_, yaml) = TEMPLATES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .with_context(|| {
+                format!(
+                    "Template `{}` doesn't exist. Choose one of: {}",
+                    name,
+                    names().join(", ")
+                )
+            })?;
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    pub fn collections(&self) -> BTreeSet<&str> {
+        self.permissions
+            .iter()
+            .map(|row| row.collection.as_str())
+            .collect()
+    }
+
+    /// The template's rows for a role.
+    pub fn rows(&self, role: &str, role_id: &str) -> Vec<PermissionRow> {
+        self.permissions
+            .iter()
+            .map(|row| row.row(role, role_id))
+            .collect()
+    }
+
+    /// Compare a role's rows in the template's collections with the template.
+    pub async fn plan(
+        &self,
+        db: &DatabaseConnection,
+        schema: &Schema,
+        role: &str,
+        role_id: &str,
+    ) -> anyhow::Result<Diff> {
+        let current: Vec<PermissionRow> = directus_permissions::Entity::find()
+            .filter(directus_permissions::Column::Role.eq(role_id))
+            .filter(directus_permissions::Column::Collection.is_in(self.collections()))
+            .all(db)
+            .await?
+            .iter()
+            .map(|model| PermissionRow::from_model(model, schema))
+            .collect();
+
+        let mut desired = self.rows(role, role_id);
+        replace::keep_presets(&current, &mut desired);
+        Ok(Diff::new(&current, &desired, schema))
+    }
+}
+
+/// How a role deviates from a template.
+///
+/// * `missing` -> Template rows the role doesn't have
+/// * `changed` -> Rows with other fields or filters than the template
+/// * `extra` -> Rows in the template's collections the template doesn't have
+#[derive(Serialize, Debug)]
+pub struct Deviation {
+    pub role: String,
+    pub missing: Vec<PermissionRow>,
+    pub changed: Vec<Change>,
+    pub extra: Vec<PermissionRow>,
+}
+
+impl Deviation {
+    pub fn new(role: &str, diff: Diff) -> Self {
+        Self {
+            role: role.to_owned(),
+            missing: diff.create,
+            changed: diff.update,
+            extra: diff.delete,
+        }
+    }
+}
+
+/// Names of all built-in templates.
+pub fn names() -> Vec<&'static str> {
+    TEMPLATES.iter().map(|(name, _)| *name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DIRECTUS_SYSTEM_COLLECTIONS;
+    use std::collections::HashMap;
+
+    #[test]
+    fn templates_only_cover_system_collections() {
+        for name in names() {
+            let template = Template::get(name).unwrap();
+            assert_eq!(template.name, name);
+            for collection in template.collections() {
+                assert!(DIRECTUS_SYSTEM_COLLECTIONS.contains(&collection));
+            }
+        }
+        assert!(Template::get("nope").is_err());
+    }
+
+    #[test]
+    fn rows_match_semantically_equal_permissions() {
+        let template = Template::get("insights").unwrap();
+        let schema = Schema::new(
+            &[],
+            &HashMap::from([("1".to_owned(), "Manager".to_owned())]),
+        );
+        let desired = template.rows("Manager", "1");
+        let mut current = desired.clone();
+        current[0].id = Some(1);
+        current[0].permissions = Value::Null;
+        current[1].id = Some(2);
+        current[1].permissions = json!({ "id": { "_eq": "$CURRENT_USER" } });
+
+        let diff = Diff::new(&current, &desired, &schema);
+        let deviation = Deviation::new("Manager", diff);
+        assert!(deviation.missing.is_empty() && deviation.extra.is_empty());
+        assert_eq!(deviation.changed.len(), 1);
+        assert_eq!(deviation.changed[0].after.collection, "directus_panels");
+    }
+}
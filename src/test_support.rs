@@ -0,0 +1,47 @@
+//! Builders shared by the tests of several modules.
+use crate::entities::{directus_fields, directus_permissions};
+
+/// A `directus_permissions` row without filters, validation and presets.
+/// `None` is the public role.
+pub fn permission(
+    role: Option<&str>,
+    collection: &str,
+    action: &str,
+    fields: &str,
+) -> directus_permissions::Model {
+    directus_permissions::Model {
+        id: 1,
+        role: role.map(str::to_owned),
+        collection: collection.to_owned(),
+        action: action.to_owned(),
+        permissions: None,
+        validation: None,
+        presets: None,
+        fields: Some(fields.to_owned()),
+    }
+}
+
+/// A `directus_fields` row with the given `special` and no other settings.
+pub fn field_meta(collection: &str, field: &str, special: Option<&str>) -> directus_fields::Model {
+    directus_fields::Model {
+        id: 0,
+        collection: collection.to_owned(),
+        field: field.to_owned(),
+        special: special.map(str::to_owned),
+        interface: None,
+        options: None,
+        display: None,
+        display_options: None,
+        readonly: false,
+        hidden: false,
+        sort: None,
+        width: None,
+        translations: None,
+        note: None,
+        conditions: None,
+        required: None,
+        group: None,
+        validation: None,
+        validation_message: None,
+    }
+}
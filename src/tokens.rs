@@ -0,0 +1,310 @@
+//! List static API tokens and what they can access.
+//!
+//! `directus_users.token` never expires and carries all permissions of the
+//! user's role. Tokens are never printed, only a fingerprint to tell them
+//! apart and to match them against a token you have at hand.
+use crate::cli::{OutputFormat, Tokens};
+use crate::directus;
+use crate::entities::{directus_permissions, directus_roles, directus_users};
+use crate::graph::canonical::canonicalize;
+use crate::graph::filter::is_equivalent_json;
+use crate::output;
+use chrono::{DateTime, FixedOffset};
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Actions that change data.
+const WRITE_ACTIONS: [&str; 3] = ["create", "update", "delete"];
+
+/// 🏡 Handle logic for the `tokens` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `tokens` specific options.
+pub async fn tokens_entrypoint(args: &TokensOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let roles = directus_roles::Entity::find().all(&db).await?;
+    let users = directus_users::Entity::find()
+        .filter(directus_users::Column::Token.is_not_null())
+        .all(&db)
+        .await?;
+
+    let inventory = Inventory {
+        permissions: &permissions,
+        fields: &fields,
+        roles: &roles,
+    };
+    let tokens: Vec<StaticToken> = users
+        .iter()
+        .filter_map(|user| inventory.token(user))
+        .collect();
+
+    output::print(&args.output, &tokens)
+}
+
+#[derive(Debug)]
+pub struct TokensOptions {
+    pub url: String,
+    pub output: OutputFormat,
+}
+
+impl From<Tokens> for TokensOptions {
+    fn from(tokens: Tokens) -> Self {
+        TokensOptions {
+            url: tokens.global_args.url,
+            output: tokens.global_args.output,
+        }
+    }
+}
+
+/// A user's static token and the fields it can access.
+///
+/// `read` and `write` map collections to the fields accessible per filter.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct StaticToken {
+    pub fingerprint: String,
+    pub user: String,
+    pub status: String,
+    pub role: String,
+    pub admin_access: bool,
+    pub last_access: Option<DateTime<FixedOffset>>,
+    pub read: BTreeMap<String, Vec<Access>>,
+    pub write: BTreeMap<String, Vec<Access>>,
+}
+
+/// Fields of a collection and the filter items must match, if any.
+///
+/// Fields are shortened to `*` if all fields of the collection are accessible.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Access {
+    pub fields: Vec<String>,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub filter: Value,
+}
+
+/// Everything needed to map tokens to their access.
+pub struct Inventory<'a> {
+    pub permissions: &'a [directus_permissions::Model],
+    pub fields: &'a [directus::Field],
+    pub roles: &'a [directus_roles::Model],
+}
+
+impl<'a> Inventory<'a> {
+    /// `None` if the user has no token.
+    pub fn token(&self, user: &directus_users::Model) -> Option<StaticToken> {
+        let token = user.token.as_deref().filter(|t| !t.is_empty())?;
+        let role = user
+            .role
+            .and_then(|id| self.roles.iter().find(|role| role.id == id));
+        let admin_access = role.is_some_and(|role| role.admin_access);
+        // Users without a role have no permissions, they are not public.
+        let role_id = role.map(|role| role.id.to_string());
+
+        let (read, write) = match (role_id, admin_access) {
+            (_, true) => (self.everything(), self.everything()),
+            (Some(id), false) => (
+                self.access(&id, &["read"]),
+                self.access(&id, &WRITE_ACTIONS),
+            ),
+            (None, false) => (BTreeMap::new(), BTreeMap::new()),
+        };
+
+        Some(StaticToken {
+            fingerprint: fingerprint(token),
+            user: user.email.clone().unwrap_or_else(|| user.id.to_string()),
+            status: user.status.clone(),
+            role: role.map_or_else(|| "None".to_owned(), |role| role.name.clone()),
+            admin_access,
+            last_access: user.last_access,
+            read,
+            write,
+        })
+    }
+
+    /// Group the role's fields of each collection by their filter, like the
+    /// caveats of the permission graph.
+    fn access(&self, role: &str, actions: &[&str]) -> BTreeMap<String, Vec<Access>> {
+        let mut groups: BTreeMap<&str, Vec<(Value, BTreeSet<String>)>> = BTreeMap::new();
+        for row in self
+            .permissions
+            .iter()
+            .filter(|p| p.role.as_deref() == Some(role) && actions.contains(&p.action.as_str()))
+        {
+            let fields = directus::granted_fields(
+                std::slice::from_ref(row),
+                self.fields,
+                Some(role),
+                &row.collection,
+                &row.action,
+            );
+            if fields.is_empty() {
+                continue;
+            }
+            let filter = match row.permissions.as_ref().map(canonicalize) {
+                Some(Value::Object(filter)) if filter.is_empty() => Value::Null,
+                Some(filter) => filter,
+                None => Value::Null,
+            };
+            let collection = groups.entry(&row.collection).or_default();
+            match collection
+                .iter_mut()
+                .find(|(f, _)| is_equivalent_json(f, &filter))
+            {
+                Some((_, granted)) => granted.extend(fields),
+                None => collection.push((filter, fields)),
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(collection, mut access)| {
+                // Unfiltered access first
+                access.sort_by_key(|(filter, _)| !filter.is_null());
+                let access = access
+                    .into_iter()
+                    .map(|(filter, granted)| Access {
+                        fields: self.shorten(collection, granted),
+                        filter,
+                    })
+                    .collect();
+                (collection.to_owned(), access)
+            })
+            .collect()
+    }
+
+    /// Admins bypass permissions and access every field.
+    fn everything(&self) -> BTreeMap<String, Vec<Access>> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let access = Access {
+                    fields: vec!["*".to_owned()],
+                    filter: Value::Null,
+                };
+                (field.collection.to_owned(), vec![access])
+            })
+            .collect()
+    }
+
+    fn shorten(&self, collection: &str, granted: BTreeSet<String>) -> Vec<String> {
+        let known = directus::known_fields(self.fields, collection);
+        let all = known.iter().all(|field| granted.contains(field));
+        match all && !known.is_empty() {
+            true => vec!["*".to_owned()],
+            false => granted.into_iter().collect(),
+        }
+    }
+}
+
+/// The first 16 hex digits of the token's SHA-256.
+///
+/// Enough to tell tokens apart and to recognize a known token with
+/// `printf %s "$TOKEN" | sha256sum`, but useless for authentication.
+pub fn fingerprint(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", &hex[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::permission;
+    use sea_orm::prelude::Uuid;
+    use serde_json::json;
+
+    fn field(collection: &str, field: &str) -> directus::Field {
+        directus::Field {
+            collection: collection.to_owned(),
+            field: field.to_owned(),
+        }
+    }
+
+    fn user(token: Option<&str>) -> directus_users::Model {
+        directus_users::Model {
+            id: Uuid::from_u128(2),
+            first_name: None,
+            last_name: None,
+            email: Some("sync@example.com".to_owned()),
+            password: None,
+            location: None,
+            title: None,
+            description: None,
+            tags: None,
+            avatar: None,
+            language: None,
+            theme: None,
+            tfa_secret: None,
+            status: "active".to_owned(),
+            role: Some(Uuid::from_u128(1)),
+            token: token.map(str::to_owned),
+            last_access: None,
+            last_page: None,
+            provider: "default".to_owned(),
+            external_identifier: None,
+            auth_data: None,
+            email_notifications: None,
+        }
+    }
+
+    #[test]
+    fn fingerprint_hides_the_token() {
+        assert_eq!(fingerprint("secret"), "sha256:2bb80d537b1da3e3");
+    }
+
+    #[test]
+    fn token_maps_to_read_and_write_access_of_the_role() {
+        let roles = vec![directus_roles::Model {
+            id: Uuid::from_u128(1),
+            name: "Integration".to_owned(),
+            icon: "api".to_owned(),
+            description: None,
+            ip_access: None,
+            enforce_tfa: false,
+            admin_access: false,
+            app_access: false,
+        }];
+        let role = Uuid::from_u128(1).to_string();
+        let own = json!({ "id": { "_eq": "$CURRENT_USER" } });
+        let permissions = vec![
+            permission(Some(&role), "customers", "read", "*"),
+            permission(Some(&role), "customers", "update", "email"),
+            directus_permissions::Model {
+                permissions: Some(own.clone()),
+                ..permission(Some(&role), "directus_users", "read", "*")
+            },
+        ];
+        let fields = vec![field("customers", "name"), field("customers", "email")];
+        let inventory = Inventory {
+            permissions: &permissions,
+            fields: &fields,
+            roles: &roles,
+        };
+
+        assert_eq!(inventory.token(&user(None)), None);
+        let result = inventory.token(&user(Some("secret"))).unwrap();
+        assert_eq!(result.role, "Integration");
+        let access = |fields: &str, filter: Value| Access {
+            fields: vec![fields.to_owned()],
+            filter,
+        };
+        assert_eq!(
+            result.read,
+            BTreeMap::from([
+                ("customers".to_owned(), vec![access("*", Value::Null)]),
+                ("directus_users".to_owned(), vec![access("*", own)]),
+            ])
+        );
+        assert_eq!(
+            result.write,
+            BTreeMap::from([("customers".to_owned(), vec![access("email", Value::Null)])])
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Find grants that nobody used within a period.
+//!
+//! Compare `create`, `update` and `delete` grants with `directus_activity`.
+//! Directus doesn't log reads or shares, so those grants are never reported.
+use crate::cli::{OutputFormat, Unused};
+use crate::directus::{self, Field, User};
+use crate::entities::{directus_activity, directus_permissions};
+use crate::manifest::Manifest;
+use crate::output;
+use crate::presets;
+use crate::reversed_permissions;
+use chrono::Utc;
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Actions that Directus records in `directus_activity`.
+const LOGGED_ACTIONS: &[&str] = &["create", "update", "delete"];
+
+/// 🏡 Handle logic for the `unused` command.
+///
+/// # Arguments
+///
+/// * `args` - A reference to user's `unused` specific options.
+pub async fn unused_entrypoint(args: &UnusedOptions) -> anyhow::Result<()> {
+    let db = Database::connect(&args.url).await?;
+
+    let collections = directus::fetch_collections(&db).await?;
+    let fields = directus::fetch_fields(&db, &collections).await?;
+    let role_names = directus::fetch_role_names(&db).await?;
+    let users = directus::fetch_users(&db).await?;
+    let permissions = directus_permissions::Entity::find().all(&db).await?;
+    let activities = directus_activity::Entity::find()
+        .filter(directus_activity::Column::Action.is_in(LOGGED_ACTIONS.iter().copied()))
+        .filter(directus_activity::Column::Timestamp.gte(Utc::now() - args.since))
+        .all(&db)
+        .await?;
+
+    let unused = find_unused(&permissions, &activities, &users);
+    if args.manifest {
+        let mut manifest = least_privilege(&permissions, &unused, &fields, &role_names);
+        let shared = presets::fetch_shared(&db).await?;
+        manifest.presets = Some(presets::to_manifest(&shared, &role_names, &[]));
+        return output::print(&args.output, &manifest);
+    }
+
+    let mut report: Vec<UnusedGrant> = unused
+        .iter()
+        .map(|row| UnusedGrant {
+            role: directus::role_name(row.role.as_deref(), &role_names),
+            collection: row.collection.to_owned(),
+            action: row.action.to_owned(),
+        })
+        .collect();
+    report.sort();
+    report.dedup();
+    output::print(&args.output, &report)
+}
+
+#[derive(Debug)]
+pub struct UnusedOptions {
+    pub url: String,
+    pub output: OutputFormat,
+    pub since: chrono::Duration,
+    pub manifest: bool,
+}
+
+impl From<Unused> for UnusedOptions {
+    fn from(unused: Unused) -> Self {
+        UnusedOptions {
+            url: unused.global_args.url,
+            output: unused.global_args.output,
+            since: unused.since,
+            manifest: unused.manifest,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnusedGrant {
+    pub role: String,
+    pub collection: String,
+    pub action: String,
+}
+
+/// Return all logged grants without a matching activity.
+///
+/// An activity matches if its user *currently* has the grant's role.
+/// Activities without a user count for the public role.
+pub fn find_unused<'a>(
+    permissions: &'a [directus_permissions::Model],
+    activities: &[directus_activity::Model],
+    users: &HashMap<String, User>,
+) -> Vec<&'a directus_permissions::Model> {
+    let used: HashSet<(Option<String>, &str, &str)> = activities
+        .iter()
+        .map(|activity| {
+            let role = match activity.user {
+                Some(id) => users.get(&id.to_string()).and_then(|u| u.role.clone()),
+                None => None,
+            };
+            (role, activity.collection.as_str(), activity.action.as_str())
+        })
+        .collect();
+
+    permissions
+        .iter()
+        .filter(|row| LOGGED_ACTIONS.contains(&row.action.as_str()))
+        .filter(|row| !used.contains(&(row.role.clone(), &row.collection, &row.action)))
+        .collect()
+}
+
+/// Build a manifest of all permissions except `unused`, ready for `gdpr replace`.
+///
+/// Collections whose rows are all unused are kept empty, so that `replace`
+/// deletes their rows. Rows for all fields stay `*`.
+pub fn least_privilege(
+    permissions: &[directus_permissions::Model],
+    unused: &[&directus_permissions::Model],
+    fields: &[Field],
+    role_names: &HashMap<String, String>,
+) -> Manifest {
+    let unused_ids: HashSet<i32> = unused.iter().map(|row| row.id).collect();
+    let kept: Vec<directus_permissions::Model> = permissions
+        .iter()
+        .filter(|row| !unused_ids.contains(&row.id))
+        .cloned()
+        .collect();
+
+    let mut manifest = reversed_permissions::Builder::new(&kept, fields, role_names).build();
+    let kept_collections: BTreeSet<&String> = kept.iter().map(|row| &row.collection).collect();
+    let emptied: BTreeSet<&String> = permissions
+        .iter()
+        .map(|row| &row.collection)
+        .filter(|collection| !kept_collections.contains(collection))
+        .collect();
+    for collection in emptied {
+        manifest
+            .collections
+            .entry(collection.to_owned())
+            .or_default();
+    }
+    manifest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use chrono::DateTime;
+    use sea_orm::prelude::Uuid;
+
+    fn permission(id: i32, role: &str, action: &str) -> directus_permissions::Model {
+        directus_permissions::Model {
+            id,
+            ..test_support::permission(Some(role), "articles", action, "*")
+        }
+    }
+
+    fn activity(action: &str) -> directus_activity::Model {
+        directus_activity::Model {
+            id: 1,
+            action: action.to_owned(),
+            user: Some(Uuid::nil()),
+            timestamp: DateTime::parse_from_rfc3339("2023-06-01T00:00:00Z").unwrap(),
+            ip: None,
+            user_agent: None,
+            collection: "articles".to_owned(),
+            item: "1".to_owned(),
+            comment: None,
+            origin: None,
+        }
+    }
+
+    fn users() -> HashMap<String, User> {
+        HashMap::from([(
+            Uuid::nil().to_string(),
+            User {
+                id: Uuid::nil().to_string(),
+                email: None,
+                role: Some("editor".to_owned()),
+            },
+        )])
+    }
+
+    #[test]
+    fn find_unused_reports_grants_without_activity() {
+        let permissions = vec![
+            permission(1, "editor", "update"),
+            permission(2, "editor", "delete"),
+            permission(3, "editor", "read"),
+            permission(4, "author", "update"),
+        ];
+        let unused = find_unused(&permissions, &[activity("update")], &users());
+        let ids: Vec<i32> = unused.iter().map(|row| row.id).collect();
+        assert_eq!(ids, vec![2, 4]);
+    }
+
+    #[test]
+    fn least_privilege_keeps_emptied_collections() {
+        let permissions = vec![permission(1, "editor", "delete")];
+        let unused = find_unused(&permissions, &[], &users());
+        let manifest = least_privilege(&permissions, &unused, &[], &HashMap::new());
+        assert!(manifest.collections["articles"].is_empty());
+    }
+
+    #[test]
+    fn least_privilege_keeps_used_rows_of_system_collections() {
+        let files = |id: i32, action: &str| directus_permissions::Model {
+            collection: "directus_files".to_owned(),
+            fields: None,
+            ..permission(id, "editor", action)
+        };
+        let permissions = vec![
+            directus_permissions::Model {
+                collection: "directus_users".to_owned(),
+                ..permission(1, "editor", "read")
+            },
+            files(2, "delete"),
+            files(3, "update"),
+        ];
+        let deleted = directus_activity::Model {
+            collection: "directus_files".to_owned(),
+            ..activity("delete")
+        };
+        let unused = find_unused(&permissions, &[deleted], &users());
+        let manifest = least_privilege(&permissions, &unused, &[], &HashMap::new());
+
+        assert_eq!(unused.len(), 1);
+        assert_eq!(manifest.collections["directus_users"]["*"].read.len(), 1);
+        let files = &manifest.collections["directus_files"]["*"];
+        assert_eq!((files.delete.len(), files.update.len()), (1, 0));
+    }
+}
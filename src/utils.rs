@@ -1,3 +1,5 @@
+use std::io::Write;
+
 /// Split a string with at most one point `.`
 ///
 /// Always return a tuple. If no point is found, return a string splice
@@ -20,7 +22,7 @@ pub fn split_one_point_strictly(string: &str) -> (Option<&str>, Option<&str>) {
         (Some(string), None)
     } else if count == 1 {
         let split = string.split_once('.').unwrap();
-        return (
+        (
             if !split.0.is_empty() {
                 Some(split.0)
             } else {
@@ -31,7 +33,7 @@ pub fn split_one_point_strictly(string: &str) -> (Option<&str>, Option<&str>) {
             } else {
                 None
             },
-        );
+        )
     } else {
         panic!("{} has too many points.", string)
     }
@@ -44,6 +46,45 @@ pub fn remove_whitespace(s: &str) -> Result<String, String> {
         .join(","))
 }
 
+/// Parse a duration like `30d`, `12h` or `2w`.
+///
+/// Supported units are `s`, `m` (minutes), `h`, `d`, `w` and `y` (365 days).
+///
+/// # Example
+/// ```
+/// let duration = parse_duration("30d").unwrap();
+/// assert_eq!(duration, chrono::Duration::days(30));
+/// ```
+pub fn parse_duration(s: &str) -> Result<chrono::Duration, String> {
+    let s = s.trim();
+    let error = || {
+        format!(
+            "Invalid duration `{}`. Use a number and a unit like `30d`, `12h` or `10y`.",
+            s
+        )
+    };
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+    let amount: i64 = s[..split].parse().map_err(|_| error())?;
+    match &s[split..] {
+        "s" => Ok(chrono::Duration::seconds(amount)),
+        "m" => Ok(chrono::Duration::minutes(amount)),
+        "h" => Ok(chrono::Duration::hours(amount)),
+        "d" => Ok(chrono::Duration::days(amount)),
+        "w" => Ok(chrono::Duration::weeks(amount)),
+        "y" => Ok(chrono::Duration::days(amount * 365)),
+        _ => Err(error()),
+    }
+}
+
+/// Ask a yes/no question on stderr. Everything but `y` and `yes` is no.
+pub fn confirm(question: &str) -> std::io::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,4 +130,19 @@ mod tests {
         let result = remove_whitespace(s);
         assert_eq!(result, Ok("h h,?".to_owned()));
     }
+
+    #[test]
+    fn parse_duration_supports_units() {
+        assert_eq!(parse_duration("30d"), Ok(chrono::Duration::days(30)));
+        assert_eq!(parse_duration("12h"), Ok(chrono::Duration::hours(12)));
+        assert_eq!(parse_duration("2w"), Ok(chrono::Duration::weeks(2)));
+        assert_eq!(parse_duration("1y"), Ok(chrono::Duration::days(365)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_missing_amount_or_unit() {
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("30x").is_err());
+    }
 }
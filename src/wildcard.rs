@@ -1,16 +1,20 @@
-#![allow(unused)] // FIXME:
 //! Tools and functions to help deduplicate data
 //! To display many data points in yamls to users more nicely.
 //!
-use regex::Regex;
+
+/// Characters that separate words in field and role names.
+///
+/// A wildcard only ever replaces whole words, so `*glob` doesn't match `no-glob`.
+const SEPARATORS: &[char] = &['_', ':', '.', ' '];
 
 /// Simplify a series of strings into a short list of glob-like patterns.
 ///
 /// Shorten and compress a series of strings with similar prefixes and suffixes
 /// by using wildcards (*).
 /// For each group, there can only ever be one wildcard at the start or the end.
-/// Prioritize putting the wildcard at the end and prefer longer matches over
-/// shorter matches.
+/// Prefer patterns that cover more strings, then prioritize putting the
+/// wildcard at the end and prefer longer matches over shorter matches.
+/// Strings no pattern replaces come first, then the patterns.
 ///
 /// # Arguments
 ///
@@ -19,14 +23,14 @@ use regex::Regex;
 /// # Example
 /// ```
 /// let pattern = build_glob_patterns(vec!["glob:", "glob:2", "no-glob:xx"])
-/// assert_eq!(pattern, vec!["glob:*", "no-glob:xx"])
+/// assert_eq!(pattern, vec!["no-glob:xx", "glob:*"])
 /// ```
 ///
 /// Works also with suffix matches:
 ///
 /// ```
 /// let pattern = build_glob_patterns(vec!["2:glob", "3:glob", "xx:no-glob"])
-/// assert_eq!(pattern, vec!["*:glob", "xx:no-glob"])
+/// assert_eq!(pattern, vec!["xx:no-glob", "*:glob"])
 /// ```
 ///
 /// Prefers longer matches and prefers prefix matches:
@@ -42,9 +46,137 @@ use regex::Regex;
 /// let pattern = build_glob_patterns(vec!["prefer_prefix_glob:xx", "random_glob_3x", "prefer_prefix_?_glob_3x", "prefer_prefix_glob_3x"])
 /// assert_eq!(pattern, vec!["random_glob_3x", "prefer_prefix_*"])
 /// ```
-
 pub fn build_glob_like_patterns(strings: Vec<&str>) -> Vec<String> {
-    todo!();
+    build_patterns(strings, |_, _| true)
+}
+
+/// Like [`build_glob_like_patterns`], but only use a pattern if it matches
+/// exactly the strings it replaces in `total_set`.
+///
+/// # Arguments
+///
+/// * `strings` - A vector of strings, e.g. some fields of a collection.
+/// * `total_set` - All known strings, e.g. all fields of that collection.
+///
+/// # Example
+/// ```
+/// let all = vec!["address_city".to_owned(), "address_zip".to_owned(), "address_secret".to_owned()];
+/// let pattern = compress_with(vec!["address_city", "address_zip"], &all);
+/// assert_eq!(pattern, vec!["address_city", "address_zip"])
+/// ```
+pub fn compress_with(strings: Vec<&str>, total_set: &[String]) -> Vec<String> {
+    build_patterns(strings, |pattern, members| {
+        let mut matches = total_set.iter().filter(|s| is_match(pattern, s));
+        matches.clone().count() == members.len() && matches.all(|m| members.contains(&m.as_str()))
+    })
+}
+
+/// A possible glob-like pattern and the strings it replaces.
+struct Candidate<'a> {
+    pattern: String,
+    members: Vec<&'a str>,
+    is_prefix: bool,
+    affix_len: usize,
+}
+
+impl<'a> Candidate<'a> {
+    fn rank(&self) -> (usize, bool, usize, std::cmp::Reverse<&str>) {
+        (
+            self.members.len(),
+            self.is_prefix,
+            self.affix_len,
+            std::cmp::Reverse(self.pattern.as_str()),
+        )
+    }
+}
+
+fn build_patterns(strings: Vec<&str>, is_valid: impl Fn(&str, &[&str]) -> bool) -> Vec<String> {
+    let mut remaining = strings;
+    remaining.sort();
+    remaining.dedup();
+
+    let mut patterns = Vec::new();
+    while let Some(best) = best_candidate(&remaining, &is_valid) {
+        remaining.retain(|s| !best.members.contains(s));
+        patterns.push(best.pattern);
+    }
+    patterns.sort();
+
+    // Strings no pattern replaces come first, then the patterns
+    let mut result: Vec<String> = remaining.iter().map(|s| s.to_string()).collect();
+    result.extend(patterns);
+    result
+}
+
+/// Find the best pattern that replaces at least two of `strings`.
+fn best_candidate<'a>(
+    strings: &[&'a str],
+    is_valid: &impl Fn(&str, &[&str]) -> bool,
+) -> Option<Candidate<'a>> {
+    let mut affixes: Vec<(&str, bool)> = Vec::new();
+    for s in strings {
+        for (i, _) in s.char_indices().skip(1).chain([(s.len(), ' ')]) {
+            affixes.push((&s[..i], true));
+        }
+        for (i, _) in s.char_indices() {
+            affixes.push((&s[i..], false));
+        }
+    }
+    affixes.sort();
+    affixes.dedup();
+
+    let mut best: Option<Candidate> = None;
+    for (affix, is_prefix) in affixes {
+        let members: Vec<&str> = strings
+            .iter()
+            .filter(|s| {
+                if is_prefix {
+                    s.starts_with(affix)
+                } else {
+                    s.ends_with(affix)
+                }
+            })
+            .copied()
+            .collect();
+        if members.len() < 2
+            || !members
+                .iter()
+                .all(|s| is_word_boundary(s, affix, is_prefix))
+        {
+            continue;
+        }
+        let pattern = if is_prefix {
+            format!("{}*", affix)
+        } else {
+            format!("*{}", affix)
+        };
+        if !is_valid(&pattern, &members) {
+            continue;
+        }
+
+        let candidate = Candidate {
+            pattern,
+            members,
+            is_prefix,
+            affix_len: affix.len(),
+        };
+        if best.as_ref().is_none_or(|b| candidate.rank() > b.rank()) {
+            best = Some(candidate);
+        }
+    }
+    best
+}
+
+/// Check if the wildcard next to `affix` in `s` replaces whole words.
+fn is_word_boundary(s: &str, affix: &str, is_prefix: bool) -> bool {
+    if s == affix {
+        return true;
+    }
+    if is_prefix {
+        affix.ends_with(SEPARATORS) || s[affix.len()..].starts_with(SEPARATORS)
+    } else {
+        affix.starts_with(SEPARATORS) || s[..s.len() - affix.len()].ends_with(SEPARATORS)
+    }
 }
 
 /// Find all matching entries in a vector that satisfy an expression.
@@ -57,20 +189,37 @@ pub fn build_glob_like_patterns(strings: Vec<&str>) -> Vec<String> {
 /// # Returns
 ///
 /// A vec of strings that matches the expression.
-pub fn find_with(expression: &str, total_set: &Vec<String>) -> Vec<String> {
+pub fn find_with(expression: &str, total_set: &[String]) -> Vec<String> {
     if !expression.contains('*') {
         return vec![expression.to_owned()];
     }
 
-    let expression = r"^".to_owned() + &expression.replace('*', ".*") + "$";
-    let re = Regex::new(&expression).unwrap();
-    let mut result: Vec<String> = vec![];
-    for item in total_set {
-        if re.is_match(&item) {
-            result.push(item.to_owned());
+    total_set
+        .iter()
+        .filter(|item| is_match(expression, item))
+        .cloned()
+        .collect()
+}
+
+/// Check if `s` matches `pattern`. Each `*` matches any number of characters.
+fn is_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let mut rest = match s.strip_prefix(parts.next().unwrap_or_default()) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
         }
     }
-    return result;
+    rest.ends_with(last)
 }
 
 #[cfg(test)]
@@ -84,6 +233,62 @@ mod tests {
         assert_eq!(result, vec!["base:*"])
     }
 
+    #[test]
+    fn build_glob_like_patterns_only_replaces_whole_words() {
+        let test_me = vec!["2:glob", "3:glob", "xx:no-glob"];
+        let result = build_glob_like_patterns(test_me);
+        assert_eq!(result, vec!["xx:no-glob", "*:glob"])
+    }
+
+    #[test]
+    fn build_glob_like_patterns_prefers_more_strings_then_prefixes() {
+        let test_me = vec![
+            "prefer_prefix_glob:xx",
+            "random_glob_3x",
+            "prefer_prefix_?_glob_3x",
+            "prefer_prefix_glob_3x",
+        ];
+        let result = build_glob_like_patterns(test_me);
+        assert_eq!(result, vec!["random_glob_3x", "prefer_prefix_*"])
+    }
+
+    #[test]
+    fn build_glob_like_patterns_prefers_longer_matches() {
+        let test_me = vec![
+            "prefer_prefix_glob:xx",
+            "prefer_prefix_glob:xxx",
+            "prefer_prefix_glob_xxx",
+        ];
+        let result = build_glob_like_patterns(test_me);
+        assert_eq!(result, vec!["prefer_prefix_glob*"])
+    }
+
+    #[test]
+    fn compress_with_only_uses_exact_patterns() {
+        let all = vec![
+            "address_street".to_owned(),
+            "address_city".to_owned(),
+            "address_zip".to_owned(),
+            "address_secret".to_owned(),
+            "name".to_owned(),
+        ];
+        let result = compress_with(vec!["address_street", "address_city", "address_zip"], &all);
+        assert_eq!(
+            result,
+            vec!["address_city", "address_street", "address_zip"]
+        );
+        let result = compress_with(
+            vec![
+                "address_street",
+                "address_city",
+                "address_zip",
+                "address_secret",
+            ],
+            &all,
+        );
+        assert_eq!(result, vec!["address_*"]);
+    }
+
     #[test]
     fn find_with_matches_wildcard_at_end() {
         let test_me = vec![
@@ -0,0 +1,148 @@
+//! Write uncompressed zip archives.
+//!
+//! Exports are small and mostly read by people, so storing files without
+//! compression is good enough and saves a dependency. Archives that need
+//! zip64, i.e. larger than 4 GiB or with more than 65535 entries, are refused.
+use anyhow::Context;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+/// Build a zip archive of `(path, content)` entries, stored without compression.
+///
+/// All entries are dated `modified`.
+pub fn write(entries: &[(String, Vec<u8>)], modified: NaiveDateTime) -> anyhow::Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+    let (time, date) = dos_date_time(modified);
+
+    for (path, content) in entries {
+        let offset = fit_u32(archive.len())?;
+        let crc = crc32(content);
+        let size = u32::try_from(content.len())
+            .with_context(|| format!("`{}` is too large for a zip archive", path))?;
+        let name = path.as_bytes();
+        let name_len = u16::try_from(name.len())
+            .with_context(|| format!("The path `{}` is too long for a zip archive", path))?;
+
+        // Local file header
+        archive.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        archive.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        archive.extend_from_slice(&0u16.to_le_bytes()); // flags
+        archive.extend_from_slice(&0u16.to_le_bytes()); // stored
+        archive.extend_from_slice(&time.to_le_bytes());
+        archive.extend_from_slice(&date.to_le_bytes());
+        archive.extend_from_slice(&crc.to_le_bytes());
+        archive.extend_from_slice(&size.to_le_bytes());
+        archive.extend_from_slice(&size.to_le_bytes());
+        archive.extend_from_slice(&name_len.to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes()); // extra length
+        archive.extend_from_slice(name);
+        archive.extend_from_slice(content);
+
+        // Central directory header
+        central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        central.extend_from_slice(&0u16.to_le_bytes()); // flags
+        central.extend_from_slice(&0u16.to_le_bytes()); // stored
+        central.extend_from_slice(&time.to_le_bytes());
+        central.extend_from_slice(&date.to_le_bytes());
+        central.extend_from_slice(&crc.to_le_bytes());
+        central.extend_from_slice(&size.to_le_bytes());
+        central.extend_from_slice(&size.to_le_bytes());
+        central.extend_from_slice(&name_len.to_le_bytes());
+        central.extend_from_slice(&[0; 8]); // extra, comment, disk, internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name);
+    }
+
+    let central_offset = fit_u32(archive.len())?;
+    let central_size = fit_u32(central.len())?;
+    let count = u16::try_from(entries.len()).context("Too many files for a zip archive")?;
+    archive.extend_from_slice(&central);
+
+    // End of central directory
+    archive.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]); // disk numbers
+    archive.extend_from_slice(&count.to_le_bytes());
+    archive.extend_from_slice(&count.to_le_bytes());
+    archive.extend_from_slice(&central_size.to_le_bytes());
+    archive.extend_from_slice(&central_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    Ok(archive)
+}
+
+fn fit_u32(len: usize) -> anyhow::Result<u32> {
+    u32::try_from(len).context("The export is too large for a zip archive")
+}
+
+/// MS-DOS time and date, which start in 1980 and count seconds in steps of two.
+fn dos_date_time(at: NaiveDateTime) -> (u16, u16) {
+    if at.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = (at.hour() << 11) | (at.minute() << 5) | (at.second() / 2);
+    let date = ((at.year().min(2107) as u32 - 1980) << 9) | (at.month() << 5) | at.day();
+    (time as u16, date as u16)
+}
+
+/// CRC-32 as used by zip (IEEE 802.3).
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn write_produces_an_archive_with_all_entries() {
+        let modified = NaiveDate::from_ymd_opt(2023, 6, 1)
+            .unwrap()
+            .and_hms_opt(13, 45, 10)
+            .unwrap();
+        let archive = write(
+            &[
+                ("a.json".to_owned(), b"{}".to_vec()),
+                ("b.json".to_owned(), b"[]".to_vec()),
+            ],
+            modified,
+        )
+        .unwrap();
+        assert_eq!(&archive[..4], &[0x50, 0x4b, 0x03, 0x04]);
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&end[..4], &[0x50, 0x4b, 0x05, 0x06]);
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+
+        let time = u16::from_le_bytes([archive[10], archive[11]]);
+        let date = u16::from_le_bytes([archive[12], archive[13]]);
+        assert_eq!(
+            (time >> 11, (time >> 5) & 0x3f, (time & 0x1f) * 2),
+            (13, 45, 10)
+        );
+        assert_eq!(
+            (1980 + (date >> 9), (date >> 5) & 0xf, date & 0x1f),
+            (2023, 6, 1)
+        );
+    }
+
+    #[test]
+    fn write_refuses_paths_longer_than_zip_allows() {
+        let path = "a".repeat(u16::MAX as usize + 1);
+        assert!(write(&[(path, Vec::new())], NaiveDateTime::default()).is_err());
+    }
+}